@@ -0,0 +1,96 @@
+#![feature(test)]
+extern crate test;
+
+/*
+ * Measures the overhead the frontend adds to each request that goes to the backend, by timing
+ * requests that do little work in the backend. Run with `cargo bench`, and on an earlier commit
+ * to compare.
+ */
+mod client_interface_bench {
+    use reqwest::blocking::Client;
+    use reqwest::StatusCode;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process::{Child, Command};
+    use std::thread;
+    use std::time::Duration;
+    use test::Bencher;
+
+    const PORT: &str = "8446";
+    const EMPTY_DIGEST: &str =
+        "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    struct TrowInstance {
+        pid: Child,
+        data_dir: PathBuf,
+    }
+
+    impl Drop for TrowInstance {
+        fn drop(&mut self) {
+            self.pid.kill().unwrap();
+            self.pid.wait().unwrap();
+            fs::remove_dir_all(&self.data_dir).ok();
+        }
+    }
+
+    fn address() -> String {
+        format!("http://127.0.0.1:{}", PORT)
+    }
+
+    fn start_trow(client: &Client) -> TrowInstance {
+        let data_dir = std::env::temp_dir().join(format!("trow-bench-{}", std::process::id()));
+        let child = Command::new("cargo")
+            .args(&["run", "--release", "--", "--no-tls", "--port", PORT])
+            .arg("--data-dir")
+            .arg(&data_dir)
+            .spawn()
+            .expect("failed to start");
+        let trow = TrowInstance {
+            pid: child,
+            data_dir,
+        };
+
+        // Allow for the release build
+        let mut timeout = 3000;
+        let readiness = format!("{}/readiness", address());
+        while !matches!(client.get(&readiness).send(), Ok(r) if r.status() == StatusCode::OK) {
+            timeout -= 1;
+            assert!(timeout > 0, "Trow failed to start");
+            thread::sleep(Duration::from_millis(100));
+        }
+        trow
+    }
+
+    #[bench]
+    fn readiness(b: &mut Bencher) {
+        let client = Client::new();
+        let _trow = start_trow(&client);
+        let url = format!("{}/readiness", address());
+        b.iter(|| {
+            let resp = client.get(&url).send().unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+        });
+    }
+
+    #[bench]
+    fn head_unknown_blob(b: &mut Bencher) {
+        let client = Client::new();
+        let _trow = start_trow(&client);
+        let url = format!("{}/v2/bench/test/blobs/{}", address(), EMPTY_DIGEST);
+        b.iter(|| {
+            let resp = client.head(&url).send().unwrap();
+            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        });
+    }
+
+    #[bench]
+    fn catalog(b: &mut Bencher) {
+        let client = Client::new();
+        let _trow = start_trow(&client);
+        let url = format!("{}/v2/_catalog", address());
+        b.iter(|| {
+            let resp = client.get(&url).send().unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+        });
+    }
+}
@@ -1,8 +1,10 @@
 use failure::{self, Error};
+use std::fmt;
 use std::io::Read;
 
 // Crypto and crypto related imports
-use sha2::{Digest, Sha256};
+use sha2::digest::DynDigest;
+use sha2::{Digest, Sha256, Sha512};
 
 // Buffer size for SHA2 hashing
 const BUFFER_SIZE: usize = 1024;
@@ -32,9 +34,121 @@ pub fn sha256_tag_digest<R: Read>(mut reader: R) -> Result<String, Error> {
     Ok(format!("sha256:{}", digest))
 }
 
+fn sha512_digest<R: Read>(mut reader: R) -> Result<String, Error> {
+    digest::<Sha512, _>(&mut reader)
+}
+
+pub fn sha512_tag_digest<R: Read>(mut reader: R) -> Result<String, Error> {
+    let digest = sha512_digest(&mut reader)?;
+    Ok(format!("sha512:{}", digest))
+}
+
+/**
+ * Calculates the digest of the content with the named algorithm, e.g. "sha512"
+ */
+pub fn tag_digest<R: Read>(alg: &str, reader: R) -> Result<String, Error> {
+    match alg {
+        "sha256" => sha256_tag_digest(reader),
+        "sha512" => sha512_tag_digest(reader),
+        _ => Err(format_err!("Hash algorithm {} not supported", alg)),
+    }
+}
+
+/**
+ * Hasher for the named algorithm, for data that doesn't come from a reader, e.g. a download.
+ * Finish with finish_tag_digest.
+ */
+pub fn hasher(alg: &str) -> Result<Box<dyn DynDigest + Send>, Error> {
+    match alg {
+        "sha256" => Ok(Box::new(Sha256::new())),
+        "sha512" => Ok(Box::new(Sha512::new())),
+        _ => Err(format_err!("Hash algorithm {} not supported", alg)),
+    }
+}
+
+/**
+ * Digest of the data given to a hasher made with the named algorithm, e.g. sha512:<hash>
+ */
+pub fn finish_tag_digest(alg: &str, hasher: Box<dyn DynDigest + Send>) -> String {
+    format!("{}:{}", alg, hex::encode(hasher.finalize()))
+}
+
+/**
+ * The algorithm a digest was calculated with, e.g. "sha256" for sha256:<hash>
+ */
+pub fn digest_alg(digest: &str) -> &str {
+    digest.split(':').next().unwrap_or_default()
+}
+
+/**
+ * Hashes of data that arrives in pieces, e.g. the chunks of an upload. The data is hashed with
+ * every supported algorithm, as which one the client wants isn't known until the upload completes.
+ */
+#[derive(Clone, Default)]
+pub struct UploadHasher {
+    sha256: Sha256,
+    sha512: Sha512,
+    size: u64,
+}
+
+impl fmt::Debug for UploadHasher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("UploadHasher")
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+impl UploadHasher {
+    pub fn new() -> UploadHasher {
+        UploadHasher::default()
+    }
+
+    /**
+     * Hasher for everything the reader gives, e.g. an upload being resumed after a restart.
+     */
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<UploadHasher, Error> {
+        let mut hasher = UploadHasher::new();
+        let mut buffer = [0u8; BUFFER_SIZE];
+        loop {
+            let n = reader.read(&mut buffer)?;
+            if n == 0 {
+                return Ok(hasher);
+            }
+            hasher.update(&buffer[..n]);
+        }
+    }
+
+    /**
+     * Whether this is the hash of len bytes.
+     */
+    pub fn covers(&self, len: u64) -> bool {
+        self.size == len
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        Digest::update(&mut self.sha256, data);
+        Digest::update(&mut self.sha512, data);
+        self.size += data.len() as u64;
+    }
+
+    /**
+     * Digest of the data so far with the named algorithm, e.g. sha512:<hash>. More data can still
+     * be added.
+     */
+    pub fn tag_digest(&self, alg: &str) -> Result<String, Error> {
+        let hash = match alg {
+            "sha256" => hex::encode(self.sha256.clone().finalize()),
+            "sha512" => hex::encode(self.sha512.clone().finalize()),
+            _ => return Err(format_err!("Hash algorithm {} not supported", alg)),
+        };
+        Ok(format!("{}:{}", alg, hash))
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use crate::digest::{sha256_digest, sha256_tag_digest};
+    use crate::digest::{digest_alg, sha256_digest, sha256_tag_digest, tag_digest, UploadHasher};
     use std::io::BufReader;
 
     #[test]
@@ -96,4 +210,45 @@ mod test {
             "sha256:05c6e08f1d9fdafa03147fcb8f82f124c76d2f70e3d989dc8aadb5e7d7450bec"
         );
     }
+
+    #[test]
+    fn sha512_tag_digest_test() {
+        let result = tag_digest("sha512", BufReader::new("hello world".as_bytes())).unwrap();
+        assert_eq!(
+            result,
+            "sha512:309ecc489c12d6eb4cc40f50c902f2b4d0ed77ee511a7c7a9bcd3ca86d4cd86f989dd35bc5ff499670da34255b45b0cfd830e81f605dcf7dc5542e93ae9cd76f"
+        );
+        assert_eq!(digest_alg(&result), "sha512");
+        assert!(tag_digest("md5", BufReader::new("hello world".as_bytes())).is_err());
+    }
+
+    #[test]
+    fn upload_hasher_test() {
+        let data: Vec<u8> = (0..300u32).map(|i| (i * 7) as u8).collect();
+        for &len in &[0, 1, 63, 64, 65, 128, 300] {
+            for &split in &[0, 1, 30, 64, 100] {
+                let split = split.min(len);
+                // Carries on from what was written before e.g. a restart
+                let mut hasher = UploadHasher::from_reader(&data[..split]).unwrap();
+                hasher.update(&data[split..len]);
+                assert!(hasher.covers(len as u64));
+                for alg in &["sha256", "sha512"] {
+                    assert_eq!(
+                        hasher.tag_digest(alg).unwrap(),
+                        tag_digest(alg, &data[..len]).unwrap()
+                    );
+                }
+            }
+        }
+
+        let mut hasher = UploadHasher::new();
+        hasher.update(b"hello ");
+        hasher.update(b"world");
+        assert!(!hasher.covers(10));
+        assert_eq!(
+            hasher.tag_digest("sha256").unwrap(),
+            "sha256:b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+        assert!(hasher.tag_digest("md5").is_err());
+    }
 }
@@ -21,17 +21,48 @@ extern crate sha2;
 use tonic::transport::Server;
 mod metrics;
 mod server;
+mod storage;
+pub mod transport;
 mod validate;
+pub use server::is_writable_repo;
+pub use server::trow_server as proto;
 use server::trow_server::admission_controller_server::AdmissionControllerServer;
 use server::trow_server::registry_server::RegistryServer;
-use server::TrowServer;
+pub use server::trow_server::FsckReport;
+pub use server::TrowServer;
+pub use server::CHUNK_SIZE;
+pub use server::DEFAULT_UPLOAD_TIMEOUT;
+pub use server::MAX_MANIFEST_SIZE;
+use std::time::Duration;
+pub use storage::{S3Config, DEFAULT_S3_CACHE_SIZE};
 use tokio::runtime::Runtime;
+use transport::GrpcAddr;
 
 pub mod manifest;
 
+/**
+ * Checks the consistency of the data directory without starting the server.
+ *
+ * Only repair changes the data directory, by quarantining corrupt blobs. Trow shouldn't be running
+ * on the same data directory when repair is set.
+ */
+pub fn check_data_dir(
+    data_path: &str,
+    s3: Option<S3Config>,
+    repair: bool,
+) -> Result<FsckReport, failure::Error> {
+    let rt = Runtime::new()?;
+    let ts = TrowServer::open_read_only(data_path)?;
+    let ts = match s3 {
+        Some(config) => ts.with_s3_storage(config)?,
+        None => ts,
+    };
+    rt.block_on(ts.run_fsck(repair))
+}
+
 pub struct TrowServerBuilder {
     data_path: String,
-    listen_addr: std::net::SocketAddr,
+    listen_addr: GrpcAddr,
     proxy_hub: bool,
     hub_user: Option<String>,
     hub_pass: Option<String>,
@@ -42,11 +73,14 @@ pub struct TrowServerBuilder {
     tls_cert: Option<Vec<u8>>,
     tls_key: Option<Vec<u8>>,
     root_key: Option<Vec<u8>>,
+    s3: Option<S3Config>,
+    upload_timeout: Duration,
+    allowed_artifact_types: Vec<String>,
 }
 
 pub fn build_server(
     data_path: &str,
-    listen_addr: std::net::SocketAddr,
+    listen_addr: GrpcAddr,
     proxy_hub: bool,
     hub_user: Option<String>,
     hub_pass: Option<String>,
@@ -68,23 +102,52 @@ pub fn build_server(
         tls_cert: None,
         tls_key: None,
         root_key: None,
+        s3: None,
+        upload_timeout: DEFAULT_UPLOAD_TIMEOUT,
+        allowed_artifact_types: vec![],
     }
 }
 
 impl TrowServerBuilder {
+    /**
+     * Serves gRPC over TLS with this PEM encoded certificate and key.
+     */
     pub fn add_tls(mut self, tls_cert: Vec<u8>, tls_key: Vec<u8>) -> TrowServerBuilder {
         self.tls_cert = Some(tls_cert);
         self.tls_key = Some(tls_key);
         self
     }
 
+    /**
+     * Only accepts frontends presenting a certificate signed by this PEM encoded CA. Requires TLS.
+     */
     pub fn add_root_cert(mut self, root_key: Vec<u8>) -> TrowServerBuilder {
         self.root_key = Some(root_key);
         self
     }
 
-    pub fn start_trow_sync(self) {
-        let mut rt = Runtime::new().expect("Failed to start Tokio runtime");
+    pub fn add_s3_storage(mut self, config: S3Config) -> TrowServerBuilder {
+        self.s3 = Some(config);
+        self
+    }
+
+    /**
+     * Uploads with no activity for this long are removed, along with their data.
+     */
+    pub fn set_upload_timeout(mut self, timeout: Duration) -> TrowServerBuilder {
+        self.upload_timeout = timeout;
+        self
+    }
+
+    /**
+     * Restricts the artifacts that can be pushed to the given types. Images are always allowed.
+     */
+    pub fn set_allowed_artifact_types(mut self, artifact_types: Vec<String>) -> TrowServerBuilder {
+        self.allowed_artifact_types = artifact_types;
+        self
+    }
+
+    fn build_trow_server(self) -> Result<TrowServer, failure::Error> {
         let ts = TrowServer::new(
             &self.data_path,
             self.proxy_hub,
@@ -94,15 +157,54 @@ impl TrowServerBuilder {
             self.allow_images,
             self.deny_prefixes,
             self.deny_images,
-        )
-        .expect("Failure configuring Trow Server");
+        )?
+        .with_allowed_artifact_types(self.allowed_artifact_types);
+        let ts = match self.s3 {
+            Some(config) => ts.with_s3_storage(config)?,
+            None => ts,
+        };
 
-        let server = Server::builder()
-            .add_service(RegistryServer::new(ts.clone()))
-            .add_service(AdmissionControllerServer::new(ts))
-            .serve(self.listen_addr);
+        tokio::spawn(ts.clone().reap_uploads_periodically(self.upload_timeout));
+        Ok(ts)
+    }
+
+    /**
+     * Builds the backend to be called in-process through its Registry and AdmissionController
+     * implementations, rather than served over gRPC.
+     *
+     * Must be called on a Tokio runtime, which removing abandoned uploads is spawned onto.
+     */
+    pub fn build_embedded(self) -> Result<TrowServer, failure::Error> {
+        debug!("Trow backend embedded");
+        self.build_trow_server()
+    }
+
+    pub fn start_trow_sync(self) {
+        let rt = Runtime::new().expect("Failed to start Tokio runtime");
+        let listen_addr = self.listen_addr.clone();
+        let tls = match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => Some(
+                transport::server_tls(cert, key, self.root_key.as_deref())
+                    .expect("Failure configuring TLS for Trow Server"),
+            ),
+            _ => None,
+        };
+        let ts = {
+            let _guard = rt.enter();
+            self.build_trow_server()
+                .expect("Failure configuring Trow Server")
+        };
 
-        debug!("Trow backend service running");
+        let server = async move {
+            let incoming = transport::listen(&listen_addr, tls).await?;
+            debug!("Trow backend service running on {}", listen_addr);
+            Server::builder()
+                .add_service(RegistryServer::new(ts.clone()))
+                .add_service(AdmissionControllerServer::new(ts))
+                .serve_with_incoming(incoming)
+                .await?;
+            Ok::<(), failure::Error>(())
+        };
 
         match rt.block_on(server) {
             Ok(()) => {
@@ -1,5 +1,7 @@
 use failure::Error;
 use serde_json::{self, Value};
+use std::collections::HashMap;
+use std::fmt;
 
 pub trait FromJson {
     fn from_json(raw: &Value) -> Result<Self, Error>
@@ -31,17 +33,21 @@ pub enum Manifest {
 #[serde(rename_all = "camelCase")]
 pub struct ManifestList {
     pub schema_version: u8,
-    pub media_type: String, //TODO: make enum
+    pub media_type: Option<String>, //TODO: make enum
     pub manifests: Vec<ManifestListEntry>,
+    pub artifact_type: Option<String>,
+    pub subject: Option<Object>,
+    pub annotations: Option<HashMap<String, String>>,
 }
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ManifestListEntry {
     pub media_type: String, //TODO: make enum
-    pub size: u32,
+    pub size: u64,
     pub digest: String,
-    pub platform: Platform,
+    // Only set for images, not e.g. artifacts or build caches
+    pub platform: Option<Platform>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -57,21 +63,42 @@ pub struct Platform {
     pub features: Option<Vec<String>>,
 }
 
+// Formatted the way docker --platform takes it, e.g. linux/arm64/v8
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.os, self.architecture)?;
+        if let Some(variant) = &self.variant {
+            write!(f, "/{}", variant)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ManifestV2 {
     pub schema_version: u8,
     pub media_type: Option<String>, //TODO: make enum
+    // Artifacts with nothing to configure may leave it out, meaning the empty descriptor
+    #[serde(default = "empty_config")]
     pub config: Object,
     pub layers: Vec<Object>,
+    pub artifact_type: Option<String>,
+    // The manifest this one refers to, e.g. the image a signature is for
+    pub subject: Option<Object>,
+    pub annotations: Option<HashMap<String, String>>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct Object {
     pub media_type: String, //enum would be better
     pub size: Option<u64>,
     pub digest: String, //special type would be nice
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<HashMap<String, String>>,
 }
 
 #[derive(Fail, Debug)]
@@ -91,28 +118,64 @@ pub mod manifest_media_type {
     // Weirdly the media type is optional in the JSON, so assume OCI_V1.
     // TODO: Check if we should be falling back to mime type
     pub const DEFAULT: &str = OCI_V1;
+
+    /// Whether the type is one of a manifest or index, rather than e.g. a blob in a build cache
+    pub fn is_manifest(media_type: &str) -> bool {
+        [DOCKER_V1, DOCKER_V2, OCI_V1, DOCKER_LIST, OCI_INDEX].contains(&media_type)
+    }
+}
+
+// Config media types of container images, as opposed to other artifacts
+pub mod image_config_media_type {
+    pub const DOCKER: &str = "application/vnd.docker.container.image.v1+json";
+    pub const OCI: &str = "application/vnd.oci.image.config.v1+json";
+}
+
+// The empty JSON object, used as the config of artifacts that don't need one
+pub mod empty_descriptor {
+    pub const MEDIA_TYPE: &str = "application/vnd.oci.empty.v1+json";
+    pub const DIGEST: &str =
+        "sha256:44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a";
+    pub const CONTENT: &[u8] = b"{}";
+}
+
+fn empty_config() -> Object {
+    Object {
+        media_type: empty_descriptor::MEDIA_TYPE.to_string(),
+        size: Some(empty_descriptor::CONTENT.len() as u64),
+        digest: empty_descriptor::DIGEST.to_string(),
+        artifact_type: None,
+        annotations: None,
+    }
 }
 
 fn schema_2(raw: &Value) -> Result<Manifest, Error> {
     // According to the spec, manifests don't have to have a mediaType (?!).
-    // Assume V2 if not present.
-    let mt = raw["mediaType"]
-        .as_str()
-        .unwrap_or(manifest_media_type::DEFAULT);
-
-    match mt {
-        manifest_media_type::DOCKER_V2 | manifest_media_type::OCI_V1 => {
+    // If it's missing, or a type we don't know such as an artifact manifest, go by the fields.
+    match raw["mediaType"].as_str() {
+        Some(manifest_media_type::DOCKER_V2) | Some(manifest_media_type::OCI_V1) => {
             Ok(Manifest::V2(serde_json::from_value(raw.clone())?))
         }
 
-        manifest_media_type::DOCKER_LIST | manifest_media_type::OCI_INDEX => {
+        Some(manifest_media_type::DOCKER_LIST) | Some(manifest_media_type::OCI_INDEX) => {
+            Ok(Manifest::List(serde_json::from_value(raw.clone())?))
+        }
+
+        _ if raw["manifests"].is_array() => {
             Ok(Manifest::List(serde_json::from_value(raw.clone())?))
         }
 
-        unknown => Err(InvalidManifest {
+        _ if raw["layers"].is_array() => Ok(Manifest::V2(serde_json::from_value(raw.clone())?)),
+
+        Some(unknown) => Err(InvalidManifest {
             err: format!("Media Type {} is not supported.", unknown),
         }
         .into()),
+
+        None => Err(InvalidManifest {
+            err: "Manifest has neither layers nor manifests".to_owned(),
+        }
+        .into()),
     }
 }
 
@@ -169,15 +232,56 @@ impl Manifest {
                 .as_ref()
                 .unwrap_or(&manifest_media_type::DEFAULT.to_string())
                 .to_string(),
-            Manifest::List(ref list) => list.media_type.clone(),
+            Manifest::List(ref list) => list
+                .media_type
+                .as_deref()
+                .unwrap_or(manifest_media_type::OCI_INDEX)
+                .to_string(),
+        }
+    }
+
+    /// Whether this is a container image, or an index of them, rather than another artifact
+    pub fn is_image(&self) -> bool {
+        match *self {
+            Manifest::V2(ref m2) => {
+                m2.artifact_type.is_none()
+                    && (m2.config.media_type == image_config_media_type::DOCKER
+                        || m2.config.media_type == image_config_media_type::OCI)
+            }
+            Manifest::List(ref list) => list.artifact_type.is_none(),
+        }
+    }
+
+    /// The manifest this one refers to, if any
+    pub fn subject(&self) -> Option<&Object> {
+        match *self {
+            Manifest::V2(ref m2) => m2.subject.as_ref(),
+            Manifest::List(ref list) => list.subject.as_ref(),
+        }
+    }
+
+    /// The type of artifact, which for images without an artifactType is the config media type
+    pub fn artifact_type(&self) -> Option<&str> {
+        match *self {
+            Manifest::V2(ref m2) => m2
+                .artifact_type
+                .as_deref()
+                .or(Some(m2.config.media_type.as_str())),
+            Manifest::List(ref list) => list.artifact_type.as_deref(),
+        }
+    }
+
+    pub fn annotations(&self) -> Option<&HashMap<String, String>> {
+        match *self {
+            Manifest::V2(ref m2) => m2.annotations.as_ref(),
+            Manifest::List(ref list) => list.annotations.as_ref(),
         }
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::FromJson;
-    use super::Manifest;
+    use super::{empty_descriptor, FromJson, Manifest};
     use crate::digest::sha256_tag_digest;
     use serde_json::{self, Value};
     use std::io::BufReader;
@@ -369,4 +473,76 @@ mod test {
         let v: Value = serde_json::from_str(&data).unwrap();
         assert!(Manifest::from_json(&v).is_ok());
     }
+
+    #[test]
+    fn valid_artifacts() {
+        // Helm chart, with its own config and layer types
+        let data = r#"{
+            "schemaVersion": 2,
+            "config": {
+                "mediaType": "application/vnd.cncf.helm.config.v1+json",
+                "digest": "sha256:8ec7c0f2f6860037c19b54c3cfbab48d9b4b21b485a93d87b64690fdb68c2111",
+                "size": 117
+            },
+            "layers": [
+                {
+                    "mediaType": "application/vnd.cncf.helm.chart.content.v1.tar+gzip",
+                    "digest": "sha256:1b251d38cfe948dfc0a5745b7af5ca574ecb61e52aed10b19039db39af6e1617",
+                    "size": 2487
+                }
+            ]
+        }"#;
+        let mani = Manifest::from_json(&serde_json::from_str(data).unwrap()).unwrap();
+        assert!(!mani.is_image());
+        assert_eq!(
+            mani.artifact_type(),
+            Some("application/vnd.cncf.helm.config.v1+json")
+        );
+
+        // Artifact without a config, which means the empty descriptor
+        let data = r#"{
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.manifest.v1+json",
+            "artifactType": "application/vnd.example.wasm",
+            "layers": [
+                {
+                    "mediaType": "application/wasm",
+                    "digest": "sha256:1b251d38cfe948dfc0a5745b7af5ca574ecb61e52aed10b19039db39af6e1617",
+                    "size": 2487
+                }
+            ]
+        }"#;
+        let mani = Manifest::from_json(&serde_json::from_str(data).unwrap()).unwrap();
+        assert_eq!(mani.artifact_type(), Some("application/vnd.example.wasm"));
+        assert!(mani
+            .get_local_asset_digests()
+            .contains(&empty_descriptor::DIGEST));
+        assert_eq!(
+            sha256_tag_digest(BufReader::new(empty_descriptor::CONTENT)).unwrap(),
+            empty_descriptor::DIGEST
+        );
+
+        // Build cache index with entries that have no platform and an unknown media type
+        let data = r#"{
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.example.cache.index.v1+json",
+            "manifests": [
+                {
+                    "mediaType": "application/vnd.buildkit.cacheconfig.v0",
+                    "digest": "sha256:1b251d38cfe948dfc0a5745b7af5ca574ecb61e52aed10b19039db39af6e1617",
+                    "size": 5000000000
+                }
+            ]
+        }"#;
+        let mani = Manifest::from_json(&serde_json::from_str(data).unwrap()).unwrap();
+        assert_eq!(
+            mani.get_media_type(),
+            "application/vnd.example.cache.index.v1+json"
+        );
+
+        let data = r#"{ "schemaVersion": 2, "mediaType": "application/vnd.example" }"#;
+        assert!(Manifest::from_json(&serde_json::from_str(data).unwrap()).is_err());
+        let data = r#"{ "schemaVersion": 2 }"#;
+        assert!(Manifest::from_json(&serde_json::from_str(data).unwrap()).is_err());
+    }
 }
@@ -32,6 +32,11 @@ lazy_static! {
         "total number of requests for blobs made",
         labels! {"type" => "blobs"}
     )).unwrap();
+    pub static ref REAPED_UPLOADS: IntCounter  = register_int_counter!(opts!(
+        "reaped_uploads",
+        "total number of abandoned uploads removed after being idle",
+        labels! {"type" => "uploads"}
+    )).unwrap();
 }
 
 // Query disk metrics
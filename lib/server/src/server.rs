@@ -1,4 +1,4 @@
-use crate::manifest::{manifest_media_type, FromJson, Manifest};
+use crate::manifest::{empty_descriptor, manifest_media_type, FromJson, Manifest};
 use chrono::prelude::*;
 use core::fmt::Display;
 use failure::{self, Error, Fail};
@@ -7,30 +7,50 @@ use reqwest::{
     self,
     header::{HeaderMap, HeaderValue},
 };
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
-use std::fs::{self, DirEntry, File};
+use std::fs::{self, File};
 use std::io;
-use std::io::{BufRead, BufReader, Read, Write};
+use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, RwLock};
-use tokio::sync::mpsc;
-use tonic::{Request, Response, Status};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::{self, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Code, Request, Response, Status, Streaming};
 use uuid::Uuid;
 pub mod trow_server {
     include!("../../protobuf/out/trow.rs");
 }
 
 use self::trow_server::*;
-use crate::digest::sha256_tag_digest;
+use crate::digest::{digest_alg, tag_digest, UploadHasher};
 use crate::server::trow_server::registry_server::Registry;
 
 use crate::metrics;
+use crate::storage::{
+    blocking, is_not_found, temp_file_name, FilesystemDriver, S3Config, S3Driver, StorageDriver,
+    TEMP_FILE_PREFIX,
+};
+
+mod fsck;
+mod gc;
+mod reaper;
+mod referrers;
+mod sessions;
 
-static SUPPORTED_DIGESTS: [&str; 1] = ["sha256"];
+pub use self::reaper::DEFAULT_UPLOAD_TIMEOUT;
+
+static SUPPORTED_DIGESTS: [&str; 2] = ["sha256", "sha512"];
+// Used for manifests unless the client asks for another
+static DEFAULT_DIGEST: &str = "sha256";
 static MANIFESTS_DIR: &str = "manifests";
 static BLOBS_DIR: &str = "blobs";
 static UPLOADS_DIR: &str = "scratch";
+static CACHE_DIR: &str = "cache";
+static DELETED_TAGS_DIR: &str = "deleted_tags";
 
 static PROXY_DIR: &str = "f/"; //Repositories starting with this are considered proxies
 static HUB_PROXY_DIR: &str = "docker/"; //Repositories starting with this are considered proxies
@@ -39,22 +59,37 @@ static HUB_AUTH_ENDPOINT: &str = "https://auth.docker.io/token";
 static HUB_RESOURCE: &str = "registry.docker.io";
 static DIGEST_HEADER: &str = "Docker-Content-Digest";
 
+/// Most data sent in one message when streaming blobs and manifests
+pub const CHUNK_SIZE: usize = 256 * 1024;
+
+/// Largest manifest accepted on push, as manifests are held in memory. As in distribution.
+pub const MAX_MANIFEST_SIZE: usize = 4 * 1024 * 1024;
+
 /* Struct implementing callbacks for the Frontend
  *
- * _active_uploads_: a HashSet of all uuids that are currently being tracked
- * _manifests_path_: path to where the manifests are
- * _layers_path_: path to where blobs are stored
+ * _active_uploads_: all uploads currently being tracked, with when they started and were last used
+ * _data_path_: path to the data directory
  * _scratch_path_: path to temporary storage for uploads
+ * _sessions_path_: path to where upload sessions are saved, so they survive a restart
+ * _storage_: where manifests and blobs are stored, the data directory by default
+ * _gc_lock_: held for writing while blobs are removed, for reading while manifests are catalogued
+ * _tag_locks_: one lock per tag file being changed, so concurrent pushes to a tag are serialized
+ * _found_blobs_: blobs clients were told are stored, e.g. on a HEAD or mount, and when
+ * _allowed_artifact_types_: artifact types other than images that can be pushed, any if empty
  *
  * Each "route" gets a clone of this struct.
  * The Arc makes sure they all point to the same data.
  */
 #[derive(Clone)]
 pub struct TrowServer {
-    active_uploads: Arc<RwLock<HashSet<Upload>>>,
-    manifests_path: PathBuf,
-    blobs_path: PathBuf,
+    active_uploads: Arc<RwLock<HashMap<Upload, UploadSession>>>,
+    data_path: PathBuf,
     scratch_path: PathBuf,
+    sessions_path: PathBuf,
+    storage: Arc<dyn StorageDriver>,
+    gc_lock: Arc<sync::RwLock<()>>,
+    tag_locks: Arc<Mutex<HashMap<String, Arc<sync::Mutex<()>>>>>,
+    found_blobs: Arc<Mutex<HashMap<String, SystemTime>>>,
     proxy_hub: bool,
     hub_user: Option<String>,
     hub_pass: Option<String>,
@@ -62,6 +97,14 @@ pub struct TrowServer {
     allow_images: Vec<String>,
     deny_local_prefixes: Vec<String>,
     deny_local_images: Vec<String>,
+    allowed_artifact_types: Vec<String>,
+}
+
+/* A manifest read from storage, with what the frontend needs to serve it */
+struct FetchedManifest {
+    digest: String,
+    content_type: String,
+    data: Vec<u8>,
 }
 
 #[derive(Eq, PartialEq, Hash, Debug, Clone)]
@@ -70,6 +113,27 @@ struct Upload {
     uuid: String,
 }
 
+#[derive(Debug, Clone)]
+struct UploadSession {
+    started: SystemTime,
+    last_active: SystemTime,
+    bytes_received: u64,
+    // Hash of the data received, if known. Only kept if it covers bytes_received.
+    hasher: Option<UploadHasher>,
+}
+
+impl UploadSession {
+    fn new() -> UploadSession {
+        let now = SystemTime::now();
+        UploadSession {
+            started: now,
+            last_active: now,
+            bytes_received: 0,
+            hasher: Some(UploadHasher::new()),
+        }
+    }
+}
+
 #[derive(Fail, Debug)]
 #[fail(display = "Error getting proxied repo {}", msg)]
 pub struct ProxyError {
@@ -83,6 +147,44 @@ pub struct DigestValidationError {
     actual_digest: String,
 }
 
+#[derive(Fail, Debug)]
+#[fail(display = "Artifact type {} is not allowed", artifact_type)]
+pub struct ArtifactTypeNotAllowed {
+    artifact_type: String,
+}
+
+/// A manifest an index refers to that hasn't been pushed to the repository
+#[derive(Serialize, Debug)]
+pub struct MissingManifest {
+    digest: String,
+    platform: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct MissingManifests {
+    missing: Vec<MissingManifest>,
+}
+
+impl fmt::Display for MissingManifests {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let missing: Vec<_> = self
+            .missing
+            .iter()
+            .map(|m| match &m.platform {
+                Some(platform) => format!("{} ({})", m.digest, platform),
+                None => m.digest.clone(),
+            })
+            .collect();
+        write!(
+            f,
+            "Index refers to missing manifests {}",
+            missing.join(", ")
+        )
+    }
+}
+
+impl Fail for MissingManifests {}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Image {
     pub host: String, //Including port, docker.io by default
@@ -145,63 +247,22 @@ fn create_path(data_path: &str, dir: &str) -> Result<PathBuf, std::io::Error> {
     Ok(dir_path)
 }
 
-fn does_manifest_match_digest(manifest: &DirEntry, digest: &str) -> bool {
-    digest
-        == match get_digest_from_manifest_path(manifest.path()) {
-            Ok(test_digest) => test_digest,
-            Err(e) => {
-                warn!("Failure reading repo {:?}", e);
-                "NO_MATCH".to_string()
-            }
-        }
-}
-
-struct RepoIterator {
-    paths: Vec<Result<DirEntry, std::io::Error>>,
-}
-
-impl RepoIterator {
-    fn new(base_dir: &Path) -> Result<RepoIterator, Error> {
-        let paths = fs::read_dir(base_dir)?.collect();
-        Ok(RepoIterator { paths })
-    }
-}
-
-impl Iterator for RepoIterator {
-    type Item = DirEntry;
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.paths.pop() {
-            None => None,
-            Some(res_path) => match res_path {
-                Err(e) => {
-                    warn!("Error iterating over repos {:?}", e);
-                    self.next()
-                }
-                Ok(path) => {
-                    if path.file_type().unwrap().is_dir() {
-                        let new_paths = fs::read_dir(path.path()).unwrap();
-                        self.paths.extend(new_paths);
-                        self.next()
-                    } else {
-                        Some(path)
-                    }
-                }
-            },
-        }
-    }
-}
-
 /**
- * Checks a file matches the given digest.
+ * Checks a file matches the given digest, using the digest's algorithm.
  *
- * TODO: should be able to use range of hashes.
- * TODO: check if using a static for the hasher speeds things up.
+ * Uploads are hashed as they are written. If the hasher covers the whole file, its digest is
+ * compared instead of reading the file again.
  */
-fn validate_digest(file: &PathBuf, digest: &str) -> Result<(), Error> {
-    let f = File::open(file)?;
-    let reader = BufReader::new(f);
-
-    let calculated_digest = sha256_tag_digest(reader)?;
+async fn validate_digest(
+    file: &Path,
+    digest: &str,
+    hasher: Option<&UploadHasher>,
+) -> Result<(), Error> {
+    let len = tokio::fs::metadata(file).await?.len();
+    let calculated_digest = match hasher {
+        Some(hasher) if hasher.covers(len) => hasher.tag_digest(digest_alg(digest))?,
+        _ => hash_file(file, digest_alg(digest)).await?,
+    };
 
     if calculated_digest != digest {
         error!(
@@ -217,6 +278,30 @@ fn validate_digest(file: &PathBuf, digest: &str) -> Result<(), Error> {
     Ok(())
 }
 
+/**
+ * Hashes a file with the given algorithm. This is done on the blocking thread pool, as the file
+ * may be a large layer.
+ */
+async fn hash_file(file: &Path, alg: &str) -> Result<String, Error> {
+    let file = file.to_path_buf();
+    let alg = alg.to_string();
+    blocking(move || tag_digest(&alg, BufReader::new(File::open(file)?))).await
+}
+
+/**
+ * Hashes what has been written to an upload so far, for when there's no hash to carry on from,
+ * e.g. after a restart. Done on the blocking thread pool, as the upload may be large.
+ */
+async fn hash_upload(path: &Path) -> Result<UploadHasher, Error> {
+    let path = path.to_path_buf();
+    blocking(move || match File::open(path) {
+        Ok(file) => UploadHasher::from_reader(BufReader::new(file)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(UploadHasher::new()),
+        Err(e) => Err(e.into()),
+    })
+    .await
+}
+
 fn is_digest(maybe_digest: &str) -> bool {
     for alg in &SUPPORTED_DIGESTS {
         if maybe_digest.starts_with(&format!("{}:", alg)) {
@@ -227,21 +312,215 @@ fn is_digest(maybe_digest: &str) -> bool {
     false
 }
 
-fn is_path_writable(path: &PathBuf) -> io::Result<bool> {
-    let file = File::open(path)?;
-    let metadata = file.metadata()?;
+/**
+ * Whether blobs and manifests can be pushed to the repository. Proxied repositories are only
+ * filled from their upstream registry.
+ */
+pub fn is_writable_repo(repo_name: &str) -> bool {
+    !repo_name.starts_with(PROXY_DIR)
+}
+
+async fn is_path_writable(path: &Path) -> io::Result<bool> {
+    let metadata = tokio::fs::metadata(path).await?;
     let permissions = metadata.permissions();
     Ok(!permissions.readonly())
 }
 
-fn get_digest_from_manifest_path<P: AsRef<Path>>(path: P) -> Result<String, Error> {
-    let digest_date = fs::read_to_string(path)?;
+/**
+ * Removes temporary files left in scratch by writes that never completed, e.g. due to a crash.
+ *
+ * Uploads are left alone.
+ */
+fn remove_temp_files(scratch_path: &Path) -> Result<(), Error> {
+    for entry in fs::read_dir(scratch_path)? {
+        let entry = entry?;
+        if entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(TEMP_FILE_PREFIX)
+        {
+            info!("Removing incomplete write {:?}", entry.path());
+            fs::remove_file(entry.path())
+                .unwrap_or_else(|e| warn!("Failed to remove {:?} {:?}", entry.path(), e));
+        }
+    }
+    Ok(())
+}
+
+/**
+ * Removes a file from scratch, unless it has already been moved into the catalog.
+ */
+async fn remove_scratch_file(path: &Path) {
+    match tokio::fs::remove_file(path).await {
+        Ok(_) => (),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+        //Not an error, even if it's not great
+        Err(e) => error!("Error deleting file {:?} {:?}", path, e),
+    }
+}
+
+/**
+ * Appends the data of a stream of chunks to the upload at the path, which must be offset bytes
+ * long, adding whatever is written to the hasher if given. Gives the size of the upload after
+ * writing.
+ */
+async fn append_chunks<S>(
+    path: &Path,
+    offset: u64,
+    data: Vec<u8>,
+    chunks: &mut S,
+    mut hasher: Option<&mut UploadHasher>,
+) -> Result<u64, Status>
+where
+    S: Stream<Item = Result<UploadChunk, Status>> + Unpin,
+{
+    let io_err = |e: io::Error| {
+        error!("Failed to write upload {:?} {:?}", path, e);
+        Status::internal("Internal error writing upload")
+    };
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await
+        .map_err(io_err)?;
+    let mut size = file.metadata().await.map_err(io_err)?.len();
+    if size != offset {
+        return Err(Status::out_of_range(format!(
+            "Upload has {} bytes, not {}",
+            size, offset
+        )));
+    }
+
+    let mut data = data;
+    loop {
+        file.write_all(&data).await.map_err(io_err)?;
+        if let Some(hasher) = hasher.as_deref_mut() {
+            hasher.update(&data);
+        }
+        size += data.len() as u64;
+        data = match chunks.next().await {
+            Some(chunk) => chunk?.data,
+            None => break,
+        };
+    }
+    file.flush().await.map_err(io_err)?;
+    Ok(size)
+}
+
+/**
+ * Sends a blob from the offset in chunks. The first chunk, which is sent even if there is nothing
+ * to read, says how big the whole blob is.
+ */
+fn stream_blob(
+    mut file: tokio::fs::File,
+    size: u64,
+    offset: u64,
+) -> ReceiverStream<Result<BlobChunk, Status>> {
+    let (tx, rx) = mpsc::channel(4);
+
+    tokio::spawn(async move {
+        if let Err(e) = file.seek(io::SeekFrom::Start(offset)).await {
+            error!("Failed to seek in blob {:?}", e);
+            tx.send(Err(Status::internal("Internal error reading blob")))
+                .await
+                .ok();
+            return;
+        }
+        let mut first = true;
+        loop {
+            let mut data = Vec::with_capacity(CHUNK_SIZE);
+            match (&mut file)
+                .take(CHUNK_SIZE as u64)
+                .read_to_end(&mut data)
+                .await
+            {
+                Ok(0) if !first => break,
+                Ok(len) => {
+                    let chunk = BlobChunk {
+                        size: if first { size } else { 0 },
+                        data,
+                    };
+                    first = false;
+                    if tx.send(Ok(chunk)).await.is_err() || len < CHUNK_SIZE {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to read blob {:?}", e);
+                    tx.send(Err(Status::internal("Internal error reading blob")))
+                        .await
+                        .ok();
+                    break;
+                }
+            }
+        }
+    });
+    ReceiverStream::new(rx)
+}
+
+fn get_digest_from_tag_file(contents: &[u8]) -> String {
+    let digest_date = String::from_utf8_lossy(contents);
     //Should be digest followed by date, but allow for digest only
-    Ok(digest_date
+    digest_date
         .split(' ')
         .next()
         .unwrap_or(&digest_date)
-        .to_string())
+        .to_string()
+}
+
+/**
+ * Storage key of the blob with the given digest, e.g. blobs/sha256/<hash>
+ */
+fn blob_key(digest: &str) -> Result<String, Error> {
+    let mut iter = digest.split(':');
+    let alg = iter
+        .next()
+        .ok_or_else(|| format_err!("Digest {} did not contain alg component", digest))?;
+    if !SUPPORTED_DIGESTS.contains(&alg) {
+        return Err(format_err!("Hash algorithm {} not supported", alg));
+    }
+    let val = iter
+        .next()
+        .ok_or_else(|| format_err!("Digest {} did not contain value component", digest))?;
+    if iter.next().is_some() {
+        return Err(format_err!(
+            "Digest {} has more than two components",
+            digest
+        ));
+    }
+    Ok(format!("{}/{}/{}", BLOBS_DIR, alg, val))
+}
+
+/**
+ * Storage key of the file recording the history of a tag
+ */
+fn tag_key(repo_name: &str, tag: &str) -> String {
+    format!("{}/{}/{}", MANIFESTS_DIR, repo_name, tag)
+}
+
+fn repo_key(repo_name: &str) -> String {
+    format!("{}/{}", MANIFESTS_DIR, repo_name)
+}
+
+/**
+ * Storage key the history of a removed tag is kept under, e.g. deleted_tags/<repo>/<tag>/<time>
+ */
+fn deleted_tag_key(repo_name: &str, tag: &str) -> String {
+    let removed = Utc::now().format("%Y%m%dT%H%M%S%.9fZ");
+    format!("{}/{}/{}/{}", DELETED_TAGS_DIR, repo_name, tag, removed)
+}
+
+/**
+ * Tags as defined by the distribution spec: [a-zA-Z0-9_][a-zA-Z0-9._-]{0,127}
+ */
+fn is_valid_tag(tag: &str) -> bool {
+    let mut chars = tag.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphanumeric() || c == '_' => (),
+        _ => return false,
+    }
+    tag.len() <= 128 && chars.all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-')
 }
 
 impl TrowServer {
@@ -255,14 +534,13 @@ impl TrowServer {
         deny_local_prefixes: Vec<String>,
         deny_local_images: Vec<String>,
     ) -> Result<Self, Error> {
-        let manifests_path = create_path(data_path, MANIFESTS_DIR)?;
+        create_path(data_path, MANIFESTS_DIR)?;
+        create_path(data_path, BLOBS_DIR)?;
         let scratch_path = create_path(data_path, UPLOADS_DIR)?;
-        let blobs_path = create_path(data_path, BLOBS_DIR)?;
-        let svc = TrowServer {
-            active_uploads: Arc::new(RwLock::new(HashSet::new())),
-            manifests_path,
-            blobs_path,
-            scratch_path,
+        create_path(data_path, sessions::SESSIONS_DIR)?;
+        remove_temp_files(&scratch_path)?;
+        let svc = TrowServer::load(data_path, true)?;
+        Ok(TrowServer {
             proxy_hub,
             hub_user,
             hub_pass,
@@ -270,96 +548,202 @@ impl TrowServer {
             allow_images,
             deny_local_prefixes,
             deny_local_images,
-        };
-        Ok(svc)
+            ..svc
+        })
+    }
+
+    /**
+     * Opens the data directory without changing anything in it, e.g. to check it while Trow may
+     * be running on it. Nothing is created or cleaned up and uploads in progress are only read.
+     */
+    pub(crate) fn open_read_only(data_path: &str) -> Result<Self, Error> {
+        TrowServer::load(data_path, false)
+    }
+
+    /**
+     * Server for the data directory with the uploads saved in it, pruning sessions that can't be
+     * resumed if set.
+     */
+    fn load(data_path: &str, prune_sessions: bool) -> Result<Self, Error> {
+        let data_path = PathBuf::from(data_path);
+        let scratch_path = data_path.join(UPLOADS_DIR);
+        let sessions_path = data_path.join(sessions::SESSIONS_DIR);
+        let active_uploads =
+            sessions::load_sessions(&sessions_path, &scratch_path, prune_sessions)?;
+        let storage = Arc::new(FilesystemDriver::new(&data_path, &scratch_path));
+        Ok(TrowServer {
+            active_uploads: Arc::new(RwLock::new(active_uploads)),
+            data_path,
+            scratch_path,
+            sessions_path,
+            storage,
+            gc_lock: Arc::new(sync::RwLock::new(())),
+            tag_locks: Arc::new(Mutex::new(HashMap::new())),
+            found_blobs: Arc::new(Mutex::new(HashMap::new())),
+            proxy_hub: false,
+            hub_user: None,
+            hub_pass: None,
+            allow_prefixes: vec![],
+            allow_images: vec![],
+            deny_local_prefixes: vec![],
+            deny_local_images: vec![],
+            allowed_artifact_types: vec![],
+        })
+    }
+
+    /**
+     * Only allows pushing artifacts of the given types, i.e. manifests whose artifactType (or
+     * config media type if there is none) is in the list. Images can always be pushed.
+     */
+    pub fn with_allowed_artifact_types(mut self, artifact_types: Vec<String>) -> Self {
+        self.allowed_artifact_types = artifact_types;
+        self
+    }
+
+    /**
+     * Stores manifests and blobs in an S3 bucket rather than the data directory.
+     *
+     * Uploads in progress are still written to the data directory, which also holds a cache of
+     * blobs read from the bucket.
+     */
+    pub fn with_s3_storage(mut self, config: S3Config) -> Result<Self, Error> {
+        let cache_path = self.data_path.join(CACHE_DIR);
+        let driver = S3Driver::new(config, &cache_path, &self.scratch_path)?;
+        info!("Storing manifests and blobs in {}", driver.describe());
+        self.storage = Arc::new(driver);
+        Ok(self)
     }
 
     fn get_upload_path_for_blob(&self, uuid: &str) -> PathBuf {
         self.scratch_path.join(uuid)
     }
 
-    fn get_catalog_path_for_blob(&self, digest: &str) -> Result<PathBuf, Error> {
-        let mut iter = digest.split(':');
-        let alg = iter
-            .next()
-            .ok_or_else(|| format_err!("Digest {} did not contain alg component", digest))?;
-        if !SUPPORTED_DIGESTS.contains(&alg) {
-            return Err(format_err!("Hash algorithm {} not supported", alg));
+    // Given a manifest digest, check if it is referenced by any tag in the repo
+    async fn verify_manifest_digest_in_repo(
+        &self,
+        repo_name: &str,
+        digest: &str,
+    ) -> Result<bool, Error> {
+        for tag in self.storage.list(&repo_key(repo_name)).await? {
+            match self.storage.read(&tag).await {
+                Ok(contents) if get_digest_from_tag_file(&contents) == digest => return Ok(true),
+                Ok(_) => (),
+                Err(e) => warn!("Failure reading repo {:?}", e),
+            }
         }
-        let val = iter
-            .next()
-            .ok_or_else(|| format_err!("Digest {} did not contain value component", digest))?;
-        assert_eq!(None, iter.next());
-        Ok(self.blobs_path.join(alg).join(val))
+        Ok(false)
     }
 
-    // Given a manifest digest, check if it is referenced by any tag in the repo
-    fn verify_manifest_digest_in_repo(&self, repo_name: &str, digest: &str) -> Result<bool, Error> {
-        let mut ri = RepoIterator::new(&self.manifests_path.join(repo_name))?;
-        let res = ri.find(|de| does_manifest_match_digest(de, &digest));
-        Ok(res.is_some())
+    async fn get_digest_from_manifest(&self, repo_name: &str, tag: &str) -> Result<String, Error> {
+        let contents = self.storage.read(&tag_key(repo_name, tag)).await?;
+        Ok(get_digest_from_tag_file(&contents))
     }
 
-    fn get_digest_from_manifest(&self, repo_name: &str, tag: &str) -> Result<String, Error> {
-        get_digest_from_manifest_path(self.manifests_path.join(repo_name).join(tag))
+    /**
+     * Locks the tag file with the given key against changes by other requests to this backend.
+     * Locks are dropped once nothing holds or waits for them.
+     */
+    async fn lock_tag(&self, key: &str) -> sync::OwnedMutexGuard<()> {
+        let lock = {
+            let mut tag_locks = self.tag_locks.lock().unwrap();
+            tag_locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+            tag_locks.entry(key.to_string()).or_default().clone()
+        };
+        lock.lock_owned().await
     }
 
-    fn save_tag(&self, digest: &str, repo_name: &str, tag: &str) -> Result<(), Error> {
+    async fn save_tag(&self, digest: &str, repo_name: &str, tag: &str) -> Result<(), Error> {
         // Tag files should contain list of digests with timestamp
         // First line should always be the current digest
 
-        let repo_dir = self.manifests_path.join(repo_name);
-        let repo_path = repo_dir.join(tag);
-        fs::create_dir_all(&repo_dir)?;
-
+        let key = tag_key(repo_name, tag);
+        // The history is read and written back, which mustn't interleave with another push
+        let _guard = self.lock_tag(&key).await;
         let ts = Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true);
-        let contents = format!("{} {}\n", digest, ts);
-
-        if let Ok(mut f) = fs::File::open(&repo_path) {
-            let mut buf = Vec::new();
-            buf.extend(contents.as_bytes().iter());
-            f.read_to_end(&mut buf)?;
-            // TODO: Probably best to write to temporary file and then copy over.
-            // Will be closer to atomic
-            fs::write(&repo_path, buf)?;
-        } else {
-            fs::File::create(&repo_path).and_then(|mut f| f.write_all(contents.as_bytes()))?;
+        let mut contents = format!("{} {}\n", digest, ts).into_bytes();
+
+        match self.storage.read(&key).await {
+            Ok(history) => contents.extend(history),
+            Err(e) if is_not_found(&e) => (),
+            Err(e) => return Err(e),
         }
+        self.storage.write(&key, &contents).await
+    }
+
+    /**
+     * Removes the tag from the catalog. The tag file is moved rather than deleted, so there is a
+     * record of what the tag pointed to.
+     */
+    async fn archive_tag(&self, repo_name: &str, tag: &str) -> Result<(), Error> {
+        let key = tag_key(repo_name, tag);
+        let archived = deleted_tag_key(repo_name, tag);
+        let _guard = self.lock_tag(&key).await;
+        self.storage.rename(&key, &archived).await?;
+        info!(
+            "Removed tag {}:{}, history kept in {}",
+            repo_name, tag, archived
+        );
         Ok(())
     }
 
-    fn get_path_for_manifest(&self, repo_name: &str, reference: &str) -> Result<PathBuf, Error> {
-        let digest = if is_digest(reference) {
-            if !self.verify_manifest_digest_in_repo(repo_name, reference)? {
+    /**
+     * Called after removing tags. Failures are only logged, as the tags are already gone.
+     */
+    async fn remove_from_referrers(&self, repo_name: &str, digest: &str) {
+        if let Err(e) = self.unindex_referrer(repo_name, digest).await {
+            error!(
+                "Failed to remove {} in {} from referrers index {:?}",
+                digest, repo_name, e
+            );
+        }
+    }
+
+    async fn get_digest_for_manifest(
+        &self,
+        repo_name: &str,
+        reference: &str,
+    ) -> Result<String, Error> {
+        if is_digest(reference) {
+            if !self
+                .verify_manifest_digest_in_repo(repo_name, reference)
+                .await?
+            {
                 error!("Digest {} not in repository {}", reference, repo_name);
                 return Err(failure::err_msg(format!(
                     "Digest {} not in repository {}",
                     reference, repo_name
                 )));
             }
-            reference.to_string()
+            Ok(reference.to_string())
         } else {
             //Content of tag is the digest
-            self.get_digest_from_manifest(repo_name, reference)?
-        };
-
-        self.get_catalog_path_for_blob(&digest)
+            self.get_digest_from_manifest(repo_name, reference).await
+        }
     }
 
-    fn create_verified_manifest(
+    /**
+     * Parses the manifest and calculates its digest with the given algorithm, e.g. "sha256".
+     */
+    async fn create_verified_manifest(
         &self,
-        manifest_path: &PathBuf,
+        manifest_bytes: &[u8],
+        alg: &str,
         verify_assets_exist: bool,
     ) -> Result<VerifiedManifest, Error> {
-        let manifest_bytes = std::fs::read(&manifest_path)?;
-        let manifest_json: serde_json::Value = serde_json::from_slice(&manifest_bytes)?;
+        let manifest_json: serde_json::Value = serde_json::from_slice(manifest_bytes)?;
         let manifest = Manifest::from_json(&manifest_json)?;
+        if let Some(subject) = manifest.subject() {
+            referrers::digest_path(&subject.digest)?;
+        }
 
         if verify_assets_exist {
             for digest in manifest.get_local_asset_digests() {
-                let path = self.get_catalog_path_for_blob(digest)?;
-
-                if !path.exists() {
+                let key = blob_key(digest)?;
+                // Clients needn't upload the empty config, and may leave it out of the manifest
+                if digest == empty_descriptor::DIGEST && !self.storage.exists(&key).await? {
+                    self.storage.write(&key, empty_descriptor::CONTENT).await?;
+                }
+                if !self.storage.exists(&key).await? {
                     return Err(format_err!(
                         "Failed to find artifact with digest {}",
                         digest
@@ -368,17 +752,129 @@ impl TrowServer {
             }
         }
 
-        // Calculate the digest: sha256:...
-        let reader = BufReader::new(manifest_bytes.as_slice());
-        let digest = sha256_tag_digest(reader)?;
+        // Calculate the digest, e.g. sha256:...
+        let reader = BufReader::new(manifest_bytes);
+        let digest = tag_digest(alg, reader)?;
 
         // For performance, could generate only if verification is on, otherwise copy from somewhere
         Ok(VerifiedManifest {
             digest,
             content_type: manifest.get_media_type(),
+            subject: manifest
+                .subject()
+                .map(|s| s.digest.clone())
+                .unwrap_or_default(),
         })
     }
 
+    /**
+     * Runs the checks on a manifest being pushed. If the client gave the digest it expects, the
+     * manifest's digest is calculated with the same algorithm and must match.
+     */
+    async fn verify_pushed_manifest(
+        &self,
+        repo_name: &str,
+        manifest_bytes: &[u8],
+        expected_digest: Option<&str>,
+    ) -> Result<VerifiedManifest, Error> {
+        self.check_artifact_type(manifest_bytes)?;
+        self.verify_index_children(repo_name, manifest_bytes)
+            .await?;
+        let alg = expected_digest.map_or(DEFAULT_DIGEST, digest_alg);
+        let vm = self
+            .create_verified_manifest(manifest_bytes, alg, true)
+            .await?;
+        match expected_digest {
+            Some(expected) if expected != vm.digest => Err(DigestValidationError {
+                user_digest: expected.to_string(),
+                actual_digest: vm.digest,
+            }
+            .into()),
+            _ => Ok(vm),
+        }
+    }
+
+    /**
+     * Checks every manifest an index refers to has been pushed to the repository and matches its
+     * descriptor. Children that are missing are reported together, with their platforms. Entries
+     * that aren't manifests only need to be stored blobs of the given size.
+     */
+    async fn verify_index_children(
+        &self,
+        repo_name: &str,
+        manifest_bytes: &[u8],
+    ) -> Result<(), Error> {
+        let list = match Manifest::from_json(&serde_json::from_slice(manifest_bytes)?)? {
+            Manifest::List(list) => list,
+            Manifest::V2(_) => return Ok(()),
+        };
+
+        let mut missing = vec![];
+        for child in &list.manifests {
+            let key = blob_key(&child.digest)?;
+            // Indexes such as build caches also list blobs, which needn't be tagged
+            if !manifest_media_type::is_manifest(&child.media_type) {
+                let len = match self.storage.metadata(&key).await {
+                    Ok(metadata) => metadata.len,
+                    Err(e) if is_not_found(&e) => {
+                        return Err(format_err!(
+                            "Failed to find artifact with digest {}",
+                            child.digest
+                        ))
+                    }
+                    Err(e) => return Err(e),
+                };
+                if len != child.size {
+                    return Err(format_err!(
+                        "Blob {} is {} bytes, but the index gives its size as {}",
+                        child.digest,
+                        len,
+                        child.size
+                    ));
+                }
+                continue;
+            }
+            let in_repo = self
+                .verify_manifest_digest_in_repo(repo_name, &child.digest)
+                .await?;
+            let bytes = match self.storage.read(&key).await {
+                Ok(bytes) if in_repo => bytes,
+                Err(e) if !is_not_found(&e) => return Err(e),
+                _ => {
+                    missing.push(MissingManifest {
+                        digest: child.digest.clone(),
+                        platform: child.platform.as_ref().map(|p| p.to_string()),
+                    });
+                    continue;
+                }
+            };
+
+            if bytes.len() as u64 != child.size {
+                return Err(format_err!(
+                    "Manifest {} is {} bytes, but the index gives its size as {}",
+                    child.digest,
+                    bytes.len(),
+                    child.size
+                ));
+            }
+            let actual_digest =
+                tag_digest(digest_alg(&child.digest), BufReader::new(bytes.as_slice()))?;
+            if actual_digest != child.digest {
+                return Err(DigestValidationError {
+                    user_digest: child.digest.clone(),
+                    actual_digest,
+                }
+                .into());
+            }
+        }
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(MissingManifests { missing }.into())
+        }
+    }
+
     /**
     If repo is proxied to another registry, this will return the details of the remote image.
     If the repo isn't proxied None is returned
@@ -442,11 +938,9 @@ impl TrowServer {
         }
 
         //First save as bytes
-        let mani_id = Uuid::new_v4().to_string();
-        let temp_mani_path = self.scratch_path.join(mani_id);
-        let mut buf = File::create(&temp_mani_path)?;
+        let temp_mani_path = self.scratch_path.join(temp_file_name());
         let bytes = resp.bytes().await?;
-        buf.write_all(&bytes)?;
+        tokio::fs::write(&temp_mani_path, &bytes).await?;
 
         let mani: Manifest = serde_json::from_slice(&bytes)?;
 
@@ -455,7 +949,7 @@ impl TrowServer {
         for digest in mani.get_local_asset_digests() {
             //skip only blob if it already exists in local storage
             //we need to continue as docker images may share blobs
-            if self.get_catalog_path_for_blob(digest)?.exists() {
+            if self.storage.exists(&blob_key(digest)?).await? {
                 info!("Already have blob {}", digest);
                 continue;
             }
@@ -470,32 +964,27 @@ impl TrowServer {
             } else {
                 cl.get(&addr).send().await?
             };
-            let path = self.scratch_path.join(digest);
+            let path = self.scratch_path.join(temp_file_name());
 
-            let mut buf = File::create(&path)?;
-            buf.write_all(&resp.bytes().await?)?; //Is this going to be buffered?
+            tokio::fs::write(&path, &resp.bytes().await?).await?; //Is this going to be buffered?
             paths.push((path, digest));
         }
 
         for (path, digest) in &paths {
-            self.save_blob(&path, digest)?;
+            self.save_blob(&path, digest).await?;
         }
 
         //Save out manifest
-        let f = File::open(&temp_mani_path)?;
-        let reader = BufReader::new(f);
-        let calculated_digest = sha256_tag_digest(reader)?;
-
-        self.save_blob(&temp_mani_path, &calculated_digest)?;
-        self.save_tag(&calculated_digest, local_repo_name, &remote_image.tag)?;
+        let calculated_digest = hash_file(&temp_mani_path, "sha256").await?;
 
-        //Delete all the temp stuff
-        fs::remove_file(&temp_mani_path)
-            .unwrap_or_else(|e| error!("Failure deleting downloaded manifest {:?}", e));
+        self.save_blob(&temp_mani_path, &calculated_digest).await?;
+        self.save_tag(&calculated_digest, local_repo_name, &remote_image.tag)
+            .await?;
 
+        //Delete any temp stuff that wasn't moved into the catalog
+        remove_scratch_file(&temp_mani_path).await;
         for (path, _digest) in paths {
-            fs::remove_file(path)
-                .unwrap_or_else(|e| error!("Failure deleting downloaded blob {:?}", e));
+            remove_scratch_file(&path).await;
         }
 
         Ok(())
@@ -604,12 +1093,12 @@ impl TrowServer {
         }
     }
 
-    async fn create_manifest_read_location(
+    async fn fetch_manifest(
         &self,
         repo_name: String,
         reference: String,
         do_verification: bool,
-    ) -> Result<ManifestReadLocation, Error> {
+    ) -> Result<FetchedManifest, Error> {
         if let Some((proxy_image, proxy_auth)) =
             self.get_proxy_address_and_auth(&repo_name, &reference)
         {
@@ -635,7 +1124,7 @@ impl TrowServer {
                 .await;
 
             if let Some(digest) = digest {
-                if self.get_catalog_path_for_blob(&digest)?.exists() {
+                if self.storage.exists(&blob_key(&digest)?).await? {
                     info!(
                         "Have up to date manifest for {} digest {}",
                         repo_name, digest
@@ -644,9 +1133,10 @@ impl TrowServer {
 
                     //Make sure our tag exists and is up-to-date
                     if !is_digest(&reference) {
-                        let our_digest = self.get_digest_from_manifest(&repo_name, &reference);
+                        let our_digest =
+                            self.get_digest_from_manifest(&repo_name, &reference).await;
                         if our_digest.is_err() || (our_digest.unwrap() != digest) {
-                            let res = self.save_tag(&digest, &repo_name, &reference);
+                            let res = self.save_tag(&digest, &repo_name, &reference).await;
                             if res.is_err() {
                                 error!(
                                     "Internal error updating tag for proxied image {:?}",
@@ -670,55 +1160,56 @@ impl TrowServer {
         }
 
         //TODO: This isn't optimal
-        let path = self.get_path_for_manifest(&repo_name, &reference)?;
-        let vm = self.create_verified_manifest(&path, do_verification)?;
-        Ok(ManifestReadLocation {
+        let digest = self.get_digest_for_manifest(&repo_name, &reference).await?;
+        let manifest_bytes = self.storage.read(&blob_key(&digest)?).await?;
+        let vm = self
+            .create_verified_manifest(&manifest_bytes, digest_alg(&digest), do_verification)
+            .await?;
+        Ok(FetchedManifest {
             content_type: vm.content_type.to_owned(),
             digest: vm.digest,
-            path: path.to_string_lossy().to_string(),
+            data: manifest_bytes,
         })
     }
 
-    fn save_blob(&self, scratch_path: &PathBuf, digest: &str) -> Result<(), Error> {
-        let digest_path = self.get_catalog_path_for_blob(digest)?;
-        let repo_path = digest_path
-            .parent()
-            .ok_or_else(|| failure::err_msg("Error finding repository path"))?;
-
-        if !repo_path.exists() {
-            fs::create_dir_all(repo_path)?;
-        }
-
-        fs::copy(&scratch_path, &digest_path)?;
-        Ok(())
+    /**
+     * Moves a file in scratch into storage under the given digest. The scratch file is gone on
+     * success unless it had to be copied.
+     */
+    async fn save_blob(&self, scratch_path: &PathBuf, digest: &str) -> Result<(), Error> {
+        self.storage
+            .put_file(&blob_key(digest)?, scratch_path)
+            .await
     }
 
-    fn validate_and_save_blob(&self, user_digest: &str, uuid: &str) -> Result<(), Error> {
+    async fn validate_and_save_blob(
+        &self,
+        user_digest: &str,
+        uuid: &str,
+        hasher: Option<&UploadHasher>,
+    ) -> Result<(), Error> {
         debug!("Saving blob {}", user_digest);
 
         let scratch_path = self.get_upload_path_for_blob(uuid);
-        let res = match validate_digest(&scratch_path, user_digest) {
-            Ok(_) => self.save_blob(&scratch_path, user_digest),
+        let res = match validate_digest(&scratch_path, user_digest, hasher).await {
+            Ok(_) => self.save_blob(&scratch_path, user_digest).await,
             Err(e) => Err(e),
         };
 
-        //Not an error, even if it's not great
-        fs::remove_file(&scratch_path).unwrap_or_else(|e| {
-            error!(
-                "Error deleting file {} {:?}",
-                &scratch_path.to_string_lossy(),
-                e
-            )
-        });
+        remove_scratch_file(&scratch_path).await;
 
         res?;
         Ok(())
     }
 
     //Support functions for validate, would like to move these
-    pub fn image_exists(&self, image: &Image) -> bool {
-        match self.get_path_for_manifest(&image.repo, &image.tag) {
-            Ok(f) => f.exists(),
+    pub async fn image_exists(&self, image: &Image) -> bool {
+        let key = match self.get_digest_for_manifest(&image.repo, &image.tag).await {
+            Ok(digest) => blob_key(&digest),
+            Err(e) => Err(e),
+        };
+        match key {
+            Ok(key) => self.storage.exists(&key).await.unwrap_or(false),
             Err(_) => false,
         }
     }
@@ -767,28 +1258,220 @@ impl TrowServer {
         false
     }
 
-    fn is_writable_repo(&self, repo_name: &str) -> bool {
-        if repo_name.starts_with(PROXY_DIR) {
-            return false;
+    /**
+     * Checks a pushed manifest is an image, or an artifact of an allowed type.
+     */
+    fn check_artifact_type(&self, manifest_bytes: &[u8]) -> Result<(), Error> {
+        if self.allowed_artifact_types.is_empty() {
+            return Ok(());
+        }
+        let manifest = Manifest::from_json(&serde_json::from_slice(manifest_bytes)?)?;
+        match manifest.artifact_type() {
+            _ if manifest.is_image() => Ok(()),
+            Some(t) if self.allowed_artifact_types.iter().any(|a| a == t) => Ok(()),
+            t => Err(ArtifactTypeNotAllowed {
+                artifact_type: t.unwrap_or_default().to_string(),
+            }
+            .into()),
         }
-
-        true
     }
-}
 
-#[tonic::async_trait]
-impl Registry for TrowServer {
-    async fn request_upload(
+    /**
+     * Appends chunks to an upload, as the WriteBlob RPC does. Taking any stream means the backend
+     * can also be called in-process.
+     */
+    pub async fn write_blob_stream<S>(
         &self,
-        request: Request<UploadRequest>,
-    ) -> Result<Response<UploadDetails>, Status> {
-        let repo_name = request.into_inner().repo_name;
-        if self.is_writable_repo(&repo_name) {
-            let uuid = Uuid::new_v4().to_string();
-            let reply = UploadDetails { uuid: uuid.clone() };
-            let upload = Upload { repo_name, uuid };
-            {
-                self.active_uploads.write().unwrap().insert(upload);
+        mut chunks: S,
+    ) -> Result<Response<UploadStatus>, Status>
+    where
+        S: Stream<Item = Result<UploadChunk, Status>> + Unpin,
+    {
+        let first = match chunks.next().await {
+            Some(chunk) => chunk?,
+            None => return Err(Status::invalid_argument("No data sent for upload")),
+        };
+        let br = first
+            .upload
+            .ok_or_else(|| Status::invalid_argument("No upload given"))?;
+        let upload = Upload {
+            repo_name: br.repo_name.clone(),
+            uuid: br.uuid.clone(),
+        };
+        let hasher = match self.active_uploads.read().unwrap().get(&upload) {
+            Some(session) => session.hasher.clone(),
+            None => {
+                return Err(Status::failed_precondition(format!(
+                    "No current upload matching {:?}",
+                    br
+                )))
+            }
+        };
+        let offset = first.offset;
+        let path = self.get_upload_path_for_blob(&br.uuid);
+        // Without a hash of the data so far, e.g. after a restart, it is read once to catch up
+        let hasher = match hasher.filter(|hasher| hasher.covers(offset)) {
+            Some(hasher) => Some(hasher),
+            None => match hash_upload(&path).await {
+                Ok(hasher) => Some(hasher),
+                Err(e) => {
+                    warn!("Failed to hash upload {:?} {:?}", path, e);
+                    None
+                }
+            },
+        };
+        // If it doesn't match the offset, the write will fail
+        let mut hasher = hasher.filter(|hasher| hasher.covers(offset));
+
+        let written = append_chunks(&path, offset, first.data, &mut chunks, hasher.as_mut()).await;
+
+        // Even if the write failed part way, some data may have been received. The hash is only
+        // kept if it covers all of it, which it can't if the write failed or another write to the
+        // upload happened at the same time.
+        let bytes_received = tokio::fs::metadata(&path)
+            .await
+            .map(|m| m.len())
+            .unwrap_or(0);
+        let hasher = hasher.filter(|hasher| written.is_ok() && hasher.covers(bytes_received));
+        let session = self
+            .active_uploads
+            .write()
+            .unwrap()
+            .get_mut(&upload)
+            .map(|session| {
+                session.last_active = SystemTime::now();
+                session.bytes_received = bytes_received;
+                session.hasher = hasher;
+                session.clone()
+            });
+        if let Some(session) = session {
+            self.persist_session(&upload, &session).await;
+        }
+        let size = written?;
+        Ok(Response::new(UploadStatus { size }))
+    }
+
+    /**
+     * Takes a pushed manifest, checks it, and puts it in the catalog by blob digest and tag. As
+     * the WriteManifest RPC, for any stream of chunks.
+     */
+    pub async fn write_manifest_stream<S>(
+        &self,
+        mut chunks: S,
+    ) -> Result<Response<VerifiedManifest>, Status>
+    where
+        S: Stream<Item = Result<ManifestUploadChunk, Status>> + Unpin,
+    {
+        let first = match chunks.next().await {
+            Some(chunk) => chunk?,
+            None => return Err(Status::invalid_argument("No data sent for manifest")),
+        };
+        let mr = first
+            .manifest
+            .ok_or_else(|| Status::invalid_argument("No manifest reference given"))?;
+        if !is_writable_repo(&mr.repo_name) {
+            return Err(Status::permission_denied(format!(
+                "Repository {} is not writable",
+                mr.repo_name
+            )));
+        }
+        let mut manifest_bytes = first.data;
+        while let Some(chunk) = chunks.next().await {
+            if manifest_bytes.len() > MAX_MANIFEST_SIZE {
+                break;
+            }
+            manifest_bytes.extend(chunk?.data);
+        }
+        if manifest_bytes.len() > MAX_MANIFEST_SIZE {
+            return Err(Status::invalid_argument(format!(
+                "Manifest is larger than {} bytes",
+                MAX_MANIFEST_SIZE
+            )));
+        }
+
+        // Stop the garbage collector removing assets between checking and tagging them
+        let _guard = self.gc_lock.read().await;
+        // The digest the client expects, if any, decides the algorithm
+        let expected_digest = if !first.digest.is_empty() {
+            Some(first.digest.as_str())
+        } else if is_digest(&mr.reference) {
+            Some(mr.reference.as_str())
+        } else {
+            None
+        };
+        let verified = self
+            .verify_pushed_manifest(&mr.repo_name, &manifest_bytes, expected_digest)
+            .await;
+        match verified {
+            Ok(vm) => {
+                // save manifest to blobs, via scratch as for uploads, and add tag
+                let digest = vm.digest.clone();
+                let uploaded_manifest = self.get_upload_path_for_blob(&Uuid::new_v4().to_string());
+                let saved = match tokio::fs::write(&uploaded_manifest, &manifest_bytes).await {
+                    Ok(_) => self.save_blob(&uploaded_manifest, &digest).await,
+                    Err(e) => Err(e.into()),
+                };
+                let saved = match saved {
+                    Ok(_) => self.save_tag(&digest, &mr.repo_name, &mr.reference).await,
+                    Err(e) => Err(e),
+                };
+                let saved = match saved {
+                    Ok(_) => self.index_referrer(&mr.repo_name, &digest).await,
+                    Err(e) => Err(e),
+                };
+                let ret = saved.map(|_| Response::new(vm)).map_err(|e| {
+                    error!(
+                        "Failure cataloguing manifest {}/{} {:?}",
+                        &mr.repo_name, &mr.reference, e
+                    );
+                    Status::internal("Internal error copying manifest")
+                });
+
+                remove_scratch_file(&uploaded_manifest).await;
+
+                ret
+            }
+            Err(e) => {
+                error!("Error verifying manifest {:?}", e);
+                let e = match e.downcast::<MissingManifests>() {
+                    Ok(e) => {
+                        let details = serde_json::to_vec(&e.missing).unwrap_or_default();
+                        return Err(Status::with_details(
+                            Code::FailedPrecondition,
+                            e.to_string(),
+                            details.into(),
+                        ));
+                    }
+                    Err(e) => e,
+                };
+                let e = match e.downcast::<DigestValidationError>() {
+                    Ok(e) => return Err(Status::invalid_argument(e.to_string())),
+                    Err(e) => e,
+                };
+                match e.downcast::<ArtifactTypeNotAllowed>() {
+                    Ok(e) => Err(Status::invalid_argument(e.to_string())),
+                    Err(_) => Err(Status::invalid_argument("Failed to verify manifest")),
+                }
+            }
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Registry for TrowServer {
+    async fn request_upload(
+        &self,
+        request: Request<UploadRequest>,
+    ) -> Result<Response<UploadDetails>, Status> {
+        let repo_name = request.into_inner().repo_name;
+        if is_writable_repo(&repo_name) {
+            let uuid = Uuid::new_v4().to_string();
+            let reply = UploadDetails { uuid: uuid.clone() };
+            let upload = Upload { repo_name, uuid };
+            let session = UploadSession::new();
+            self.persist_session(&upload, &session).await;
+            {
+                self.active_uploads.write().unwrap().insert(upload, session);
                 debug!("Upload Table: {:?}", self.active_uploads);
             }
             Ok(Response::new(reply))
@@ -800,73 +1483,168 @@ impl Registry for TrowServer {
         }
     }
 
-    async fn get_write_location_for_blob(
+    async fn write_blob(
+        &self,
+        req: Request<Streaming<UploadChunk>>,
+    ) -> Result<Response<UploadStatus>, Status> {
+        self.write_blob_stream(req.into_inner()).await
+    }
+
+    async fn get_upload_status(
         &self,
         req: Request<UploadRef>,
-    ) -> Result<Response<WriteLocation>, Status> {
+    ) -> Result<Response<UploadStatus>, Status> {
         let br = req.into_inner();
         let upload = Upload {
             repo_name: br.repo_name.clone(),
             uuid: br.uuid.clone(),
         };
 
-        // Apparently unwrap() is correct here. From the docs:
-        // "We unwrap() the return value to assert that we are not expecting
-        // threads to ever fail while holding the lock."
+        if !self.active_uploads.read().unwrap().contains_key(&upload) {
+            return Err(Status::not_found(format!(
+                "No current upload matching {:?}",
+                br
+            )));
+        }
+
+        // The session is only updated once a stream of chunks has been written
+        let path = self.get_upload_path_for_blob(&br.uuid);
+        let size = tokio::fs::metadata(&path)
+            .await
+            .map(|m| m.len())
+            .unwrap_or(0);
+        Ok(Response::new(UploadStatus { size }))
+    }
 
-        let set = self.active_uploads.read().unwrap();
-        if set.contains(&upload) {
-            let path = self.get_upload_path_for_blob(&br.uuid);
-            Ok(Response::new(WriteLocation {
-                path: path.to_string_lossy().to_string(),
-            }))
-        } else {
-            Err(Status::failed_precondition(format!(
+    async fn cancel_upload(
+        &self,
+        req: Request<UploadRef>,
+    ) -> Result<Response<UploadCancelled>, Status> {
+        let br = req.into_inner();
+        let upload = Upload {
+            repo_name: br.repo_name.clone(),
+            uuid: br.uuid.clone(),
+        };
+
+        if self
+            .active_uploads
+            .write()
+            .unwrap()
+            .remove(&upload)
+            .is_none()
+        {
+            return Err(Status::not_found(format!(
                 "No current upload matching {:?}",
                 br
-            )))
+            )));
         }
+
+        info!("Cancelling upload {} to {}", br.uuid, br.repo_name);
+        remove_scratch_file(&self.get_upload_path_for_blob(&br.uuid)).await;
+        self.remove_persisted_session(&br.uuid).await;
+        Ok(Response::new(UploadCancelled {}))
     }
 
-    async fn get_read_location_for_blob(
+    type ReadBlobStream = ReceiverStream<Result<BlobChunk, Status>>;
+
+    async fn read_blob(
         &self,
-        req: Request<BlobRef>,
-    ) -> Result<Response<BlobReadLocation>, Status> {
+        req: Request<ReadBlobRequest>,
+    ) -> Result<Response<Self::ReadBlobStream>, Status> {
         metrics::TOTAL_BLOB_REQUESTS.inc();
         let br = req.into_inner();
-        let path = self
-            .get_catalog_path_for_blob(&br.digest)
+        let key = blob_key(&br.digest)
             .map_err(|e| Status::invalid_argument(format!("Error parsing digest {:?}", e)))?;
 
-        if !path.exists() {
-            warn!("Request for unknown blob: {:?}", path);
-            Err(Status::not_found(format!(
+        let file = match self.storage.open(&key).await {
+            Ok(file) => file,
+            Err(e) if is_not_found(&e) => {
+                warn!("Request for unknown blob: {:?}", key);
+                return Err(Status::not_found(format!(
+                    "No blob found matching {:?}",
+                    br
+                )));
+            }
+            Err(e) => {
+                error!("Failed to read blob {:?} {:?}", br, e);
+                return Err(Status::internal("Internal error reading blob"));
+            }
+        };
+        let size = file.metadata().await.map(|m| m.len()).map_err(|e| {
+            error!("Failed to read blob {:?} {:?}", br, e);
+            Status::internal("Internal error reading blob")
+        })?;
+        Ok(Response::new(stream_blob(file, size, br.offset)))
+    }
+
+    async fn get_blob_info(&self, req: Request<BlobRef>) -> Result<Response<BlobInfo>, Status> {
+        let br = req.into_inner();
+        let key = blob_key(&br.digest)
+            .map_err(|e| Status::invalid_argument(format!("Error parsing digest {:?}", e)))?;
+
+        match self.storage.metadata(&key).await {
+            Ok(metadata) => {
+                // The client may skip uploading the blob and push a manifest using it
+                self.found_blobs
+                    .lock()
+                    .unwrap()
+                    .insert(br.digest.clone(), SystemTime::now());
+                Ok(Response::new(BlobInfo {
+                    digest: br.digest,
+                    size: metadata.len,
+                }))
+            }
+            Err(e) if is_not_found(&e) => Err(Status::not_found(format!(
                 "No blob found matching {:?}",
                 br
-            )))
-        } else {
-            Ok(Response::new(BlobReadLocation {
-                path: path.to_string_lossy().to_string(),
-            }))
+            ))),
+            Err(e) => {
+                error!("Failed to stat blob {:?} {:?}", br, e);
+                Err(Status::internal("Internal error reading blob"))
+            }
         }
     }
 
     /**
-     * TODO: check if blob referenced by manifests. If so, refuse to delete.
+     * Refuses to delete blobs referenced by any stored manifest in any repository, tagged or not.
+     * What refers to the blob is returned as a JSON array in the status details, as given by
+     * references_to: "repo:tag" for tags and the digest of each untagged manifest.
      */
     async fn delete_blob(&self, req: Request<BlobRef>) -> Result<Response<BlobDeleted>, Status> {
         let br = req.into_inner();
-        let path = self
-            .get_catalog_path_for_blob(&br.digest)
+        let key = blob_key(&br.digest)
             .map_err(|e| Status::invalid_argument(format!("Error parsing digest {:?}", e)))?;
-        if !path.exists() {
-            warn!("Request for unknown blob: {:?}", path);
+
+        // Stop a manifest referencing the blob being catalogued between the check and delete
+        let _guard = self.gc_lock.write().await;
+        let exists = self.storage.exists(&key).await.map_err(|e| {
+            error!("Failed to check blob {:?} {:?}", br, e);
+            Status::internal("Internal error deleting blob")
+        })?;
+        if !exists {
+            warn!("Request for unknown blob: {:?}", key);
             Err(Status::not_found(format!(
                 "No blob found matching {:?}",
                 br
             )))
         } else {
-            fs::remove_file(&path)
+            let tags = self.references_to(&br.digest).await.map_err(|e| {
+                error!("Failed to check references to blob {:?} {:?}", br, e);
+                Status::internal("Internal error deleting blob")
+            })?;
+            if !tags.is_empty() {
+                info!("Refusing to delete blob {:?} referenced by {:?}", br, tags);
+                let details = serde_json::to_vec(&tags).unwrap_or_default();
+                return Err(Status::with_details(
+                    Code::FailedPrecondition,
+                    format!("Blob {} is referenced by {}", br.digest, tags.join(", ")),
+                    details.into(),
+                ));
+            }
+
+            self.storage
+                .delete(&key)
+                .await
                 .map_err(|e| {
                     error!("Failed to delete blob {:?} {:?}", br, e);
                     Status::internal("Internal error deleting blob")
@@ -875,72 +1653,151 @@ impl Registry for TrowServer {
         }
     }
 
+    /**
+     * Deleting by tag removes just that tag. Deleting by digest removes every tag in the
+     * repository that currently points to the manifest. Either way the tag history is archived
+     * and the manifest itself is left for the garbage collector.
+     */
     async fn delete_manifest(
         &self,
         req: Request<ManifestRef>,
     ) -> Result<Response<ManifestDeleted>, Status> {
         let mr = req.into_inner();
         if !is_digest(&mr.reference) {
-            return Err(Status::invalid_argument(format!(
-                "Manifests can only be deleted by digest. Got {}",
-                mr.reference
-            )));
+            if !is_valid_tag(&mr.reference) {
+                return Err(Status::invalid_argument(format!(
+                    "Invalid tag {}",
+                    mr.reference
+                )));
+            }
+            let digest = self
+                .get_digest_from_manifest(&mr.repo_name, &mr.reference)
+                .await;
+            return match self.archive_tag(&mr.repo_name, &mr.reference).await {
+                Ok(_) => {
+                    if let Ok(digest) = digest {
+                        self.remove_from_referrers(&mr.repo_name, &digest).await;
+                    }
+                    Ok(Response::new(ManifestDeleted {}))
+                }
+                Err(e) if is_not_found(&e) => Err(Status::not_found(format!(
+                    "No tag {} in repository {}",
+                    mr.reference, mr.repo_name
+                ))),
+                Err(e) => {
+                    error!("Failed to remove tag {:?} {:?}", mr, e);
+                    Err(Status::internal("Internal error removing tag"))
+                }
+            };
         }
         let digest = mr.reference;
         //For the repo, go through all tags and see if they reference the digest. Delete them.
         //Can only delete manifest if no other tags in any repo reference it
 
-        let ri = RepoIterator::new(&self.manifests_path.join(&mr.repo_name)).map_err(|e| {
-            error!("Problem reading manifest catalog {:?}", e);
-            Status::failed_precondition("Repository not found")
-        })?;
+        let repo_prefix = format!("{}/", repo_key(&mr.repo_name));
+        let tags = self
+            .storage
+            .list(&repo_key(&mr.repo_name))
+            .await
+            .map_err(|e| {
+                error!("Problem reading manifest catalog {:?}", e);
+                Status::internal("Internal error reading manifest catalog")
+            })?;
+        if tags.is_empty() {
+            return Err(Status::failed_precondition("Repository not found"));
+        }
 
         //TODO: error if no manifest matches?
-        ri.filter(|de| does_manifest_match_digest(de, &digest))
-            .for_each(|man| match fs::remove_file(man.path()) {
-                Ok(_) => (),
-                Err(e) => error!("Failed to delete manifest {:?} {:?}", &man, e),
-            });
+        for tag in tags {
+            let matches = match self.storage.read(&tag).await {
+                Ok(contents) => get_digest_from_tag_file(&contents) == digest,
+                Err(e) => {
+                    warn!("Failure reading repo {:?}", e);
+                    false
+                }
+            };
+            if matches {
+                let tag_name = tag.trim_start_matches(&repo_prefix);
+                if let Err(e) = self.archive_tag(&mr.repo_name, tag_name).await {
+                    error!("Failed to delete manifest {:?} {:?}", &tag, e);
+                }
+            }
+        }
+        self.remove_from_referrers(&mr.repo_name, &digest).await;
 
         Ok(Response::new(ManifestDeleted {}))
     }
 
-    async fn get_write_details_for_manifest(
+    type ReadManifestStream = ReceiverStream<Result<ManifestChunk, Status>>;
+
+    async fn read_manifest(
         &self,
         req: Request<ManifestRef>,
-    ) -> Result<Response<ManifestWriteDetails>, Status> {
-        let repo_name = req.into_inner().repo_name;
-        if self.is_writable_repo(&repo_name) {
-            //Give the manifest a UUID and save it to the uploads dir
-            let uuid = Uuid::new_v4().to_string();
+    ) -> Result<Response<Self::ReadManifestStream>, Status> {
+        //Don't actually need to verify here; could set to false
 
-            let manifest_path = self.get_upload_path_for_blob(&uuid);
-            Ok(Response::new(ManifestWriteDetails {
-                path: manifest_path.to_string_lossy().to_string(),
-                uuid,
-            }))
-        } else {
-            Err(Status::invalid_argument(format!(
-                "Repository {} is not writable",
-                repo_name
-            )))
-        }
+        let mr = req.into_inner();
+        metrics::TOTAL_MANIFEST_REQUESTS.inc();
+        let manifest = match self.fetch_manifest(mr.repo_name, mr.reference, true).await {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                warn!("Internal error with manifest {:?}", e);
+                return Err(Status::internal("Internal error finding manifest"));
+            }
+        };
+
+        let (tx, rx) = mpsc::channel(4);
+        tokio::spawn(async move {
+            let mut chunks = manifest.data.chunks(CHUNK_SIZE);
+            let mut chunk = ManifestChunk {
+                digest: manifest.digest,
+                content_type: manifest.content_type,
+                data: chunks.next().unwrap_or_default().to_vec(),
+            };
+            loop {
+                if tx.send(Ok(chunk)).await.is_err() {
+                    break;
+                }
+                chunk = match chunks.next() {
+                    Some(data) => ManifestChunk {
+                        data: data.to_vec(),
+                        ..Default::default()
+                    },
+                    None => break,
+                };
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
     }
 
-    async fn get_read_location_for_manifest(
+    async fn get_manifest_info(
         &self,
         req: Request<ManifestRef>,
-    ) -> Result<Response<ManifestReadLocation>, Status> {
-        //Don't actually need to verify here; could set to false
-
+    ) -> Result<Response<ManifestInfo>, Status> {
         let mr = req.into_inner();
-        metrics::TOTAL_MANIFEST_REQUESTS.inc();
-        // TODO refactor to return directly
-        match self
-            .create_manifest_read_location(mr.repo_name, mr.reference, true)
+        // Goes through the same path as a GET so proxied images are fetched, but the manifest's
+        // assets aren't checked as that means a request per layer
+        let info = match self
+            .fetch_manifest(mr.repo_name.clone(), mr.reference.clone(), false)
             .await
         {
-            Ok(vm) => Ok(Response::new(vm)),
+            Ok(rl) => match blob_key(&rl.digest) {
+                Ok(key) => self.storage.metadata(&key).await.map(|m| ManifestInfo {
+                    digest: rl.digest,
+                    size: m.len,
+                    content_type: rl.content_type,
+                }),
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+
+        match info {
+            Ok(info) => Ok(Response::new(info)),
+            Err(e) if is_not_found(&e) => Err(Status::not_found(format!(
+                "No manifest found matching {:?}",
+                mr
+            ))),
             Err(e) => {
                 warn!("Internal error with manifest {:?}", e);
                 Err(Status::internal("Internal error finding manifest"))
@@ -948,44 +1805,11 @@ impl Registry for TrowServer {
         }
     }
 
-    /**
-     * Take uploaded manifest (which should be uuid in uploads), check it, put in catalog and
-     * by blob digest
-     */
-    async fn verify_manifest(
+    async fn write_manifest(
         &self,
-        req: Request<VerifyManifestRequest>,
+        req: Request<Streaming<ManifestUploadChunk>>,
     ) -> Result<Response<VerifiedManifest>, Status> {
-        let req = req.into_inner();
-        let mr = req.manifest.unwrap(); // Pissed off that the manifest is optional!
-        let uploaded_manifest = self.get_upload_path_for_blob(&req.uuid);
-
-        match self.create_verified_manifest(&uploaded_manifest, true) {
-            Ok(vm) => {
-                // copy manifest to blobs and add tag
-                let digest = vm.digest.clone();
-                let ret = self
-                    .save_blob(&uploaded_manifest, &digest)
-                    .and(self.save_tag(&digest, &mr.repo_name, &mr.reference))
-                    .map(|_| Response::new(vm))
-                    .map_err(|e| {
-                        error!(
-                            "Failure cataloguing manifest {}/{} {:?}",
-                            &mr.repo_name, &mr.reference, e
-                        );
-                        Status::internal("Internal error copying manifest")
-                    });
-
-                fs::remove_file(&uploaded_manifest)
-                    .unwrap_or_else(|e| error!("Failure deleting uploaded manifest {:?}", e));
-
-                ret
-            }
-            Err(e) => {
-                error!("Error verifying manifest {:?}", e);
-                Err(Status::invalid_argument("Failed to verify manifest"))
-            }
-        }
+        self.write_manifest_stream(req.into_inner()).await
     }
 
     async fn complete_upload(
@@ -993,7 +1817,20 @@ impl Registry for TrowServer {
         req: Request<CompleteRequest>,
     ) -> Result<Response<CompletedUpload>, Status> {
         let cr = req.into_inner();
-        let ret = match self.validate_and_save_blob(&cr.user_digest, &cr.uuid) {
+        let upload = Upload {
+            repo_name: cr.repo_name.clone(),
+            uuid: cr.uuid.clone(),
+        };
+        let hasher = self
+            .active_uploads
+            .read()
+            .unwrap()
+            .get(&upload)
+            .and_then(|session| session.hasher.clone());
+        let ret = match self
+            .validate_and_save_blob(&cr.user_digest, &cr.uuid, hasher.as_ref())
+            .await
+        {
             Ok(_) => Ok(Response::new(CompletedUpload {
                 digest: cr.user_digest.clone(),
             })),
@@ -1007,19 +1844,20 @@ impl Registry for TrowServer {
         };
 
         //delete uuid from uploads tracking
-        let upload = Upload {
-            repo_name: cr.repo_name.clone(),
-            uuid: cr.uuid,
-        };
-
-        let mut set = self.active_uploads.write().unwrap();
-        if !set.remove(&upload) {
+        if self
+            .active_uploads
+            .write()
+            .unwrap()
+            .remove(&upload)
+            .is_none()
+        {
             warn!("Upload {:?} not found when deleting", upload);
         }
+        self.remove_persisted_session(&upload.uuid).await;
         ret
     }
 
-    type GetCatalogStream = mpsc::Receiver<Result<CatalogEntry, Status>>;
+    type GetCatalogStream = ReceiverStream<Result<CatalogEntry, Status>>;
 
     async fn get_catalog(
         &self,
@@ -1028,20 +1866,18 @@ impl Registry for TrowServer {
         let cr = request.into_inner();
         let limit = cr.limit as usize;
 
-        let (mut tx, rx) = mpsc::channel(4);
-        let catalog: HashSet<String> = RepoIterator::new(&self.manifests_path)
+        let (tx, rx) = mpsc::channel(4);
+        let catalog: HashSet<String> = self
+            .storage
+            .list(MANIFESTS_DIR)
+            .await
             .map_err(|e| {
                 error!("Error accessing catalog {:?}", e);
                 Status::internal("Internal error streaming catalog")
             })?
-            .map(|de| de.path())
-            .filter_map(|p| p.parent().map(|p| p.to_path_buf()))
-            .filter_map(|r| {
-                r.strip_prefix(&self.manifests_path)
-                    .ok()
-                    .map(|p| p.to_path_buf())
-            })
-            .map(|p| p.to_string_lossy().to_string())
+            .iter()
+            .filter_map(|key| Some(key.strip_prefix(MANIFESTS_DIR)?.rsplit_once('/')?.0))
+            .map(|repo| repo.trim_start_matches('/').to_string())
             .collect();
         let partial_catalog: Vec<String> = if cr.last_repo.is_empty() {
             catalog.into_iter().take(limit).collect()
@@ -1061,29 +1897,32 @@ impl Registry for TrowServer {
                 tx.send(Ok(ce)).await.expect("Error streaming catalog");
             }
         });
-        Ok(Response::new(rx))
+        Ok(Response::new(ReceiverStream::new(rx)))
     }
 
-    type ListTagsStream = mpsc::Receiver<Result<Tag, Status>>;
+    type ListTagsStream = ReceiverStream<Result<Tag, Status>>;
 
     async fn list_tags(
         &self,
         request: Request<ListTagsRequest>,
     ) -> Result<Response<Self::ListTagsStream>, Status> {
-        let (mut tx, rx) = mpsc::channel(4);
-        let mut path = PathBuf::from(&self.manifests_path);
+        let (tx, rx) = mpsc::channel(4);
 
         let ltr = request.into_inner();
 
         let limit = ltr.limit as usize;
-        path.push(&ltr.repo_name);
 
-        let mut catalog: Vec<String> = RepoIterator::new(&path)
+        let mut catalog: Vec<String> = self
+            .storage
+            .list(&repo_key(&ltr.repo_name))
+            .await
             .map_err(|e| {
                 error!("Error accessing catalog {:?}", e);
                 Status::internal("Internal error streaming catalog")
             })?
-            .map(|de| de.path().file_name().unwrap().to_string_lossy().to_string())
+            .iter()
+            .filter_map(|key| key.rsplit('/').next())
+            .map(|tag| tag.to_string())
             .collect();
         catalog.sort();
         let partial_catalog: Vec<String> = if ltr.last_tag.is_empty() {
@@ -1106,10 +1945,42 @@ impl Registry for TrowServer {
                 .expect("Error streaming tags");
             }
         });
-        Ok(Response::new(rx))
+        Ok(Response::new(ReceiverStream::new(rx)))
     }
 
-    type GetManifestHistoryStream = mpsc::Receiver<Result<ManifestHistoryEntry, Status>>;
+    type ListReferrersStream = ReceiverStream<Result<Referrer, Status>>;
+
+    async fn list_referrers(
+        &self,
+        request: Request<ReferrersRequest>,
+    ) -> Result<Response<Self::ListReferrersStream>, Status> {
+        let rr = request.into_inner();
+        if referrers::digest_path(&rr.digest).is_err() {
+            return Err(Status::invalid_argument(format!(
+                "Invalid digest {}",
+                rr.digest
+            )));
+        }
+        let referrers = self
+            .get_referrers(&rr.repo_name, &rr.digest, &rr.artifact_type)
+            .await
+            .map_err(|e| {
+                error!("Error reading referrers index {:?}", e);
+                Status::internal("Internal error reading referrers")
+            })?;
+
+        let (tx, rx) = mpsc::channel(4);
+        tokio::spawn(async move {
+            for referrer in referrers {
+                tx.send(Ok(Referrer::from(referrer)))
+                    .await
+                    .expect("Error streaming referrers");
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    type GetManifestHistoryStream = ReceiverStream<Result<ManifestHistoryEntry, Status>>;
 
     async fn get_manifest_history(
         &self,
@@ -1122,21 +1993,20 @@ impl Registry for TrowServer {
             ));
         }
 
-        let manifest_path = self.manifests_path.join(&mr.repo_name).join(&mr.tag);
+        let key = tag_key(&mr.repo_name, &mr.tag);
 
-        let file = File::open(&manifest_path);
-
-        if file.is_err() {
-            return Err(Status::not_found(format!(
-                "Could not find the requested manifest at: {}",
-                &manifest_path.to_str().unwrap()
-            )));
-        }
-
-        // It's safe to unwrap here
-        let reader = BufReader::new(file.unwrap());
+        let history = match self.storage.read(&key).await {
+            Ok(history) => history,
+            Err(_) => {
+                return Err(Status::not_found(format!(
+                    "Could not find the requested manifest at: {}",
+                    key
+                )));
+            }
+        };
+        let reader = BufReader::new(io::Cursor::new(history));
 
-        let (mut tx, rx) = mpsc::channel(4);
+        let (tx, rx) = mpsc::channel(4);
         tokio::spawn(async move {
             let mut searching_for_digest = mr.last_digest != ""; //Looking for a digest iff it's not empty
 
@@ -1189,7 +2059,7 @@ impl Registry for TrowServer {
                 }
             }
         });
-        Ok(Response::new(rx))
+        Ok(Response::new(ReceiverStream::new(rx)))
     }
 
     // Readiness check
@@ -1197,8 +2067,8 @@ impl Registry for TrowServer {
         &self,
         _request: Request<ReadinessRequest>,
     ) -> Result<Response<ReadyStatus>, Status> {
-        for path in &[&self.scratch_path, &self.manifests_path, &self.blobs_path] {
-            match is_path_writable(path) {
+        for path in &[&self.data_path, &self.scratch_path] {
+            match is_path_writable(path).await {
                 Ok(true) => {}
                 Ok(false) => {
                     return Err(Status::unavailable(format!(
@@ -1234,7 +2104,8 @@ impl Registry for TrowServer {
         &self,
         _request: Request<MetricsRequest>,
     ) -> Result<Response<MetricsResponse>, Status> {
-        match metrics::gather_metrics(&self.blobs_path) {
+        let scratch_path = self.scratch_path.clone();
+        match blocking(move || metrics::gather_metrics(&scratch_path)).await {
             Ok(metrics) => {
                 let reply = trow_server::MetricsResponse { metrics };
                 Ok(Response::new(reply))
@@ -1243,4 +2114,750 @@ impl Registry for TrowServer {
             Err(error) => Err(Status::unavailable(error.to_string())),
         }
     }
+
+    async fn collect_garbage(
+        &self,
+        request: Request<GarbageCollectionRequest>,
+    ) -> Result<Response<GarbageCollectionReport>, Status> {
+        let dry_run = request.into_inner().dry_run;
+        match self.run_gc(dry_run, gc::GC_GRACE_PERIOD).await {
+            Ok(report) => Ok(Response::new(report)),
+            Err(e) => {
+                error!("Error collecting garbage {:?}", e);
+                Err(Status::internal("Internal error collecting garbage"))
+            }
+        }
+    }
+    async fn fsck(&self, request: Request<FsckRequest>) -> Result<Response<FsckReport>, Status> {
+        let repair = request.into_inner().repair;
+        match self.run_fsck(repair).await {
+            Ok(report) => Ok(Response::new(report)),
+            Err(e) => {
+                error!("Error checking data directory {:?}", e);
+                Err(Status::internal("Internal error checking data directory"))
+            }
+        }
+    }
+}
+
+/**
+ * Server for tests, keeping its data in the given directory, normally a tempfile::TempDir.
+ */
+#[cfg(test)]
+pub(crate) fn test_server(dir: &Path) -> TrowServer {
+    TrowServer::new(
+        dir.to_str().unwrap(),
+        false,
+        None,
+        None,
+        vec![],
+        vec![],
+        vec![],
+        vec![],
+    )
+    .unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::trow_server::registry_server::Registry;
+    use super::trow_server::{
+        BlobRef, CompleteRequest, ManifestRef, ManifestUploadChunk, ReadBlobRequest, UploadChunk,
+        UploadRef, UploadRequest,
+    };
+    use super::{
+        blob_key, empty_descriptor, tag_key, test_server, CHUNK_SIZE, MAX_MANIFEST_SIZE,
+        UPLOADS_DIR,
+    };
+    use crate::digest::{sha256_tag_digest, sha512_tag_digest};
+    use crate::storage::temp_file_name;
+    use std::fs;
+    use tokio_stream::StreamExt;
+    use tonic::{Code, Request};
+    use uuid::Uuid;
+
+    #[test]
+    fn blob_keys_are_only_made_from_valid_digests() {
+        assert_eq!(blob_key("sha256:abc").unwrap(), "blobs/sha256/abc");
+        assert!(blob_key("md5:abc").is_err());
+        assert!(blob_key("sha256").is_err());
+        assert!(blob_key("sha256:abc:def").is_err());
+    }
+
+    #[tokio::test]
+    async fn startup_removes_incomplete_writes() {
+        let dir = tempfile::tempdir().unwrap();
+        let scratch = dir.path().join(UPLOADS_DIR);
+        fs::create_dir_all(&scratch).unwrap();
+        let upload = scratch.join(Uuid::new_v4().to_string());
+        let temp = scratch.join(temp_file_name());
+        fs::write(&upload, "upload").unwrap();
+        fs::write(&temp, "half written").unwrap();
+
+        let ts = test_server(dir.path());
+        assert!(upload.exists());
+        assert!(!temp.exists());
+
+        ts.save_tag("sha256:1", "atomic/test", "latest")
+            .await
+            .unwrap();
+        ts.save_tag("sha256:2", "atomic/test", "latest")
+            .await
+            .unwrap();
+        let history =
+            fs::read_to_string(dir.path().join(tag_key("atomic/test", "latest"))).unwrap();
+        let digests: Vec<&str> = history
+            .lines()
+            .map(|l| l.split(' ').next().unwrap())
+            .collect();
+        assert_eq!(digests, vec!["sha256:2", "sha256:1"]);
+        // Only the upload is left in scratch
+        assert_eq!(fs::read_dir(&scratch).unwrap().count(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn concurrent_pushes_keep_tag_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = test_server(dir.path());
+
+        let pushes: Vec<_> = (0..20)
+            .map(|i| {
+                let ts = ts.clone();
+                tokio::spawn(async move {
+                    ts.save_tag(&format!("sha256:{}", i), "concurrent/test", "latest")
+                        .await
+                        .unwrap()
+                })
+            })
+            .collect();
+        for push in pushes {
+            push.await.unwrap();
+        }
+
+        let history =
+            fs::read_to_string(dir.path().join(tag_key("concurrent/test", "latest"))).unwrap();
+        assert_eq!(history.lines().count(), 20);
+        assert!(ts.tag_locks.lock().unwrap().len() <= 1);
+    }
+
+    #[tokio::test]
+    async fn save_blob_moves_upload_into_catalog() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = test_server(dir.path());
+
+        let uuid = Uuid::new_v4().to_string();
+        fs::write(ts.get_upload_path_for_blob(&uuid), "layer").unwrap();
+        let digest = sha256_tag_digest("layer".as_bytes()).unwrap();
+
+        ts.validate_and_save_blob(&digest, &uuid, None)
+            .await
+            .unwrap();
+        let blob = dir.path().join(blob_key(&digest).unwrap());
+        assert_eq!(fs::read_to_string(blob).unwrap(), "layer");
+        assert!(!ts.get_upload_path_for_blob(&uuid).exists());
+    }
+
+    #[tokio::test]
+    async fn upload_must_match_digest() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = test_server(dir.path());
+
+        let upload = |data: &'static [&'static str]| {
+            let ts = ts.clone();
+            async move {
+                let req = UploadRequest {
+                    repo_name: "digest/test".to_string(),
+                };
+                let upload_ref = UploadRef {
+                    repo_name: "digest/test".to_string(),
+                    uuid: ts
+                        .request_upload(Request::new(req))
+                        .await
+                        .unwrap()
+                        .into_inner()
+                        .uuid,
+                };
+                let mut offset = 0;
+                for data in data {
+                    let chunk = UploadChunk {
+                        upload: Some(upload_ref.clone()),
+                        offset,
+                        data: data.as_bytes().to_vec(),
+                    };
+                    offset = ts
+                        .write_blob_stream(tokio_stream::iter(vec![Ok(chunk)]))
+                        .await
+                        .unwrap()
+                        .into_inner()
+                        .size;
+                }
+                upload_ref
+            }
+        };
+        let complete = |upload_ref: UploadRef, digest: &str| {
+            ts.complete_upload(Request::new(CompleteRequest {
+                repo_name: upload_ref.repo_name,
+                uuid: upload_ref.uuid,
+                user_digest: digest.to_string(),
+            }))
+        };
+
+        // The digest of other content of the same length is refused
+        let other = sha256_tag_digest("other".as_bytes()).unwrap();
+        let err = complete(upload(&["lay", "er"]).await, &other)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        assert!(!dir.path().join(blob_key(&other).unwrap()).exists());
+
+        let digest = sha256_tag_digest("layer".as_bytes()).unwrap();
+        complete(upload(&["lay", "er"]).await, &digest)
+            .await
+            .unwrap();
+        let blob = dir.path().join(blob_key(&digest).unwrap());
+        assert_eq!(fs::read_to_string(blob).unwrap(), "layer");
+
+        // Digests with other algorithms are checked by reading the upload
+        let digest = sha512_tag_digest("layer".as_bytes()).unwrap();
+        complete(upload(&["lay", "er"]).await, &digest)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn upload_status_reports_bytes_received() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = test_server(dir.path());
+
+        let req = UploadRequest {
+            repo_name: "status/test".to_string(),
+        };
+        let upload_ref = UploadRef {
+            repo_name: "status/test".to_string(),
+            uuid: ts
+                .request_upload(Request::new(req))
+                .await
+                .unwrap()
+                .into_inner()
+                .uuid,
+        };
+        let status = ts
+            .get_upload_status(Request::new(upload_ref.clone()))
+            .await
+            .unwrap();
+        assert_eq!(status.into_inner().size, 0);
+
+        fs::write(ts.get_upload_path_for_blob(&upload_ref.uuid), "chunk").unwrap();
+        let status = ts
+            .get_upload_status(Request::new(upload_ref.clone()))
+            .await
+            .unwrap();
+        assert_eq!(status.into_inner().size, 5);
+
+        let unknown = UploadRef {
+            repo_name: "status/test".to_string(),
+            uuid: Uuid::new_v4().to_string(),
+        };
+        let err = ts
+            .get_upload_status(Request::new(unknown))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn blobs_are_streamed_in_and_out() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = test_server(dir.path());
+
+        let req = UploadRequest {
+            repo_name: "stream/test".to_string(),
+        };
+        let upload_ref = UploadRef {
+            repo_name: "stream/test".to_string(),
+            uuid: ts
+                .request_upload(Request::new(req))
+                .await
+                .unwrap()
+                .into_inner()
+                .uuid,
+        };
+        let write = |offset: u64, data: &[&str]| {
+            let chunks: Vec<_> = data
+                .iter()
+                .enumerate()
+                .map(|(i, data)| UploadChunk {
+                    upload: if i == 0 {
+                        Some(upload_ref.clone())
+                    } else {
+                        None
+                    },
+                    offset,
+                    data: data.as_bytes().to_vec(),
+                })
+                .collect();
+            ts.write_blob_stream(tokio_stream::iter(chunks).map(Ok))
+        };
+        let status = write(0, &["he", "llo"]).await.unwrap().into_inner();
+        assert_eq!(status.size, 5);
+        // Chunks out of order are refused
+        let err = write(3, &[" world"]).await.unwrap_err();
+        assert_eq!(err.code(), Code::OutOfRange);
+        let status = write(5, &[" world"]).await.unwrap().into_inner();
+        assert_eq!(status.size, 11);
+
+        let digest = sha256_tag_digest("hello world".as_bytes()).unwrap();
+        ts.validate_and_save_blob(&digest, &upload_ref.uuid, None)
+            .await
+            .unwrap();
+        let read = |offset: u64| {
+            ts.read_blob(Request::new(ReadBlobRequest {
+                repo_name: "stream/test".to_string(),
+                digest: digest.clone(),
+                offset,
+            }))
+        };
+        for (offset, expected) in &[(0, "hello world"), (6, "world"), (11, "")] {
+            let chunks: Vec<_> = read(*offset)
+                .await
+                .unwrap()
+                .into_inner()
+                .map(Result::unwrap)
+                .collect()
+                .await;
+            assert_eq!(chunks[0].size, 11);
+            let data: Vec<u8> = chunks.into_iter().flat_map(|c| c.data).collect();
+            assert_eq!(data, expected.as_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn cancel_upload_removes_session_and_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = test_server(dir.path());
+
+        let req = UploadRequest {
+            repo_name: "cancel/test".to_string(),
+        };
+        let upload_ref = UploadRef {
+            repo_name: "cancel/test".to_string(),
+            uuid: ts
+                .request_upload(Request::new(req))
+                .await
+                .unwrap()
+                .into_inner()
+                .uuid,
+        };
+        let path = ts.get_upload_path_for_blob(&upload_ref.uuid);
+        fs::write(&path, "chunk").unwrap();
+
+        ts.cancel_upload(Request::new(upload_ref.clone()))
+            .await
+            .unwrap();
+        assert!(ts.active_uploads.read().unwrap().is_empty());
+        assert!(!path.exists());
+        assert!(!ts.sessions_path.join(&upload_ref.uuid).exists());
+
+        let err = ts
+            .cancel_upload(Request::new(upload_ref))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn info_requests_report_size_without_reading() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = test_server(dir.path());
+
+        let config = "{}\n";
+        let config_digest = sha256_tag_digest(config.as_bytes()).unwrap();
+        let uuid = Uuid::new_v4().to_string();
+        fs::write(ts.get_upload_path_for_blob(&uuid), config).unwrap();
+        ts.validate_and_save_blob(&config_digest, &uuid, None)
+            .await
+            .unwrap();
+
+        let blob_ref = BlobRef {
+            repo_name: "info/test".to_string(),
+            digest: config_digest.clone(),
+        };
+        let info = ts
+            .get_blob_info(Request::new(blob_ref))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(info.size, 3);
+        assert_eq!(info.digest, config_digest);
+
+        let manifest = format!(
+            r#"{{"schemaVersion":2,"mediaType":"application/vnd.oci.image.manifest.v1+json","config":{{"mediaType":"application/vnd.oci.image.config.v1+json","digest":"{}","size":3}},"layers":[]}}"#,
+            config_digest
+        );
+        let manifest_digest = sha256_tag_digest(manifest.as_bytes()).unwrap();
+        let uuid = Uuid::new_v4().to_string();
+        fs::write(ts.get_upload_path_for_blob(&uuid), &manifest).unwrap();
+        ts.validate_and_save_blob(&manifest_digest, &uuid, None)
+            .await
+            .unwrap();
+        ts.save_tag(&manifest_digest, "info/test", "latest")
+            .await
+            .unwrap();
+
+        for reference in &["latest", manifest_digest.as_str()] {
+            let manifest_ref = ManifestRef {
+                repo_name: "info/test".to_string(),
+                reference: reference.to_string(),
+            };
+            let info = ts
+                .get_manifest_info(Request::new(manifest_ref))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(info.size, manifest.len() as u64);
+            assert_eq!(info.digest, manifest_digest);
+            assert_eq!(
+                info.content_type,
+                "application/vnd.oci.image.manifest.v1+json"
+            );
+        }
+
+        let unknown = BlobRef {
+            repo_name: "info/test".to_string(),
+            digest: sha256_tag_digest("missing".as_bytes()).unwrap(),
+        };
+        let err = ts.get_blob_info(Request::new(unknown)).await.unwrap_err();
+        assert_eq!(err.code(), Code::NotFound);
+        let unknown = ManifestRef {
+            repo_name: "info/test".to_string(),
+            reference: "missing".to_string(),
+        };
+        let err = ts
+            .get_manifest_info(Request::new(unknown))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn delete_by_tag_archives_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = test_server(dir.path());
+        ts.save_tag("sha256:1", "untag/test", "old").await.unwrap();
+        ts.save_tag("sha256:1", "untag/test", "current")
+            .await
+            .unwrap();
+
+        let delete = |reference: &str| {
+            ts.delete_manifest(Request::new(ManifestRef {
+                repo_name: "untag/test".to_string(),
+                reference: reference.to_string(),
+            }))
+        };
+        delete("old").await.unwrap();
+        assert!(!dir.path().join(tag_key("untag/test", "old")).exists());
+        assert!(dir.path().join(tag_key("untag/test", "current")).exists());
+        let archived: Vec<_> = fs::read_dir(dir.path().join("deleted_tags/untag/test/old"))
+            .unwrap()
+            .collect();
+        assert_eq!(archived.len(), 1);
+        let history = fs::read_to_string(archived[0].as_ref().unwrap().path()).unwrap();
+        assert!(history.starts_with("sha256:1 "));
+
+        assert_eq!(delete("old").await.unwrap_err().code(), Code::NotFound);
+        assert_eq!(
+            delete("../current").await.unwrap_err().code(),
+            Code::InvalidArgument
+        );
+
+        // Deleting by digest removes the remaining tag, again keeping its history
+        delete("sha256:1").await.unwrap();
+        assert!(!dir.path().join(tag_key("untag/test", "current")).exists());
+        assert!(dir.path().join("deleted_tags/untag/test/current").is_dir());
+    }
+
+    #[tokio::test]
+    async fn artifact_types_can_be_restricted() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = test_server(dir.path())
+            .with_allowed_artifact_types(vec!["application/vnd.example.chart".to_string()]);
+
+        let config = "{}\n";
+        let config_digest = sha256_tag_digest(config.as_bytes()).unwrap();
+        let uuid = Uuid::new_v4().to_string();
+        fs::write(ts.get_upload_path_for_blob(&uuid), config).unwrap();
+        ts.validate_and_save_blob(&config_digest, &uuid, None)
+            .await
+            .unwrap();
+
+        let push = |tag: &str, manifest: String| {
+            ts.write_manifest_stream(tokio_stream::iter(vec![Ok(ManifestUploadChunk {
+                manifest: Some(ManifestRef {
+                    repo_name: "artifact/test".to_string(),
+                    reference: tag.to_string(),
+                }),
+                digest: String::new(),
+                data: manifest.into_bytes(),
+            })]))
+        };
+        let image = format!(
+            r#"{{"schemaVersion":2,"config":{{"mediaType":"application/vnd.oci.image.config.v1+json","digest":"{}","size":3}},"layers":[]}}"#,
+            config_digest
+        );
+        push("image", image).await.unwrap();
+
+        // The empty config is stored for artifacts that don't upload it
+        let artifact = |artifact_type: &str| {
+            format!(
+                r#"{{"schemaVersion":2,"artifactType":"{}","layers":[]}}"#,
+                artifact_type
+            )
+        };
+        push("chart", artifact("application/vnd.example.chart"))
+            .await
+            .unwrap();
+        let empty = dir.path().join(blob_key(empty_descriptor::DIGEST).unwrap());
+        assert_eq!(fs::read_to_string(empty).unwrap(), "{}");
+
+        let err = push("wasm", artifact("application/vnd.example.wasm"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        assert!(err.message().contains("application/vnd.example.wasm"));
+        assert!(!dir.path().join(tag_key("artifact/test", "wasm")).exists());
+    }
+
+    #[tokio::test]
+    async fn index_children_must_be_in_repo() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = test_server(dir.path());
+
+        let push = |repo_name: &str, tag: &str, manifest: &str| {
+            ts.write_manifest_stream(tokio_stream::iter(vec![Ok(ManifestUploadChunk {
+                manifest: Some(ManifestRef {
+                    repo_name: repo_name.to_string(),
+                    reference: tag.to_string(),
+                }),
+                digest: String::new(),
+                data: manifest.as_bytes().to_vec(),
+            })]))
+        };
+        let image = |arch: &str| {
+            format!(
+                r#"{{"schemaVersion":2,"artifactType":"application/vnd.example.{}","layers":[]}}"#,
+                arch
+            )
+        };
+        let index = |children: &[(&str, u64, &str)]| {
+            let entries: Vec<_> = children
+                .iter()
+                .map(|(digest, size, arch)| {
+                    format!(
+                        r#"{{"mediaType":"application/vnd.oci.image.manifest.v1+json","digest":"{}","size":{},"platform":{{"os":"linux","architecture":"{}"}}}}"#,
+                        digest, size, arch
+                    )
+                })
+                .collect();
+            format!(
+                r#"{{"schemaVersion":2,"mediaType":"application/vnd.oci.image.index.v1+json","manifests":[{}]}}"#,
+                entries.join(",")
+            )
+        };
+
+        let amd64 = image("amd64");
+        let amd64_digest = push("index/test", "amd64", &amd64)
+            .await
+            .unwrap()
+            .into_inner()
+            .digest;
+        // Pushed, but to another repository
+        let arm64 = image("arm64");
+        let arm64_digest = push("index/other", "arm64", &arm64)
+            .await
+            .unwrap()
+            .into_inner()
+            .digest;
+
+        let err = push(
+            "index/test",
+            "latest",
+            &index(&[
+                (&amd64_digest, amd64.len() as u64, "amd64"),
+                (&arm64_digest, arm64.len() as u64, "arm64"),
+            ]),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.code(), Code::FailedPrecondition);
+        let missing: serde_json::Value = serde_json::from_slice(err.details()).unwrap();
+        assert_eq!(
+            missing,
+            serde_json::json!([{"digest": arm64_digest, "platform": "linux/arm64"}])
+        );
+
+        let err = push(
+            "index/test",
+            "latest",
+            &index(&[(&amd64_digest, 1, "amd64")]),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        assert!(!dir.path().join(tag_key("index/test", "latest")).exists());
+
+        push(
+            "index/test",
+            "latest",
+            &index(&[(&amd64_digest, amd64.len() as u64, "amd64")]),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn build_cache_index_lists_blobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = test_server(dir.path());
+
+        let mut blobs = vec![];
+        for contents in &["cache config", "cached layer"] {
+            let digest = sha256_tag_digest(contents.as_bytes()).unwrap();
+            let uuid = Uuid::new_v4().to_string();
+            fs::write(ts.get_upload_path_for_blob(&uuid), contents).unwrap();
+            ts.validate_and_save_blob(&digest, &uuid, None)
+                .await
+                .unwrap();
+            blobs.push((digest, contents.len()));
+        }
+        let missing = sha256_tag_digest("missing".as_bytes()).unwrap();
+
+        // As BuildKit pushes with --cache-to type=registry
+        let index = |layer: &str, layer_size: usize| {
+            format!(
+                r#"{{"schemaVersion":2,"mediaType":"application/vnd.oci.image.index.v1+json","manifests":[{{"mediaType":"application/vnd.oci.image.layer.v1.tar+gzip","digest":"{}","size":{},"annotations":{{"buildkit/createdat":"2021-06-01T00:00:00Z"}}}},{{"mediaType":"application/vnd.buildkit.cacheconfig.v0","digest":"{}","size":{}}}]}}"#,
+                layer, layer_size, blobs[0].0, blobs[0].1
+            )
+        };
+        let push = |manifest: String| {
+            ts.write_manifest_stream(tokio_stream::iter(vec![Ok(ManifestUploadChunk {
+                manifest: Some(ManifestRef {
+                    repo_name: "cache/test".to_string(),
+                    reference: "buildcache".to_string(),
+                }),
+                digest: String::new(),
+                data: manifest.into_bytes(),
+            })]))
+        };
+
+        let err = push(index(&missing, 7)).await.unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        let err = push(index(&blobs[1].0, 1)).await.unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        assert!(!dir
+            .path()
+            .join(tag_key("cache/test", "buildcache"))
+            .exists());
+
+        push(index(&blobs[1].0, blobs[1].1)).await.unwrap();
+        assert!(dir
+            .path()
+            .join(tag_key("cache/test", "buildcache"))
+            .exists());
+    }
+
+    #[tokio::test]
+    async fn oversized_manifests_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = test_server(dir.path());
+
+        let padding = "x".repeat(MAX_MANIFEST_SIZE);
+        let manifest = format!(
+            r#"{{"schemaVersion":2,"artifactType":"application/vnd.example","annotations":{{"padding":"{}"}},"layers":[]}}"#,
+            padding
+        );
+        // Only the first chunk says which manifest it is
+        let mut chunks: Vec<_> = manifest
+            .as_bytes()
+            .chunks(CHUNK_SIZE)
+            .map(|data| ManifestUploadChunk {
+                manifest: None,
+                digest: String::new(),
+                data: data.to_vec(),
+            })
+            .collect();
+        chunks[0].manifest = Some(ManifestRef {
+            repo_name: "big/test".to_string(),
+            reference: "latest".to_string(),
+        });
+        let err = ts
+            .write_manifest_stream(tokio_stream::iter(chunks.into_iter().map(Ok)))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        assert!(!dir.path().join(tag_key("big/test", "latest")).exists());
+    }
+
+    #[tokio::test]
+    async fn sha512_blobs_and_manifests() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = test_server(dir.path());
+
+        let config = "{}\n";
+        let config_digest = sha512_tag_digest(config.as_bytes()).unwrap();
+        let uuid = Uuid::new_v4().to_string();
+        fs::write(ts.get_upload_path_for_blob(&uuid), config).unwrap();
+        ts.validate_and_save_blob(&config_digest, &uuid, None)
+            .await
+            .unwrap();
+        let config_key = blob_key(&config_digest).unwrap();
+        assert!(config_key.starts_with("blobs/sha512/"));
+        assert!(dir.path().join(config_key).exists());
+
+        let manifest = format!(
+            r#"{{"schemaVersion":2,"config":{{"mediaType":"application/vnd.oci.image.config.v1+json","digest":"{}","size":3}},"layers":[]}}"#,
+            config_digest
+        );
+        let push = |digest: &str| {
+            ts.write_manifest_stream(tokio_stream::iter(vec![Ok(ManifestUploadChunk {
+                manifest: Some(ManifestRef {
+                    repo_name: "sha512/test".to_string(),
+                    reference: "latest".to_string(),
+                }),
+                digest: digest.to_string(),
+                data: manifest.as_bytes().to_vec(),
+            })]))
+        };
+
+        // Manifests are sha256 unless the client asks otherwise
+        let vm = push("").await.unwrap().into_inner();
+        assert_eq!(vm.digest, sha256_tag_digest(manifest.as_bytes()).unwrap());
+
+        let manifest_digest = sha512_tag_digest(manifest.as_bytes()).unwrap();
+        let vm = push(&manifest_digest).await.unwrap().into_inner();
+        assert_eq!(vm.digest, manifest_digest);
+        assert!(dir
+            .path()
+            .join(blob_key(&manifest_digest).unwrap())
+            .exists());
+
+        let chunk = ts
+            .read_manifest(Request::new(ManifestRef {
+                repo_name: "sha512/test".to_string(),
+                reference: manifest_digest.clone(),
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .next()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(chunk.digest, manifest_digest);
+        assert_eq!(chunk.data, manifest.as_bytes());
+
+        let wrong = sha512_tag_digest("other".as_bytes()).unwrap();
+        let err = push(&wrong).await.unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+        assert!(err.message().contains(&wrong));
+    }
 }
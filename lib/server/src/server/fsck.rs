@@ -0,0 +1,332 @@
+use super::gc::digest_from_blob_key;
+use super::trow_server::FsckReport;
+use super::{blob_key, is_digest, TrowServer, BLOBS_DIR};
+use crate::digest::digest_alg;
+use crate::manifest::Manifest;
+use failure::Error;
+use std::collections::HashSet;
+use std::fmt;
+
+static QUARANTINE_DIR: &str = "quarantine";
+
+impl TrowServer {
+    /**
+     * Checks the catalog is consistent.
+     *
+     * Every blob is re-hashed, as read from the store rather than any local cache, and compared to
+     * its digest. Every tag is checked to point to an existing, parseable manifest whose config
+     * and layers (or child manifests for lists) exist. Files in scratch that don't belong to an
+     * active upload are reported.
+     *
+     * If repair is set, corrupt blobs are moved to quarantine/ next to blobs/. This takes the GC
+     * lock for writing, as it removes blobs from the catalog.
+     */
+    pub(crate) async fn run_fsck(&self, repair: bool) -> Result<FsckReport, Error> {
+        let _read_guard = if repair {
+            None
+        } else {
+            Some(self.gc_lock.read().await)
+        };
+        let _write_guard = if repair {
+            Some(self.gc_lock.write().await)
+        } else {
+            None
+        };
+
+        let mut report = FsckReport::default();
+        self.check_blobs(repair, &mut report).await?;
+        self.check_tags(&mut report).await?;
+        self.check_scratch(&mut report).await?;
+        Ok(report)
+    }
+
+    async fn check_blobs(&self, repair: bool, report: &mut FsckReport) -> Result<(), Error> {
+        for key in self.storage.list(BLOBS_DIR).await? {
+            let digest = match digest_from_blob_key(&key) {
+                Some(digest) if is_digest(&digest) => digest,
+                _ => {
+                    report
+                        .errors
+                        .push(format!("Unexpected entry {} in blobs", key));
+                    continue;
+                }
+            };
+            report.checked_blobs += 1;
+
+            match self.storage.digest(&key, digest_alg(&digest)).await {
+                Ok(calculated) if calculated == digest => continue,
+                Ok(calculated) => {
+                    warn!("Blob {} has content with digest {}", digest, calculated);
+                }
+                Err(e) => {
+                    report
+                        .errors
+                        .push(format!("Failed to read blob {}: {}", digest, e));
+                    continue;
+                }
+            }
+            report.corrupt_blobs.push(digest.clone());
+
+            if repair {
+                let quarantine_key = quarantine_key(&key);
+                match self.storage.rename(&key, &quarantine_key).await {
+                    Ok(_) => {
+                        info!("Quarantined corrupt blob {}", digest);
+                        report.quarantined_blobs.push(digest);
+                    }
+                    Err(e) => report
+                        .errors
+                        .push(format!("Failed to quarantine blob {}: {}", digest, e)),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn check_tags(&self, report: &mut FsckReport) -> Result<(), Error> {
+        let mut checked = HashSet::new();
+        for (tag, digest) in self.tagged_manifests().await? {
+            report.checked_tags += 1;
+            self.check_manifest(&tag, &digest, &mut checked, report)
+                .await;
+        }
+        Ok(())
+    }
+
+    /**
+     * Checks the manifest and everything it refers to exists. Errors are reported against the
+     * tag that led to the manifest; each manifest is only checked once.
+     */
+    async fn check_manifest(
+        &self,
+        tag: &str,
+        digest: &str,
+        checked: &mut HashSet<String>,
+        report: &mut FsckReport,
+    ) {
+        let mut pending = vec![digest.to_string()];
+        while let Some(digest) = pending.pop() {
+            if !checked.insert(digest.clone()) {
+                continue;
+            }
+
+            let exists = match blob_key(&digest) {
+                Ok(key) => self.storage.exists(&key).await,
+                Err(e) => Err(e),
+            };
+            match exists {
+                Ok(true) => (),
+                Ok(false) => {
+                    report
+                        .errors
+                        .push(format!("{}: manifest {} does not exist", tag, digest));
+                    continue;
+                }
+                Err(e) => {
+                    report.errors.push(format!(
+                        "{}: invalid manifest digest {}: {}",
+                        tag, digest, e
+                    ));
+                    continue;
+                }
+            }
+
+            let manifest = match self.read_manifest_blob(&digest).await {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    report.errors.push(format!(
+                        "{}: manifest {} could not be parsed: {}",
+                        tag, digest, e
+                    ));
+                    continue;
+                }
+            };
+
+            match manifest {
+                Manifest::List(_) => pending.extend(
+                    manifest
+                        .get_local_asset_digests()
+                        .into_iter()
+                        .map(|d| d.to_string()),
+                ),
+                Manifest::V2(_) => {
+                    for asset in manifest.get_local_asset_digests() {
+                        let exists = match blob_key(asset) {
+                            Ok(key) => self.storage.exists(&key).await.unwrap_or(false),
+                            Err(_) => false,
+                        };
+                        if !exists {
+                            report.errors.push(format!(
+                                "{}: manifest {} references missing blob {}",
+                                tag, digest, asset
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    async fn check_scratch(&self, report: &mut FsckReport) -> Result<(), Error> {
+        let active: HashSet<String> = self
+            .active_uploads
+            .read()
+            .unwrap()
+            .keys()
+            .map(|u| u.uuid.clone())
+            .collect();
+
+        let mut entries = tokio::fs::read_dir(&self.scratch_path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !active.contains(&name) {
+                report.orphaned_scratch_files.push(name);
+            }
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Key a corrupt blob is moved to, e.g. quarantine/sha256/<hash>
+ */
+fn quarantine_key(blob_key: &str) -> String {
+    let rel_key = blob_key
+        .trim_start_matches(BLOBS_DIR)
+        .trim_start_matches('/');
+    format!("{}/{}", QUARANTINE_DIR, rel_key)
+}
+
+impl FsckReport {
+    /// Orphaned scratch files don't count, as they may belong to writes in progress
+    pub fn has_errors(&self) -> bool {
+        !(self.corrupt_blobs.is_empty() && self.errors.is_empty())
+    }
+}
+
+impl fmt::Display for FsckReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "Checked {} blobs and {} tags",
+            self.checked_blobs, self.checked_tags
+        )?;
+        for digest in &self.corrupt_blobs {
+            let status = if self.quarantined_blobs.contains(digest) {
+                " (quarantined)"
+            } else {
+                ""
+            };
+            writeln!(f, "Corrupt blob {}{}", digest, status)?;
+        }
+        for error in &self.errors {
+            writeln!(f, "{}", error)?;
+        }
+        for file in &self.orphaned_scratch_files {
+            writeln!(f, "Orphaned scratch file {}", file)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{blob_key, test_server};
+    use super::quarantine_key;
+    use crate::check_data_dir;
+    use crate::digest::sha256_tag_digest;
+    use crate::storage::temp_file_name;
+    use std::collections::BTreeSet;
+    use std::fs;
+    use std::io::BufReader;
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn fsck_finds_and_quarantines_corrupt_blobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = test_server(dir.path());
+
+        let config = sha256_tag_digest(BufReader::new("config".as_bytes())).unwrap();
+        let config_key = blob_key(&config).unwrap();
+        ts.storage.write(&config_key, b"corrupted").await.unwrap();
+
+        let missing = sha256_tag_digest(BufReader::new("missing".as_bytes())).unwrap();
+        let manifest = format!(
+            r#"{{
+                "schemaVersion": 2,
+                "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                "config": {{
+                    "mediaType": "application/vnd.oci.image.config.v1+json",
+                    "size": 6,
+                    "digest": "{}"
+                }},
+                "layers": [{{
+                    "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip",
+                    "size": 7,
+                    "digest": "{}"
+                }}]
+            }}"#,
+            config, missing
+        );
+        let manifest_digest = sha256_tag_digest(BufReader::new(manifest.as_bytes())).unwrap();
+        ts.storage
+            .write(&blob_key(&manifest_digest).unwrap(), manifest.as_bytes())
+            .await
+            .unwrap();
+        ts.save_tag(&manifest_digest, "fsck/test", "latest")
+            .await
+            .unwrap();
+        ts.save_tag("sha256:gone", "fsck/test", "gone")
+            .await
+            .unwrap();
+        fs::write(ts.get_upload_path_for_blob("abandoned"), "").unwrap();
+
+        let report = ts.run_fsck(false).await.unwrap();
+        assert!(report.has_errors());
+        assert_eq!(report.checked_blobs, 2);
+        assert_eq!(report.checked_tags, 2);
+        assert_eq!(report.corrupt_blobs, vec![config.clone()]);
+        assert!(report.quarantined_blobs.is_empty());
+        assert_eq!(report.errors.len(), 2);
+        assert_eq!(report.orphaned_scratch_files, vec!["abandoned"]);
+
+        let report = ts.run_fsck(true).await.unwrap();
+        assert_eq!(report.quarantined_blobs, vec![config.clone()]);
+        assert!(!ts.storage.exists(&config_key).await.unwrap());
+        assert!(ts
+            .storage
+            .exists(&quarantine_key(&config_key))
+            .await
+            .unwrap());
+
+        // The config is now reported missing instead of corrupt
+        let report = ts.run_fsck(false).await.unwrap();
+        assert!(report.corrupt_blobs.is_empty());
+        assert_eq!(report.errors.len(), 3);
+    }
+
+    #[test]
+    fn fsck_without_repair_changes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = test_server(dir.path());
+        // As a running server could have them: a write in progress, an upload whose session is
+        // still to be saved and a session whose data is still to be written
+        fs::write(ts.scratch_path.join(temp_file_name()), "half written").unwrap();
+        fs::write(ts.get_upload_path_for_blob("new"), "upload").unwrap();
+        fs::write(ts.sessions_path.join("unsaved"), "{").unwrap();
+
+        let listing = |path: &Path| -> BTreeSet<_> {
+            fs::read_dir(path)
+                .unwrap()
+                .map(|e| e.unwrap().file_name())
+                .collect()
+        };
+        let scratch = listing(&ts.scratch_path);
+        let sessions = listing(&ts.sessions_path);
+
+        let report = check_data_dir(dir.path().to_str().unwrap(), None, false).unwrap();
+        assert_eq!(report.orphaned_scratch_files.len(), 2);
+        assert_eq!(listing(&ts.scratch_path), scratch);
+        assert_eq!(listing(&ts.sessions_path), sessions);
+    }
+}
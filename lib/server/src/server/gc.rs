@@ -0,0 +1,399 @@
+use super::trow_server::GarbageCollectionReport;
+use super::{
+    blob_key, get_digest_from_tag_file, TrowServer, BLOBS_DIR, DELETED_TAGS_DIR, MANIFESTS_DIR,
+};
+use crate::manifest::{FromJson, Manifest};
+use failure::Error;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime};
+
+/*
+ * Unreferenced blobs younger than this are left alone, as are those a client was told are
+ * stored within this time.
+ *
+ * Clients upload layers before the manifest that references them, so a freshly written blob
+ * with no tag pointing at it is most likely part of a push that is still in progress. Layers the
+ * registry already has aren't uploaded again, only checked with a HEAD or mounted from another
+ * repository, so however old they are they may be about to be referenced too.
+ */
+pub const GC_GRACE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+impl TrowServer {
+    /**
+     * Mark and sweep garbage collection of blobs.
+     *
+     * Every tag file is read to find the current manifest for the tag. The manifest, its config
+     * and layers are marked as referenced, as are the children of manifest lists (and their
+     * config and layers). Any blob under blobs/ that is not marked, was last written before the
+     * grace period and wasn't found by a client since is removed, or only reported if dry_run is
+     * set. Finding blobs is only recorded in memory, so a push in progress across a restart can
+     * still lose a layer it skipped.
+     *
+     * Holds the GC lock for writing, so no manifest can be verified and tagged between the mark
+     * and sweep phases.
+     */
+    pub(crate) async fn run_gc(
+        &self,
+        dry_run: bool,
+        grace_period: Duration,
+    ) -> Result<GarbageCollectionReport, Error> {
+        let _guard = self.gc_lock.write().await;
+
+        let referenced = self.referenced_blobs().await?;
+        let found = {
+            let mut found = self.found_blobs.lock().unwrap();
+            found.retain(|_, when| is_within(*when, grace_period));
+            found.clone()
+        };
+        let mut report = GarbageCollectionReport {
+            dry_run,
+            referenced_blobs: referenced.len() as u32,
+            ..Default::default()
+        };
+
+        for key in self.storage.list(BLOBS_DIR).await? {
+            let digest = match digest_from_blob_key(&key) {
+                Some(digest) => digest,
+                None => continue,
+            };
+            if referenced.contains(&digest) {
+                continue;
+            }
+
+            let metadata = self.storage.metadata(&key).await?;
+            let is_recent =
+                is_within(metadata.modified, grace_period) || found.contains_key(&digest);
+            if is_recent {
+                debug!("Skipping recently written or found blob {}", digest);
+                report.skipped_recent_blobs += 1;
+                continue;
+            }
+
+            if !dry_run {
+                if let Err(e) = self.storage.delete(&key).await {
+                    error!("Failed to remove unreferenced blob {} {:?}", digest, e);
+                    continue;
+                }
+                info!("Removed unreferenced blob {}", digest);
+            }
+            report.reclaimed_bytes += metadata.len;
+            report.removed_blobs.push(digest);
+        }
+
+        Ok(report)
+    }
+
+    /**
+     * Returns what refers to the given blob: "repo:tag" for every tag whose manifest refers to
+     * it, either directly or through a manifest list, and the digest of every other stored
+     * manifest that refers to it. A tagged manifest counts as referring to itself.
+     *
+     * Untagged manifests, e.g. the children of an index, referrers pushed by digest or manifests
+     * a tag used to point to, are found through the tag files: every pushed manifest is saved
+     * under a tag or its digest, and stays in that tag's history even once the tag is removed.
+     */
+    pub(crate) async fn references_to(&self, digest: &str) -> Result<Vec<String>, Error> {
+        let mut checked: HashMap<String, bool> = HashMap::new();
+        let mut references = Vec::new();
+
+        for (tag, manifest_digest) in self.tagged_manifests().await? {
+            let refers = match checked.get(&manifest_digest) {
+                Some(refers) => *refers,
+                None => {
+                    let refers = self.reachable_from(&manifest_digest).await.contains(digest);
+                    checked.insert(manifest_digest, refers);
+                    refers
+                }
+            };
+            if refers {
+                references.push(tag);
+            }
+        }
+
+        for manifest_digest in self.pushed_manifests().await? {
+            if manifest_digest == digest || checked.contains_key(&manifest_digest) {
+                continue;
+            }
+            let exists = match blob_key(&manifest_digest) {
+                Ok(key) => self.storage.exists(&key).await?,
+                Err(_) => false,
+            };
+            let refers = exists && self.reachable_from(&manifest_digest).await.contains(digest);
+            if refers {
+                references.push(manifest_digest.clone());
+            }
+            checked.insert(manifest_digest, refers);
+        }
+
+        Ok(references)
+    }
+
+    /**
+     * Returns the digest of every manifest in the history of any tag, current or removed.
+     */
+    async fn pushed_manifests(&self) -> Result<HashSet<String>, Error> {
+        let mut digests = HashSet::new();
+        for dir in &[MANIFESTS_DIR, DELETED_TAGS_DIR] {
+            for key in self.storage.list(dir).await? {
+                match self.storage.read(&key).await {
+                    Ok(contents) => digests.extend(
+                        String::from_utf8_lossy(&contents)
+                            .lines()
+                            .filter_map(|line| line.split(' ').next())
+                            .filter(|digest| !digest.is_empty())
+                            .map(str::to_string),
+                    ),
+                    Err(e) => warn!("Failed to read tag file {:?} {:?}", key, e),
+                }
+            }
+        }
+        Ok(digests)
+    }
+
+    /**
+     * Returns the digests of all blobs reachable from a tag in any repository.
+     */
+    async fn referenced_blobs(&self) -> Result<HashSet<String>, Error> {
+        let mut referenced = HashSet::new();
+        for (_, manifest_digest) in self.tagged_manifests().await? {
+            if !referenced.contains(&manifest_digest) {
+                referenced.extend(self.reachable_from(&manifest_digest).await);
+            }
+        }
+
+        Ok(referenced)
+    }
+
+    /**
+     * Returns "repo:tag" and the current manifest digest of every tag in the catalog.
+     */
+    pub(super) async fn tagged_manifests(&self) -> Result<Vec<(String, String)>, Error> {
+        let mut tags = Vec::new();
+        for key in self.storage.list(MANIFESTS_DIR).await? {
+            let digest = match self.storage.read(&key).await {
+                Ok(contents) => get_digest_from_tag_file(&contents),
+                Err(e) => {
+                    warn!("Failed to read tag file {:?} {:?}", key, e);
+                    continue;
+                }
+            };
+            let rel_key = key
+                .trim_start_matches(MANIFESTS_DIR)
+                .trim_start_matches('/');
+            if let Some(sep) = rel_key.rfind('/') {
+                let (repo, tag) = (&rel_key[..sep], &rel_key[sep + 1..]);
+                tags.push((format!("{}:{}", repo, tag), digest));
+            }
+        }
+
+        Ok(tags)
+    }
+
+    /**
+     * Returns the given manifest digest and the digests of all blobs it refers to, walking into
+     * the children of manifest lists.
+     *
+     * Manifests that can't be read or parsed are logged and skipped; they still count as
+     * reachable themselves.
+     */
+    async fn reachable_from(&self, manifest_digest: &str) -> HashSet<String> {
+        let mut reachable = HashSet::new();
+        let mut pending = vec![manifest_digest.to_string()];
+
+        while let Some(digest) = pending.pop() {
+            if !reachable.insert(digest.clone()) {
+                continue;
+            }
+
+            match self.read_manifest_blob(&digest).await {
+                // Children of a list are manifests themselves and need to be walked
+                Ok(m @ Manifest::List(_)) => pending.extend(
+                    m.get_local_asset_digests()
+                        .into_iter()
+                        .map(|d| d.to_string()),
+                ),
+                Ok(m) => reachable.extend(
+                    m.get_local_asset_digests()
+                        .into_iter()
+                        .map(|d| d.to_string()),
+                ),
+                Err(e) => warn!("Failed to read manifest {} {:?}", digest, e),
+            }
+        }
+
+        reachable
+    }
+
+    pub(super) async fn read_manifest_blob(&self, digest: &str) -> Result<Manifest, Error> {
+        let bytes = self.storage.read(&blob_key(digest)?).await?;
+        let json: serde_json::Value = serde_json::from_slice(&bytes)?;
+        Manifest::from_json(&json)
+    }
+}
+
+/*
+ * Whether the time is less than the period ago. Times in the future count as within it.
+ */
+fn is_within(time: SystemTime, period: Duration) -> bool {
+    time.elapsed().map(|age| age < period).unwrap_or(true)
+}
+
+/**
+ * Turns a key such as blobs/sha256/<hash> back into a digest, or None for anything else.
+ */
+pub(super) fn digest_from_blob_key(key: &str) -> Option<String> {
+    let mut parts = key
+        .strip_prefix(BLOBS_DIR)?
+        .trim_start_matches('/')
+        .split('/');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(alg), Some(val), None) => Some(format!("{}:{}", alg, val)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::trow_server::registry_server::Registry;
+    use super::super::trow_server::BlobRef;
+    use super::super::{blob_key, test_server};
+    use super::{TrowServer, GC_GRACE_PERIOD};
+    use crate::digest::sha256_tag_digest;
+    use filetime::FileTime;
+    use std::io::BufReader;
+    use std::time::{Duration, SystemTime};
+    use tonic::Request;
+
+    async fn write_blob(ts: &TrowServer, content: &[u8]) -> String {
+        let digest = sha256_tag_digest(BufReader::new(content)).unwrap();
+        let key = blob_key(&digest).unwrap();
+        ts.storage.write(&key, content).await.unwrap();
+        digest
+    }
+
+    async fn blob_exists(ts: &TrowServer, digest: &str) -> bool {
+        ts.storage.exists(&blob_key(digest).unwrap()).await.unwrap()
+    }
+
+    fn image_manifest(config: &str, layer: &str) -> String {
+        format!(
+            r#"{{
+                "schemaVersion": 2,
+                "mediaType": "application/vnd.docker.distribution.manifest.v2+json",
+                "config": {{
+                    "mediaType": "application/vnd.docker.container.image.v1+json",
+                    "size": 1,
+                    "digest": "{}"
+                }},
+                "layers": [{{
+                    "mediaType": "application/vnd.docker.image.rootfs.diff.tar.gzip",
+                    "size": 1,
+                    "digest": "{}"
+                }}]
+            }}"#,
+            config, layer
+        )
+    }
+
+    #[tokio::test]
+    async fn gc_removes_only_unreferenced_blobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = test_server(dir.path());
+
+        let config = write_blob(&ts, b"config").await;
+        let layer = write_blob(&ts, b"layer").await;
+        let orphan = write_blob(&ts, b"orphan").await;
+        let manifest = write_blob(&ts, image_manifest(&config, &layer).as_bytes()).await;
+
+        let list = format!(
+            r#"{{
+                "schemaVersion": 2,
+                "mediaType": "application/vnd.docker.distribution.manifest.list.v2+json",
+                "manifests": [{{
+                    "mediaType": "application/vnd.docker.distribution.manifest.v2+json",
+                    "size": 1,
+                    "digest": "{}",
+                    "platform": {{ "architecture": "amd64", "os": "linux" }}
+                }}]
+            }}"#,
+            manifest
+        );
+        let list = write_blob(&ts, list.as_bytes()).await;
+        ts.save_tag(&list, "gc/test", "latest").await.unwrap();
+
+        let report = ts.run_gc(true, Duration::from_secs(0)).await.unwrap();
+        assert_eq!(report.referenced_blobs, 4);
+        assert_eq!(report.removed_blobs, vec![orphan.clone()]);
+        assert!(blob_exists(&ts, &orphan).await);
+
+        // Nothing is old enough to be collected
+        let report = ts.run_gc(false, Duration::from_secs(3600)).await.unwrap();
+        assert!(report.removed_blobs.is_empty());
+        assert_eq!(report.skipped_recent_blobs, 1);
+
+        let report = ts.run_gc(false, Duration::from_secs(0)).await.unwrap();
+        assert_eq!(report.removed_blobs, vec![orphan.clone()]);
+        assert!(!blob_exists(&ts, &orphan).await);
+        for digest in &[&config, &layer, &manifest, &list] {
+            assert!(blob_exists(&ts, digest).await);
+        }
+
+        assert_eq!(
+            ts.references_to(&layer).await.unwrap(),
+            vec!["gc/test:latest"]
+        );
+        assert!(ts.references_to(&orphan).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn untagged_manifests_hold_on_to_blobs() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = test_server(dir.path());
+
+        let config = write_blob(&ts, b"config").await;
+        let layer = write_blob(&ts, b"layer").await;
+        let manifest = write_blob(&ts, image_manifest(&config, &layer).as_bytes()).await;
+        let newer = write_blob(&ts, image_manifest(&config, &config).as_bytes()).await;
+
+        // The only tag has moved on from the manifest with the layer
+        ts.save_tag(&manifest, "gc/test", "latest").await.unwrap();
+        ts.save_tag(&newer, "gc/test", "latest").await.unwrap();
+        assert_eq!(
+            ts.references_to(&layer).await.unwrap(),
+            vec![manifest.clone()]
+        );
+        // A manifest doesn't hold on to itself unless tagged
+        assert!(ts.references_to(&manifest).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn gc_spares_blobs_found_by_clients() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = test_server(dir.path());
+
+        // Layers pushed long ago, which a client may reuse rather than upload again
+        let found = write_blob(&ts, b"found").await;
+        let unused = write_blob(&ts, b"unused").await;
+        let old = FileTime::from_system_time(SystemTime::now() - 2 * GC_GRACE_PERIOD);
+        for digest in &[&found, &unused] {
+            let path = dir.path().join(blob_key(digest).unwrap());
+            filetime::set_file_mtime(path, old).unwrap();
+        }
+
+        // As on a HEAD or mount before the manifest is pushed
+        let req = BlobRef {
+            repo_name: "gc/test".to_string(),
+            digest: found.clone(),
+        };
+        ts.get_blob_info(Request::new(req)).await.unwrap();
+
+        let report = ts.run_gc(false, GC_GRACE_PERIOD).await.unwrap();
+        assert_eq!(report.removed_blobs, vec![unused.clone()]);
+        assert_eq!(report.skipped_recent_blobs, 1);
+        assert!(blob_exists(&ts, &found).await);
+
+        // Once the push has had time to finish, the blob is collected
+        let report = ts.run_gc(false, Duration::from_secs(0)).await.unwrap();
+        assert_eq!(report.removed_blobs, vec![found.clone()]);
+    }
+}
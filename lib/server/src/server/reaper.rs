@@ -0,0 +1,200 @@
+use super::{remove_scratch_file, TrowServer, Upload, UploadSession};
+use crate::metrics;
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
+
+/*
+ * Uploads with no activity for this long are considered abandoned, e.g. by an interrupted push.
+ */
+pub const DEFAULT_UPLOAD_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+// Upper bound on how often the reaper runs
+const REAPER_INTERVAL: Duration = Duration::from_secs(60);
+
+fn idle_time(last_active: SystemTime, now: SystemTime) -> Duration {
+    // A time in the future counts as just active
+    now.duration_since(last_active).unwrap_or_default()
+}
+
+impl TrowServer {
+    /**
+     * Removes upload sessions that have been idle for longer than the timeout, along with their
+     * data in scratch.
+     *
+     * Chunks are appended to the scratch file as they are streamed in, before the session is
+     * updated, so an upload counts as active if either the session was used or its file was
+     * written to. Files in scratch that don't belong to a session (e.g. manifests that were never
+     * verified) are removed once they are as old as the timeout.
+     *
+     * Returns the number of sessions removed.
+     */
+    pub(crate) async fn reap_uploads(&self, idle_timeout: Duration) -> usize {
+        let now = SystemTime::now();
+        // The lock can't be held while checking the files, so sessions are rechecked on removal
+        let sessions: Vec<(Upload, UploadSession)> = self
+            .active_uploads
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(upload, session)| (upload.clone(), session.clone()))
+            .collect();
+
+        let mut reaped = 0;
+        for (upload, session) in sessions {
+            let last_activity = self.last_activity(&upload, &session).await;
+            if idle_time(last_activity, now) < idle_timeout {
+                continue;
+            }
+
+            let removed = {
+                let mut sessions = self.active_uploads.write().unwrap();
+                match sessions.get(&upload) {
+                    Some(current) if current.last_active == session.last_active => {
+                        sessions.remove(&upload)
+                    }
+                    _ => None,
+                }
+            };
+            if removed.is_none() {
+                continue;
+            }
+            info!(
+                "Removing upload {} to {} started {}s ago, idle for {}s",
+                upload.uuid,
+                upload.repo_name,
+                idle_time(session.started, now).as_secs(),
+                idle_time(last_activity, now).as_secs()
+            );
+            remove_scratch_file(&self.get_upload_path_for_blob(&upload.uuid)).await;
+            self.remove_persisted_session(&upload.uuid).await;
+            metrics::REAPED_UPLOADS.inc();
+            reaped += 1;
+        }
+
+        let active: HashSet<String> = self
+            .active_uploads
+            .read()
+            .unwrap()
+            .keys()
+            .map(|u| u.uuid.clone())
+            .collect();
+        let mut entries = match tokio::fs::read_dir(&self.scratch_path).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("Failed to read scratch directory {:?}", e);
+                return reaped;
+            }
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if active.contains(&name) {
+                continue;
+            }
+            let modified = entry.metadata().await.and_then(|m| m.modified());
+            if let Ok(modified) = modified {
+                if idle_time(modified, now) >= idle_timeout {
+                    info!("Removing abandoned scratch file {}", name);
+                    remove_scratch_file(&entry.path()).await;
+                }
+            }
+        }
+
+        reaped
+    }
+
+    async fn last_activity(&self, upload: &Upload, session: &UploadSession) -> SystemTime {
+        tokio::fs::metadata(self.get_upload_path_for_blob(&upload.uuid))
+            .await
+            .and_then(|m| m.modified())
+            .map(|written| written.max(session.last_active))
+            .unwrap_or(session.last_active)
+    }
+
+    /**
+     * Runs the reaper until the runtime shuts down. It runs at least as often as the timeout, so
+     * uploads are removed no later than twice the timeout after their last activity.
+     */
+    pub async fn reap_uploads_periodically(self, idle_timeout: Duration) {
+        let period = idle_timeout
+            .min(REAPER_INTERVAL)
+            .max(Duration::from_secs(1));
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            let reaped = self.reap_uploads(idle_timeout).await;
+            if reaped > 0 {
+                info!("Removed {} abandoned uploads", reaped);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::{test_server, Upload, UploadSession};
+    use super::TrowServer;
+    use filetime::FileTime;
+    use std::fs;
+    use std::path::Path;
+    use std::time::{Duration, SystemTime};
+    use uuid::Uuid;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    fn add_session(ts: &TrowServer, idle: Duration) -> Upload {
+        let upload = Upload {
+            repo_name: "reaper/test".to_string(),
+            uuid: Uuid::new_v4().to_string(),
+        };
+        let last_active = SystemTime::now() - idle;
+        let session = UploadSession {
+            started: last_active,
+            last_active,
+            bytes_received: 0,
+            hasher: None,
+        };
+        ts.active_uploads
+            .write()
+            .unwrap()
+            .insert(upload.clone(), session);
+        upload
+    }
+
+    fn write_file(path: &Path, age: Duration) {
+        fs::write(path, "partial").unwrap();
+        let mtime = FileTime::from_system_time(SystemTime::now() - age);
+        filetime::set_file_mtime(path, mtime).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reaper_removes_idle_uploads() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = test_server(dir.path());
+
+        let active = add_session(&ts, Duration::from_secs(0));
+        let abandoned = add_session(&ts, 2 * HOUR);
+        write_file(&ts.get_upload_path_for_blob(&abandoned.uuid), 2 * HOUR);
+        // Writes to the file count as activity
+        let writing = add_session(&ts, 2 * HOUR);
+        write_file(
+            &ts.get_upload_path_for_blob(&writing.uuid),
+            Duration::from_secs(0),
+        );
+        let old_manifest = ts.get_upload_path_for_blob(&Uuid::new_v4().to_string());
+        write_file(&old_manifest, 2 * HOUR);
+        let new_manifest = ts.get_upload_path_for_blob(&Uuid::new_v4().to_string());
+        write_file(&new_manifest, Duration::from_secs(0));
+
+        assert_eq!(ts.reap_uploads(HOUR).await, 1);
+        {
+            let sessions = ts.active_uploads.read().unwrap();
+            assert!(sessions.contains_key(&active));
+            assert!(!sessions.contains_key(&abandoned));
+            assert!(sessions.contains_key(&writing));
+        }
+        assert!(!ts.get_upload_path_for_blob(&abandoned.uuid).exists());
+        assert!(ts.get_upload_path_for_blob(&writing.uuid).exists());
+        assert!(!old_manifest.exists());
+        assert!(new_manifest.exists());
+    }
+}
@@ -0,0 +1,255 @@
+use super::trow_server::Referrer;
+use super::{blob_key, TrowServer, SUPPORTED_DIGESTS};
+use crate::manifest::{FromJson, Manifest, Object};
+use crate::storage::is_not_found;
+use failure::Error;
+
+/*
+ * The referrers index records which manifests refer to another through their subject field, e.g.
+ * signatures and SBOMs attached to an image. Each referrer has an entry holding its descriptor at
+ * referrers/<repo>/<subject alg>/<subject hash>/<alg>/<hash>.
+ *
+ * Entries are added when a manifest with a subject is pushed and removed once no tag in the
+ * repository points to it. The subject doesn't have to exist, as clients may push referrers first.
+ */
+static REFERRERS_DIR: &str = "referrers";
+
+/**
+ * Turns a digest into a relative path, e.g. sha256/<hash>. Anything but an alg we support and a
+ * hex hash is rejected, so the digest can't be used to escape the index.
+ */
+pub(super) fn digest_path(digest: &str) -> Result<String, Error> {
+    let mut parts = digest.splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some(alg), Some(hash))
+            if SUPPORTED_DIGESTS.contains(&alg)
+                && !hash.is_empty()
+                && hash.chars().all(|c| c.is_ascii_hexdigit()) =>
+        {
+            Ok(format!("{}/{}", alg, hash))
+        }
+        _ => Err(format_err!("Invalid digest {}", digest)),
+    }
+}
+
+fn referrers_key(repo_name: &str, subject: &str) -> Result<String, Error> {
+    Ok(format!(
+        "{}/{}/{}",
+        REFERRERS_DIR,
+        repo_name,
+        digest_path(subject)?
+    ))
+}
+
+fn referrer_key(repo_name: &str, subject: &str, digest: &str) -> Result<String, Error> {
+    Ok(format!(
+        "{}/{}",
+        referrers_key(repo_name, subject)?,
+        digest_path(digest)?
+    ))
+}
+
+impl From<Object> for Referrer {
+    fn from(descriptor: Object) -> Self {
+        Referrer {
+            media_type: descriptor.media_type,
+            digest: descriptor.digest,
+            size: descriptor.size.unwrap_or(0),
+            artifact_type: descriptor.artifact_type.unwrap_or_default(),
+            annotations: descriptor.annotations.unwrap_or_default(),
+        }
+    }
+}
+
+impl TrowServer {
+    /**
+     * Reads the stored manifest and returns the digest of its subject, along with the descriptor
+     * it is listed under in the index. None if the manifest has no subject.
+     */
+    async fn get_referrer(&self, digest: &str) -> Result<Option<(String, Object)>, Error> {
+        let bytes = self.storage.read(&blob_key(digest)?).await?;
+        let manifest = Manifest::from_json(&serde_json::from_slice(&bytes)?)?;
+        Ok(manifest.subject().map(|subject| {
+            let descriptor = Object {
+                media_type: manifest.get_media_type(),
+                size: Some(bytes.len() as u64),
+                digest: digest.to_string(),
+                artifact_type: manifest.artifact_type().map(str::to_string),
+                annotations: manifest.annotations().cloned(),
+            };
+            (subject.digest.clone(), descriptor)
+        }))
+    }
+
+    /**
+     * Adds the manifest to the index of its subject's referrers, if it has a subject.
+     */
+    pub(super) async fn index_referrer(&self, repo_name: &str, digest: &str) -> Result<(), Error> {
+        if let Some((subject, descriptor)) = self.get_referrer(digest).await? {
+            let key = referrer_key(repo_name, &subject, digest)?;
+            self.storage
+                .write(&key, &serde_json::to_vec(&descriptor)?)
+                .await?;
+            debug!(
+                "Indexed {} as referrer of {} in {}",
+                digest, subject, repo_name
+            );
+        }
+        Ok(())
+    }
+
+    /**
+     * Removes the manifest from the referrers index, unless a tag in the repository still points
+     * to it.
+     */
+    pub(super) async fn unindex_referrer(
+        &self,
+        repo_name: &str,
+        digest: &str,
+    ) -> Result<(), Error> {
+        if self
+            .verify_manifest_digest_in_repo(repo_name, digest)
+            .await?
+        {
+            return Ok(());
+        }
+        let subject = match self.get_referrer(digest).await {
+            Ok(Some((subject, _))) => subject,
+            Ok(None) => return Ok(()),
+            Err(e) if is_not_found(&e) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        match self
+            .storage
+            .delete(&referrer_key(repo_name, &subject, digest)?)
+            .await
+        {
+            Err(e) if !is_not_found(&e) => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /**
+     * Descriptors of the manifests in the repository that refer to the subject, sorted by digest.
+     * If artifact_type isn't empty, only referrers of that type are returned.
+     */
+    pub(super) async fn get_referrers(
+        &self,
+        repo_name: &str,
+        subject: &str,
+        artifact_type: &str,
+    ) -> Result<Vec<Object>, Error> {
+        let prefix = format!("{}/", referrers_key(repo_name, subject)?);
+        let mut referrers = Vec::new();
+        for key in self.storage.list(&prefix).await? {
+            // Nested repositories, e.g. <repo>/<name>, are under the same prefix
+            match key.strip_prefix(&prefix) {
+                Some(rel) if rel.matches('/').count() == 1 => (),
+                _ => continue,
+            }
+            let descriptor: Object = match self.storage.read(&key).await {
+                Ok(bytes) => serde_json::from_slice(&bytes)?,
+                // Removed since it was listed
+                Err(e) if is_not_found(&e) => continue,
+                Err(e) => return Err(e),
+            };
+            if artifact_type.is_empty()
+                || descriptor.artifact_type.as_deref() == Some(artifact_type)
+            {
+                referrers.push(descriptor);
+            }
+        }
+        referrers.sort_by(|a, b| a.digest.cmp(&b.digest));
+        Ok(referrers)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test_server;
+    use super::super::trow_server::registry_server::Registry;
+    use super::super::trow_server::ManifestRef;
+    use super::TrowServer;
+    use crate::digest::sha256_tag_digest;
+    use std::fs;
+    use tonic::Request;
+    use uuid::Uuid;
+
+    async fn push(ts: &TrowServer, repo_name: &str, tag: &str, manifest: &str) -> String {
+        let digest = sha256_tag_digest(manifest.as_bytes()).unwrap();
+        let uuid = Uuid::new_v4().to_string();
+        fs::write(ts.get_upload_path_for_blob(&uuid), manifest).unwrap();
+        ts.validate_and_save_blob(&digest, &uuid, None)
+            .await
+            .unwrap();
+        ts.save_tag(&digest, repo_name, tag).await.unwrap();
+        ts.index_referrer(repo_name, &digest).await.unwrap();
+        digest
+    }
+
+    fn artifact(artifact_type: &str, config: &str, subject: &str) -> String {
+        format!(
+            r#"{{"schemaVersion":2,"mediaType":"application/vnd.oci.image.manifest.v1+json",
+                "artifactType":"{}","config":{{"mediaType":"application/vnd.oci.empty.v1+json",
+                "digest":"{}","size":2}},"layers":[],"subject":{{
+                "mediaType":"application/vnd.oci.image.manifest.v1+json","digest":"{}","size":1}},
+                "annotations":{{"org.example":"{}"}}}}"#,
+            artifact_type, config, subject, artifact_type
+        )
+    }
+
+    #[tokio::test]
+    async fn referrers_follow_pushes_and_deletes() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = test_server(dir.path());
+
+        let config = sha256_tag_digest("{}".as_bytes()).unwrap();
+        let subject = sha256_tag_digest("image".as_bytes()).unwrap();
+        let sig = push(&ts, "ref/test", "sig", &artifact("sig", &config, &subject)).await;
+        let sbom = artifact("sbom", &config, &subject);
+        let sbom = push(&ts, "ref/test", "sbom", &sbom).await;
+        // Referrers in other repositories, including nested ones, aren't listed
+        push(
+            &ts,
+            "ref/test/nested",
+            "sig",
+            &artifact("x", &config, &subject),
+        )
+        .await;
+
+        let referrers = ts.get_referrers("ref/test", &subject, "").await.unwrap();
+        let mut expected = vec![sig.clone(), sbom.clone()];
+        expected.sort();
+        let digests: Vec<_> = referrers.iter().map(|r| r.digest.clone()).collect();
+        assert_eq!(digests, expected);
+
+        let sigs = ts.get_referrers("ref/test", &subject, "sig").await.unwrap();
+        assert_eq!(sigs.len(), 1);
+        assert_eq!(sigs[0].digest, sig);
+        assert_eq!(sigs[0].artifact_type.as_deref(), Some("sig"));
+        assert_eq!(sigs[0].annotations.as_ref().unwrap()["org.example"], "sig");
+
+        ts.delete_manifest(Request::new(ManifestRef {
+            repo_name: "ref/test".to_string(),
+            reference: "sig".to_string(),
+        }))
+        .await
+        .unwrap();
+        ts.delete_manifest(Request::new(ManifestRef {
+            repo_name: "ref/test".to_string(),
+            reference: sbom,
+        }))
+        .await
+        .unwrap();
+        assert!(ts
+            .get_referrers("ref/test", &subject, "")
+            .await
+            .unwrap()
+            .is_empty());
+
+        assert!(ts
+            .get_referrers("ref/test", "sha256:../..", "")
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use super::{TrowServer, Upload, UploadSession};
+use crate::storage::{blocking, write_atomically};
+use failure::Error;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::time::SystemTime;
+
+pub(super) static SESSIONS_DIR: &str = "uploads";
+
+/*
+ * What is stored in uploads/<uuid> for each upload in progress, so uploads can be resumed after
+ * a restart. The data itself stays in scratch/<uuid>.
+ */
+#[derive(Serialize, Deserialize, Debug)]
+struct SessionRecord {
+    repo_name: String,
+    uuid: String,
+    started: SystemTime,
+    last_active: SystemTime,
+    bytes_received: u64,
+}
+
+/**
+ * Reads the sessions saved in the sessions directory.
+ *
+ * The number of bytes received is taken from the scratch file, as the client may have written more
+ * since the session was saved. The data is hashed again when the client carries on with the upload.
+ * Sessions that can't be read, or whose data has gone, are skipped, and removed if prune is set.
+ */
+pub(super) fn load_sessions(
+    sessions_path: &Path,
+    scratch_path: &Path,
+    prune: bool,
+) -> Result<HashMap<Upload, UploadSession>, Error> {
+    let mut sessions = HashMap::new();
+    let remove = |path: &Path| if prune { fs::remove_file(path) } else { Ok(()) };
+    for entry in fs::read_dir(sessions_path)? {
+        let path = entry?.path();
+        let record = fs::read(&path)
+            .map_err(Error::from)
+            .and_then(|bytes| Ok(serde_json::from_slice::<SessionRecord>(&bytes)?));
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                warn!("Unreadable upload session {:?} {:?}", path, e);
+                remove(&path)?;
+                continue;
+            }
+        };
+
+        let bytes_received = match fs::metadata(scratch_path.join(&record.uuid)) {
+            Ok(metadata) => metadata.len(),
+            Err(_) if record.bytes_received == 0 => 0,
+            Err(e) => {
+                warn!("Upload session {} has no data {:?}", record.uuid, e);
+                remove(&path)?;
+                continue;
+            }
+        };
+
+        debug!("Resuming upload {} to {}", record.uuid, record.repo_name);
+        sessions.insert(
+            Upload {
+                repo_name: record.repo_name,
+                uuid: record.uuid,
+            },
+            UploadSession {
+                started: record.started,
+                last_active: record.last_active,
+                bytes_received,
+                hasher: None,
+            },
+        );
+    }
+    Ok(sessions)
+}
+
+impl TrowServer {
+    /**
+     * Saves the session so it can be reloaded on startup. Failures are only logged; the upload
+     * can carry on, it just won't survive a restart.
+     */
+    pub(super) async fn persist_session(&self, upload: &Upload, session: &UploadSession) {
+        let record = SessionRecord {
+            repo_name: upload.repo_name.clone(),
+            uuid: upload.uuid.clone(),
+            started: session.started,
+            last_active: session.last_active,
+            bytes_received: session.bytes_received,
+        };
+        let temp_dir = self.scratch_path.clone();
+        let dest = self.sessions_path.join(&upload.uuid);
+        let res = blocking(move || {
+            let json = serde_json::to_vec(&record)?;
+            write_atomically(&temp_dir, &dest, |f| f.write_all(&json))
+        })
+        .await;
+        if let Err(e) = res {
+            error!("Failed to save upload session {:?} {:?}", upload, e);
+        }
+    }
+
+    pub(super) async fn remove_persisted_session(&self, uuid: &str) {
+        match tokio::fs::remove_file(self.sessions_path.join(uuid)).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                error!("Failed to remove upload session {} {:?}", uuid, e)
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::trow_server::registry_server::Registry;
+    use super::super::trow_server::{UploadChunk, UploadRef, UploadRequest};
+    use super::super::{test_server, Upload};
+    use crate::digest::sha512_tag_digest;
+    use std::fs;
+    use tonic::Request;
+
+    #[tokio::test]
+    async fn uploads_survive_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = test_server(dir.path());
+
+        let req = UploadRequest {
+            repo_name: "sessions/test".to_string(),
+        };
+        let uuid = ts
+            .request_upload(Request::new(req))
+            .await
+            .unwrap()
+            .into_inner()
+            .uuid;
+        let upload_ref = UploadRef {
+            repo_name: "sessions/test".to_string(),
+            uuid: uuid.clone(),
+        };
+        let chunk = |offset: u64, data: &str| {
+            tokio_stream::iter(vec![Ok(UploadChunk {
+                upload: Some(upload_ref.clone()),
+                offset,
+                data: data.as_bytes().to_vec(),
+            })])
+        };
+        ts.write_blob_stream(chunk(0, "first chunk")).await.unwrap();
+        drop(ts);
+
+        let ts = test_server(dir.path());
+        let upload = Upload {
+            repo_name: upload_ref.repo_name.clone(),
+            uuid: uuid.clone(),
+        };
+        let session = ts.active_uploads.read().unwrap()[&upload].clone();
+        assert_eq!(session.bytes_received, 11);
+        assert!(session.hasher.is_none());
+        let status = ts
+            .write_blob_stream(chunk(11, ", second"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(status.size, 19);
+        // What was written before the restart is hashed before carrying on
+        let hasher = ts.active_uploads.read().unwrap()[&upload].hasher.clone();
+        let hasher = hasher.unwrap();
+        assert!(hasher.covers(19));
+        assert_eq!(
+            hasher.tag_digest("sha512").unwrap(),
+            sha512_tag_digest("first chunk, second".as_bytes()).unwrap()
+        );
+
+        // Sessions whose data has been lost are dropped
+        fs::remove_file(ts.get_upload_path_for_blob(&uuid)).unwrap();
+        let ts = test_server(dir.path());
+        assert!(ts.active_uploads.read().unwrap().is_empty());
+        assert!(!ts.sessions_path.join(&uuid).exists());
+    }
+}
@@ -0,0 +1,249 @@
+use super::{blocking, temp_file_name, ObjectMetadata, ObjectNotFound, StorageDriver};
+use crate::digest::tag_digest;
+use failure::Error;
+use std::fs::{self, DirEntry, File};
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/**
+ * Stores objects as files under a root directory, with keys as relative paths.
+ *
+ * Writes go to a temporary file in temp_dir first, which must be on the same filesystem as the
+ * root for writes to be atomic. Anything more than a single file operation, e.g. walking
+ * directories or copying, is run on the blocking thread pool.
+ */
+pub struct FilesystemDriver {
+    root: PathBuf,
+    temp_dir: PathBuf,
+}
+
+struct RepoIterator {
+    paths: Vec<Result<DirEntry, std::io::Error>>,
+}
+
+impl RepoIterator {
+    fn new(base_dir: &Path) -> Result<RepoIterator, Error> {
+        let paths = fs::read_dir(base_dir)?.collect();
+        Ok(RepoIterator { paths })
+    }
+}
+
+impl Iterator for RepoIterator {
+    type Item = DirEntry;
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.paths.pop() {
+            None => None,
+            Some(res_path) => match res_path {
+                Err(e) => {
+                    warn!("Error iterating over repos {:?}", e);
+                    self.next()
+                }
+                Ok(path) => {
+                    if path.file_type().unwrap().is_dir() {
+                        let new_paths = fs::read_dir(path.path()).unwrap();
+                        self.paths.extend(new_paths);
+                        self.next()
+                    } else {
+                        Some(path)
+                    }
+                }
+            },
+        }
+    }
+}
+
+/**
+ * Syncs a directory, so that renames and new links in it are persisted.
+ */
+fn sync_dir(path: &Path) -> io::Result<()> {
+    File::open(path).and_then(|d| d.sync_all())
+}
+
+/**
+ * Writes to a temporary file in temp_dir, syncs it to disk and renames it over the destination.
+ *
+ * A crash or full disk leaves either the old or new version at the destination, never a partial
+ * write. The temporary file is cleaned up on error or by the next startup.
+ */
+pub(crate) fn write_atomically<F>(temp_dir: &Path, dest: &Path, write: F) -> Result<(), Error>
+where
+    F: FnOnce(&mut File) -> io::Result<()>,
+{
+    let temp_path = temp_dir.join(temp_file_name());
+    let res = File::create(&temp_path)
+        .and_then(|mut f| write(&mut f).and_then(|_| f.sync_all()))
+        .and_then(|_| fs::rename(&temp_path, dest))
+        .and_then(|_| match dest.parent() {
+            Some(dir) => sync_dir(dir),
+            None => Ok(()),
+        });
+
+    if res.is_err() && temp_path.exists() {
+        fs::remove_file(&temp_path)
+            .unwrap_or_else(|e| warn!("Failed to remove {:?} {:?}", temp_path, e));
+    }
+    Ok(res?)
+}
+
+fn map_not_found(e: io::Error, key: &str) -> Error {
+    if e.kind() == io::ErrorKind::NotFound {
+        ObjectNotFound {
+            key: key.to_string(),
+        }
+        .into()
+    } else {
+        e.into()
+    }
+}
+
+impl FilesystemDriver {
+    pub fn new(root: &Path, temp_dir: &Path) -> FilesystemDriver {
+        FilesystemDriver {
+            root: root.to_path_buf(),
+            temp_dir: temp_dir.to_path_buf(),
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+
+    fn create_parent(path: &Path) -> io::Result<()> {
+        match path.parent() {
+            Some(dir) if !dir.exists() => fs::create_dir_all(dir),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl StorageDriver for FilesystemDriver {
+    fn describe(&self) -> String {
+        format!("directory {:?}", self.root)
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, Error> {
+        Ok(tokio::fs::metadata(self.path(key)).await.is_ok())
+    }
+
+    async fn read(&self, key: &str) -> Result<Vec<u8>, Error> {
+        tokio::fs::read(self.path(key))
+            .await
+            .map_err(|e| map_not_found(e, key))
+    }
+
+    async fn open(&self, key: &str) -> Result<tokio::fs::File, Error> {
+        tokio::fs::File::open(self.path(key))
+            .await
+            .map_err(|e| map_not_found(e, key))
+    }
+
+    async fn digest(&self, key: &str, alg: &str) -> Result<String, Error> {
+        let path = self.path(key);
+        let key = key.to_string();
+        let alg = alg.to_string();
+        blocking(move || {
+            let file = File::open(path).map_err(|e| map_not_found(e, &key))?;
+            tag_digest(&alg, BufReader::new(file))
+        })
+        .await
+    }
+
+    async fn write(&self, key: &str, contents: &[u8]) -> Result<(), Error> {
+        let path = self.path(key);
+        let temp_dir = self.temp_dir.clone();
+        let contents = contents.to_vec();
+        blocking(move || {
+            Self::create_parent(&path)?;
+            write_atomically(&temp_dir, &path, |f| f.write_all(&contents))
+        })
+        .await
+    }
+
+    /**
+     * Renaming avoids writing the data a second time. The data is only copied if that fails,
+     * e.g. when the file and root are on different filesystems or mounts.
+     */
+    async fn put_file(&self, key: &str, file: &Path) -> Result<(), Error> {
+        let path = self.path(key);
+        let root = self.root.clone();
+        let temp_dir = self.temp_dir.clone();
+        let file = file.to_path_buf();
+        blocking(move || {
+            Self::create_parent(&path)?;
+
+            // The data must be on disk before it appears in the catalog
+            File::open(&file)?.sync_all()?;
+
+            match fs::rename(&file, &path) {
+                Ok(_) => sync_dir(path.parent().unwrap_or(&root))?,
+                Err(e) => {
+                    debug!("Failed to rename {:?}, falling back to copy {:?}", file, e);
+                    write_atomically(&temp_dir, &path, |f| {
+                        io::copy(&mut File::open(&file)?, f).map(|_| ())
+                    })?;
+                }
+            }
+
+            match fs::remove_file(&file) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                    warn!("Failed to remove {:?} {:?}", file, e)
+                }
+                _ => (),
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), Error> {
+        tokio::fs::remove_file(self.path(key))
+            .await
+            .map_err(|e| map_not_found(e, key))
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<(), Error> {
+        let from_path = self.path(from);
+        let to_path = self.path(to);
+        let from = from.to_string();
+        blocking(move || {
+            Self::create_parent(&to_path)?;
+            fs::rename(from_path, &to_path).map_err(|e| map_not_found(e, &from))
+        })
+        .await
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        let dir = self.path(prefix);
+        let root = self.root.clone();
+        blocking(move || {
+            if !dir.is_dir() {
+                return Ok(vec![]);
+            }
+
+            let keys = RepoIterator::new(&dir)?
+                .filter_map(|de| {
+                    let path = de.path();
+                    let rel_path = path.strip_prefix(&root).ok()?;
+                    let components: Vec<_> = rel_path
+                        .components()
+                        .map(|c| c.as_os_str().to_string_lossy())
+                        .collect();
+                    Some(components.join("/"))
+                })
+                .collect();
+            Ok(keys)
+        })
+        .await
+    }
+
+    async fn metadata(&self, key: &str) -> Result<ObjectMetadata, Error> {
+        let metadata = tokio::fs::metadata(self.path(key))
+            .await
+            .map_err(|e| map_not_found(e, key))?;
+        Ok(ObjectMetadata {
+            len: metadata.len(),
+            modified: metadata.modified()?,
+        })
+    }
+}
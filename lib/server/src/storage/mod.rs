@@ -0,0 +1,91 @@
+/*
+ * Storage drivers for the catalog, i.e. blobs and tag files.
+ *
+ * Objects are addressed by '/' separated keys relative to the root of the store, e.g.
+ * "blobs/sha256/<hash>" or "manifests/<repo>/<tag>".
+ *
+ * Uploads in progress always live in the local scratch directory, where chunks streamed from the
+ * frontend are appended. They are handed to the driver with put_file once complete. Likewise blobs
+ * are streamed to the frontend from a local file, which drivers for remote stores download into a
+ * cache.
+ */
+use failure::{Error, Fail};
+use std::path::Path;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+mod fs;
+mod s3;
+
+pub(crate) use self::fs::write_atomically;
+pub use self::fs::FilesystemDriver;
+pub use self::s3::{S3Config, S3Driver, DEFAULT_S3_CACHE_SIZE};
+
+//Prefix for files in scratch that are not uploads and can be removed on startup
+pub static TEMP_FILE_PREFIX: &str = "tmp-";
+
+pub fn temp_file_name() -> String {
+    format!("{}{}", TEMP_FILE_PREFIX, Uuid::new_v4())
+}
+
+#[derive(Fail, Debug)]
+#[fail(display = "No object stored at {}", key)]
+pub struct ObjectNotFound {
+    pub key: String,
+}
+
+pub fn is_not_found(e: &Error) -> bool {
+    e.downcast_ref::<ObjectNotFound>().is_some()
+}
+
+/**
+ * Runs file system work on the runtime's blocking thread pool, so that hashing or copying a large
+ * file doesn't hold up other requests. Used where the work is more than a single tokio::fs call.
+ */
+pub(crate) async fn blocking<F, T>(f: F) -> Result<T, Error>
+where
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f).await?
+}
+
+#[derive(Debug)]
+pub struct ObjectMetadata {
+    pub len: u64,
+    pub modified: SystemTime,
+}
+
+#[tonic::async_trait]
+pub trait StorageDriver: Send + Sync {
+    /// Short description for logs e.g. the data directory or bucket
+    fn describe(&self) -> String;
+
+    async fn exists(&self, key: &str) -> Result<bool, Error>;
+
+    /// Reads a whole object into memory. Meant for small objects such as tags and manifests.
+    async fn read(&self, key: &str) -> Result<Vec<u8>, Error>;
+
+    /// Opens a local file the object can be read from. The open file stays readable even if the
+    /// object is removed, e.g. from a cache, in the meantime.
+    async fn open(&self, key: &str) -> Result<tokio::fs::File, Error>;
+
+    /// Digest of the object with the named algorithm, e.g. "sha256". The object is read from the
+    /// store itself, never a local copy.
+    async fn digest(&self, key: &str, alg: &str) -> Result<String, Error>;
+
+    /// Atomically replaces the object with the given contents.
+    async fn write(&self, key: &str, contents: &[u8]) -> Result<(), Error>;
+
+    /// Stores a local file under the key. The file is consumed (normally moved) on success.
+    async fn put_file(&self, key: &str, file: &Path) -> Result<(), Error>;
+
+    async fn delete(&self, key: &str) -> Result<(), Error>;
+
+    async fn rename(&self, from: &str, to: &str) -> Result<(), Error>;
+
+    /// Keys of all objects under the given prefix, at any depth. Empty if there are none.
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, Error>;
+
+    async fn metadata(&self, key: &str) -> Result<ObjectMetadata, Error>;
+}
@@ -0,0 +1,409 @@
+use failure::Error;
+use rustls::server::AllowAnyAuthenticatedClient;
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerName};
+pub use rustls::{ClientConfig, ServerConfig};
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, BufReader};
+use std::os::unix::fs::FileTypeExt;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream, UnixListener, UnixStream};
+use tokio::sync::mpsc;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::server::Connected;
+use tonic::transport::{Channel, Endpoint, Uri};
+use tower::service_fn;
+
+/**
+ * Where the backend serves gRPC and frontends connect to it. Either a TCP address, or a Unix
+ * domain socket written as unix:<path> for a frontend on the same host.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum GrpcAddr {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl FromStr for GrpcAddr {
+    type Err = Error;
+
+    fn from_str(addr: &str) -> Result<Self, Error> {
+        if let Some(path) = addr.strip_prefix("unix:") {
+            if path.is_empty() {
+                return Err(format_err!("No path given for Unix socket {}", addr));
+            }
+            return Ok(GrpcAddr::Unix(PathBuf::from(path)));
+        }
+        match addr.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {
+                Ok(GrpcAddr::Tcp(addr.to_string()))
+            }
+            _ => Err(format_err!(
+                "Expected host:port or unix:<path> for gRPC address, got {}",
+                addr
+            )),
+        }
+    }
+}
+
+impl fmt::Display for GrpcAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GrpcAddr::Tcp(addr) => write!(f, "{}", addr),
+            GrpcAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+// Name the backend's certificate is checked against when connecting over a Unix socket
+const UNIX_SERVER_NAME: &str = "localhost";
+
+trait Io: AsyncRead + AsyncWrite + Send + Unpin {}
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Io for T {}
+
+/**
+ * A connection between the frontend and backend, whichever the socket and whether or not it is
+ * encrypted.
+ */
+pub struct GrpcStream(Box<dyn Io>);
+
+impl GrpcStream {
+    fn new(io: impl AsyncRead + AsyncWrite + Send + Unpin + 'static) -> GrpcStream {
+        GrpcStream(Box::new(io))
+    }
+}
+
+impl Connected for GrpcStream {}
+
+impl AsyncRead for GrpcStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for GrpcStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+fn read_certs(pem: &[u8]) -> Result<Vec<Certificate>, Error> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(pem))?;
+    if certs.is_empty() {
+        return Err(format_err!("No certificates found in PEM"));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn read_key(pem: &[u8]) -> Result<PrivateKey, Error> {
+    let mut reader = BufReader::new(pem);
+    while let Some(item) = rustls_pemfile::read_one(&mut reader)? {
+        match item {
+            rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::ECKey(key) => return Ok(PrivateKey(key)),
+            _ => continue,
+        }
+    }
+    Err(format_err!("No private key found in PEM"))
+}
+
+fn read_roots(pem: &[u8]) -> Result<RootCertStore, Error> {
+    let mut roots = RootCertStore::empty();
+    for cert in read_certs(pem)? {
+        roots.add(&cert)?;
+    }
+    Ok(roots)
+}
+
+/**
+ * TLS for the backend. If a CA is given, frontends must present a certificate signed by it.
+ */
+pub fn server_tls(
+    cert: &[u8],
+    key: &[u8],
+    client_ca: Option<&[u8]>,
+) -> Result<ServerConfig, Error> {
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = match client_ca {
+        Some(ca) => builder
+            .with_client_cert_verifier(AllowAnyAuthenticatedClient::new(read_roots(ca)?).boxed()),
+        None => builder.with_no_client_auth(),
+    };
+    let mut config = builder.with_single_cert(read_certs(cert)?, read_key(key)?)?;
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    Ok(config)
+}
+
+/**
+ * TLS for a frontend, which checks the backend's certificate was signed by the CA and presents
+ * its own certificate.
+ */
+pub fn client_tls(cert: &[u8], key: &[u8], ca: &[u8]) -> Result<ClientConfig, Error> {
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(read_roots(ca)?)
+        .with_client_auth_cert(read_certs(cert)?, read_key(key)?)?;
+    config.alpn_protocols = vec![b"h2".to_vec()];
+    Ok(config)
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl Listener {
+    async fn bind(addr: &GrpcAddr) -> io::Result<Listener> {
+        match addr {
+            GrpcAddr::Tcp(addr) => Ok(Listener::Tcp(TcpListener::bind(addr).await?)),
+            GrpcAddr::Unix(path) => {
+                // A socket may be left behind if the backend didn't exit cleanly. Anything else is
+                // kept, in case the path was mistyped.
+                match std::fs::symlink_metadata(path) {
+                    Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+                    Ok(_) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            format!("{} exists and is not a socket", path.display()),
+                        ))
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+                    Err(e) => return Err(e),
+                }
+                Ok(Listener::Unix(UnixListener::bind(path)?))
+            }
+        }
+    }
+
+    async fn accept(&self) -> io::Result<GrpcStream> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, _) = listener.accept().await?;
+                stream.set_nodelay(true)?;
+                Ok(GrpcStream::new(stream))
+            }
+            Listener::Unix(listener) => Ok(GrpcStream::new(listener.accept().await?.0)),
+        }
+    }
+}
+
+/**
+ * Accepts connections from frontends, to be served with serve_with_incoming.
+ *
+ * TLS handshakes happen off the accept loop, so a slow client can't hold up others. Connections
+ * that fail to be accepted are logged and dropped rather than stopping the server.
+ */
+pub async fn listen(
+    addr: &GrpcAddr,
+    tls: Option<ServerConfig>,
+) -> io::Result<ReceiverStream<io::Result<GrpcStream>>> {
+    let listener = Listener::bind(addr).await?;
+    let acceptor = tls.map(|config| TlsAcceptor::from(Arc::new(config)));
+    let (tx, rx) = mpsc::channel(16);
+
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Failed to accept gRPC connection: {}", e);
+                    // Don't spin if e.g. out of file descriptors
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+            match acceptor {
+                None => {
+                    if tx.send(Ok(stream)).await.is_err() {
+                        break;
+                    }
+                }
+                Some(ref acceptor) => {
+                    if tx.is_closed() {
+                        break;
+                    }
+                    let acceptor = acceptor.clone();
+                    let tx = tx.clone();
+                    tokio::spawn(async move {
+                        match acceptor.accept(stream).await {
+                            Ok(stream) => {
+                                tx.send(Ok(GrpcStream::new(stream))).await.ok();
+                            }
+                            Err(e) => warn!("TLS handshake with frontend failed: {}", e),
+                        }
+                    });
+                }
+            }
+        }
+    });
+
+    Ok(ReceiverStream::new(rx))
+}
+
+async fn connect_stream(addr: &GrpcAddr, tls: Option<Arc<ClientConfig>>) -> io::Result<GrpcStream> {
+    let (stream, host) = match addr {
+        GrpcAddr::Unix(path) => (
+            GrpcStream::new(UnixStream::connect(path).await?),
+            UNIX_SERVER_NAME,
+        ),
+        GrpcAddr::Tcp(addr) => {
+            let stream = TcpStream::connect(addr).await?;
+            stream.set_nodelay(true)?;
+            let host = addr
+                .rsplit_once(':')
+                .map_or(addr.as_str(), |(host, _)| host);
+            (
+                GrpcStream::new(stream),
+                host.trim_start_matches('[').trim_end_matches(']'),
+            )
+        }
+    };
+    match tls {
+        None => Ok(stream),
+        Some(config) => {
+            let name = ServerName::try_from(host)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            let stream = TlsConnector::from(config).connect(name, stream).await?;
+            Ok(GrpcStream::new(stream))
+        }
+    }
+}
+
+/**
+ * Connects a frontend to the backend. The channel reconnects if the connection is lost, e.g. on a
+ * backend restart.
+ *
+ * With TLS, the backend's certificate must be for the host of a TCP address, or for localhost if
+ * connecting over a Unix socket.
+ */
+pub async fn connect(addr: &GrpcAddr, tls: Option<Arc<ClientConfig>>) -> Result<Channel, Error> {
+    // Only used for the :authority of requests, as the connector decides where to connect
+    let uri = match addr {
+        GrpcAddr::Tcp(addr) => format!("http://{}", addr),
+        GrpcAddr::Unix(_) => format!("http://{}", UNIX_SERVER_NAME),
+    };
+    let addr = addr.clone();
+    let channel = Endpoint::from_shared(uri)?
+        .connect_with_connector(service_fn(move |_: Uri| {
+            let addr = addr.clone();
+            let tls = tls.clone();
+            async move { connect_stream(&addr, tls).await }
+        }))
+        .await?;
+    Ok(channel)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{client_tls, connect_stream, listen, server_tls, GrpcAddr};
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_stream::StreamExt;
+
+    #[test]
+    fn parses_addresses() {
+        assert_eq!(
+            "127.0.0.1:51000".parse::<GrpcAddr>().unwrap(),
+            GrpcAddr::Tcp("127.0.0.1:51000".to_string())
+        );
+        assert_eq!(
+            "[::1]:51000".parse::<GrpcAddr>().unwrap(),
+            GrpcAddr::Tcp("[::1]:51000".to_string())
+        );
+        assert_eq!(
+            "unix:/run/trow/backend.sock".parse::<GrpcAddr>().unwrap(),
+            GrpcAddr::Unix(PathBuf::from("/run/trow/backend.sock"))
+        );
+        assert!("unix:".parse::<GrpcAddr>().is_err());
+        assert!("localhost".parse::<GrpcAddr>().is_err());
+        assert!(":51000".parse::<GrpcAddr>().is_err());
+        assert!("localhost:port".parse::<GrpcAddr>().is_err());
+    }
+
+    #[tokio::test]
+    async fn connects_over_unix_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let addr = GrpcAddr::Unix(dir.path().join("backend.sock"));
+        let mut incoming = listen(&addr, None).await.unwrap();
+
+        let mut client = connect_stream(&addr, None).await.unwrap();
+        client.write_all(b"ping").await.unwrap();
+
+        let mut server = incoming.next().await.unwrap().unwrap();
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+    }
+
+    #[tokio::test]
+    async fn connects_over_unix_socket_with_tls() {
+        let dir = tempfile::tempdir().unwrap();
+        let addr = GrpcAddr::Unix(dir.path().join("backend.sock"));
+        let ca = include_bytes!("../testdata/ca.crt");
+        let server = server_tls(
+            include_bytes!("../testdata/backend.crt"),
+            include_bytes!("../testdata/backend.key"),
+            Some(ca),
+        )
+        .unwrap();
+        let client = client_tls(
+            include_bytes!("../testdata/frontend.crt"),
+            include_bytes!("../testdata/frontend.key"),
+            ca,
+        )
+        .unwrap();
+        let mut incoming = listen(&addr, Some(server)).await.unwrap();
+
+        let mut client = connect_stream(&addr, Some(Arc::new(client))).await.unwrap();
+        client.write_all(b"ping").await.unwrap();
+        client.flush().await.unwrap();
+
+        let mut server = incoming.next().await.unwrap().unwrap();
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+    }
+
+    #[tokio::test]
+    async fn only_replaces_stale_sockets() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backend.sock");
+        let addr = GrpcAddr::Unix(path.clone());
+
+        // Left behind by a backend that was killed
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        let _incoming = listen(&addr, None).await.unwrap();
+        connect_stream(&addr, None).await.unwrap();
+
+        let data = dir.path().join("data");
+        fs::write(&data, "not a socket").unwrap();
+        assert!(listen(&GrpcAddr::Unix(data.clone()), None).await.is_err());
+        assert_eq!(fs::read_to_string(&data).unwrap(), "not a socket");
+    }
+}
@@ -117,11 +117,12 @@ impl AdmissionController for TrowServer {
         let mut reason = "".to_string();
 
         for image_raw in ar.images {
+            let exists = self.image_exists(&parse_image(&image_raw)).await;
             //Using a closure here is inefficient but makes it easier to test check_image
             let (v, r) = check_image(
                 &image_raw,
                 &ar.host_names,
-                &|image| self.image_exists(image),
+                &|_| exists,
                 &|i| self.is_local_denied(i),
                 &|i| self.is_allowed(i),
             );
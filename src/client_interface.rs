@@ -1,21 +1,26 @@
-pub mod trow_proto {
-    include!("../lib/protobuf/out/trow.rs");
-}
+pub use trow_server::proto as trow_proto;
 
-use crate::registry_interface::digest::{self, Digest, DigestAlgorithm};
+use crate::registry_interface::digest::{self, Digest};
 use crate::registry_interface::{
-    validation, BlobReader, CatalogOperations, ContentInfo, ManifestHistory, ManifestReader,
-    Metrics, MetricsError, MetricsResponse, Validation, ValidationError,
+    validation, AdminError, AdminOperations, BlobInfo, BlobReader, CatalogOperations, ContentInfo,
+    FsckReport, GarbageCollectionReport, ManifestHistory, ManifestInfo, ManifestReader, Metrics,
+    MetricsError, MetricsResponse, MissingManifest, Referrer, ReferrerList, SeekRead,
+    StoredManifest, Validation, ValidationError,
 };
-use tokio::runtime::Runtime;
 use trow_proto::{
-    admission_controller_client::AdmissionControllerClient, registry_client::RegistryClient,
-    BlobRef, CatalogRequest, CompleteRequest, HealthRequest, ListTagsRequest,
-    ManifestHistoryRequest, ManifestRef, MetricsRequest, ReadinessRequest, UploadRef,
-    UploadRequest, VerifyManifestRequest,
+    admission_controller_client::AdmissionControllerClient,
+    admission_controller_server::AdmissionController, registry_client::RegistryClient,
+    registry_server::Registry, BlobChunk, BlobRef, CatalogRequest, CompleteRequest, FsckRequest,
+    GarbageCollectionRequest, HealthRequest, ListTagsRequest, ManifestHistoryRequest, ManifestRef,
+    ManifestUploadChunk, MetricsRequest, ReadBlobRequest, ReadinessRequest, ReferrersRequest,
+    UploadChunk, UploadRef, UploadRequest,
 };
 
-use tonic::{Code, Request};
+use tokio::sync::OnceCell;
+use tonic::transport::Channel;
+use tonic::{Code, Request, Response};
+use trow_server::transport::{self, ClientConfig, GrpcAddr};
+use trow_server::{TrowServer, CHUNK_SIZE};
 
 use crate::types::{self, *};
 use crate::{
@@ -23,17 +28,249 @@ use crate::{
     registry_interface::{BlobStorage, ManifestStorage, StorageDriverError},
 };
 use failure::Error;
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::{ready, FutureExt, StreamExt, TryStreamExt};
 use serde_json::Value;
 use std::convert::TryInto;
-use std::fs::OpenOptions;
-use std::io;
-use std::io::prelude::*;
+use std::io::{Cursor, SeekFrom};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncSeek, ReadBuf};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+/*
+ * Where the backend runs.
+ *
+ * _Grpc_: connection to a backend serving gRPC, multiplexed over by concurrent requests. It
+ *         connects on first use and reconnects if the connection is lost, e.g. on a backend
+ *         restart.
+ * _Embedded_: backend in this process, whose methods are called directly.
+ */
+enum Backend {
+    Grpc(GrpcBackend),
+    Embedded(Box<TrowServer>),
+}
+
+struct GrpcBackend {
+    addr: GrpcAddr,
+    tls: Option<Arc<ClientConfig>>,
+    channel: OnceCell<Channel>,
+}
+
+impl GrpcBackend {
+    /*
+     * Connecting waits until a request needs the backend, so the frontend can be started first.
+     * A failed connection is retried on the next request.
+     */
+    async fn channel(&self) -> Result<Channel, tonic::Status> {
+        self.channel
+            .get_or_try_init(|| transport::connect(&self.addr, self.tls.clone()))
+            .await
+            .cloned()
+            .map_err(|e| {
+                warn!("Failed to connect to backend at {}: {}", self.addr, e);
+                tonic::Status::unavailable(format!("Failed to connect to backend: {}", e))
+            })
+    }
+}
+
+type BlobChunks = BoxStream<'static, Result<BlobChunk, tonic::Status>>;
+
+/*
+ * Where a blob is read from. Unlike the ClientInterface it can be kept by the reader given to the
+ * request handler, which may need to ask for the blob again from another offset.
+ */
+#[derive(Clone)]
+enum BlobSource {
+    Grpc(Channel),
+    Embedded(Box<TrowServer>),
+}
+
+impl BlobSource {
+    async fn read(self, req: ReadBlobRequest) -> Result<BlobChunks, tonic::Status> {
+        match self {
+            BlobSource::Grpc(channel) => RegistryClient::new(channel)
+                .read_blob(Request::new(req))
+                .await
+                .map(|resp| resp.into_inner().boxed()),
+            BlobSource::Embedded(server) => Registry::read_blob(&*server, Request::new(req))
+                .await
+                .map(|resp| resp.into_inner().boxed()),
+        }
+    }
+}
+
+/*
+ * Reads a blob as it is streamed from the backend. The stream is opened on the first read, and
+ * seeking drops it so the next read asks the backend to stream from the new position. Ranges are
+ * served without reading the whole blob.
+ */
+struct BlobStream {
+    source: BlobSource,
+    request: ReadBlobRequest,
+    size: u64,
+    pos: u64,
+    opening: Option<BoxFuture<'static, Result<BlobChunks, tonic::Status>>>,
+    chunks: Option<BlobChunks>,
+    // The part of the last chunk received that hasn't been read yet
+    chunk: Cursor<Vec<u8>>,
+}
+
+impl SeekRead for BlobStream {}
+
+fn status_to_io(status: tonic::Status) -> io::Error {
+    io::Error::other(status)
+}
+
+impl AsyncRead for BlobStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            let pending = &this.chunk.get_ref()[this.chunk.position() as usize..];
+            if !pending.is_empty() {
+                let n = pending.len().min(buf.remaining());
+                buf.put_slice(&pending[..n]);
+                this.chunk.set_position(this.chunk.position() + n as u64);
+                this.pos += n as u64;
+                return Poll::Ready(Ok(()));
+            }
+
+            if this.chunks.is_none() {
+                if this.pos >= this.size {
+                    return Poll::Ready(Ok(()));
+                }
+                let source = &this.source;
+                let request = &this.request;
+                let pos = this.pos;
+                let opening = this.opening.get_or_insert_with(|| {
+                    let req = ReadBlobRequest {
+                        offset: pos,
+                        ..request.clone()
+                    };
+                    source.clone().read(req).boxed()
+                });
+                let opened = ready!(opening.poll_unpin(cx));
+                this.opening = None;
+                this.chunks = Some(opened.map_err(status_to_io)?);
+            }
+
+            match ready!(this.chunks.as_mut().unwrap().poll_next_unpin(cx)) {
+                Some(Ok(chunk)) => this.chunk = Cursor::new(chunk.data),
+                Some(Err(e)) => {
+                    this.chunks = None;
+                    return Poll::Ready(Err(status_to_io(e)));
+                }
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+    }
+}
+
+impl AsyncSeek for BlobStream {
+    fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let pos = match position {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => add_offset(self.size, n),
+            SeekFrom::Current(n) => add_offset(self.pos, n),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek before start of blob"))?;
+
+        if pos != self.pos {
+            self.pos = pos;
+            self.opening = None;
+            self.chunks = None;
+            self.chunk = Cursor::new(Vec::new());
+        }
+        Ok(())
+    }
 
-// BIG TODO:
-// Creating a new runtime for each request is awful.
-// Best fix is to move to Rocket 0.5 or another framework
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Ok(self.pos))
+    }
+}
+
+fn add_offset(pos: u64, offset: i64) -> Option<u64> {
+    if offset >= 0 {
+        pos.checked_add(offset as u64)
+    } else {
+        pos.checked_sub(offset.unsigned_abs())
+    }
+}
+
+/*
+ * Reads data in chunks of up to CHUNK_SIZE and sends them as the messages made by the given
+ * function, until the data runs out or the receiver is dropped.
+ */
+async fn send_chunks<T>(
+    data: &mut (dyn AsyncRead + Unpin + Send),
+    tx: mpsc::Sender<T>,
+    mut message: impl FnMut(Vec<u8>) -> T,
+) -> io::Result<()> {
+    loop {
+        let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+        let len = (&mut *data)
+            .take(CHUNK_SIZE as u64)
+            .read_to_end(&mut chunk)
+            .await?;
+        // The receiver is only dropped if the call failed, which the caller reports
+        if tx.send(message(chunk)).await.is_err() || len < CHUNK_SIZE {
+            return Ok(());
+        }
+    }
+}
+
+/*
+ * Calls a Registry method of the backend and gives the message it returns.
+ */
+macro_rules! registry_call {
+    ($ci:expr, $method:ident, $req:expr) => {
+        match &$ci.backend {
+            Backend::Grpc(grpc) => match grpc.channel().await {
+                Ok(channel) => {
+                    RegistryClient::new(channel)
+                        .$method(Request::new($req))
+                        .await
+                }
+                Err(e) => Err(e),
+            },
+            Backend::Embedded(server) => Registry::$method(&**server, Request::new($req)).await,
+        }
+        .map(Response::into_inner)
+    };
+}
+
+/*
+ * As registry_call, for methods that return a stream of messages.
+ */
+macro_rules! registry_stream {
+    ($ci:expr, $method:ident, $req:expr) => {
+        match &$ci.backend {
+            Backend::Grpc(grpc) => match grpc.channel().await {
+                Ok(channel) => RegistryClient::new(channel)
+                    .$method(Request::new($req))
+                    .await
+                    .map(|resp| resp.into_inner().boxed()),
+                Err(e) => Err(e),
+            },
+            Backend::Embedded(server) => Registry::$method(&**server, Request::new($req))
+                .await
+                .map(|resp| resp.into_inner().boxed()),
+        }
+    };
+}
+
+/*
+ * Talks to the backend on behalf of the request handlers.
+ */
 pub struct ClientInterface {
-    server: String,
+    backend: Backend,
 }
 
 /**
@@ -72,16 +309,21 @@ pub enum RegistryError {
     InvalidName,
     #[fail(display = "Invalid manifest")]
     InvalidManifest,
+    #[fail(display = "Index refers to missing manifests")]
+    MissingManifests(Vec<MissingManifest>),
     #[fail(display = "Invalid Range")]
     Internal,
 }
 
+#[rocket::async_trait]
 impl ManifestStorage for ClientInterface {
-    fn get_manifest(&self, name: &str, tag: &str) -> Result<ManifestReader, StorageDriverError> {
-        let mut rt = Runtime::new().unwrap();
+    async fn get_manifest(
+        &self,
+        name: &str,
+        tag: &str,
+    ) -> Result<ManifestReader, StorageDriverError> {
         let rn = RepoName(name.to_string());
-        let f = self.get_reader_for_manifest(&rn, tag);
-        let mr = rt.block_on(f).map_err(|e| {
+        let mr = self.get_reader_for_manifest(&rn, tag).await.map_err(|e| {
             warn!("Error getting manifest {:?}", e);
             StorageDriverError::Internal
         })?;
@@ -89,54 +331,107 @@ impl ManifestStorage for ClientInterface {
         Ok(mr)
     }
 
-    fn store_manifest(
+    async fn store_manifest(
         &self,
         name: &str,
         tag: &str,
-        data: &mut Box<dyn Read>,
-    ) -> Result<Digest, StorageDriverError> {
+        digest: Option<&Digest>,
+        data: &mut (dyn AsyncRead + Unpin + Send),
+    ) -> Result<StoredManifest, StorageDriverError> {
         let repo = RepoName(name.to_string());
 
-        let mut rt = Runtime::new().unwrap();
-        match rt.block_on(self.upload_manifest(&repo, &tag, data)) {
-            Ok(vm) => Ok(vm.digest().clone()),
+        match self.upload_manifest(&repo, tag, digest, data).await {
+            Ok(vm) => Ok(StoredManifest {
+                digest: vm.digest().clone(),
+                subject: vm.subject().cloned(),
+            }),
             Err(RegistryError::InvalidName) => {
                 Err(StorageDriverError::InvalidName(format!("{}:{}", name, tag)))
             }
             Err(RegistryError::InvalidManifest) => Err(StorageDriverError::InvalidManifest),
+            Err(RegistryError::MissingManifests(missing)) => {
+                Err(StorageDriverError::MissingManifests(missing))
+            }
             Err(_) => Err(StorageDriverError::Internal),
         }
     }
 
-    fn delete_manifest(&self, name: &str, digest: &Digest) -> Result<(), StorageDriverError> {
+    async fn delete_manifest(&self, name: &str, digest: &Digest) -> Result<(), StorageDriverError> {
         let repo = RepoName(name.to_string());
-        let r = self.delete_by_manifest(&repo, &digest);
-        Runtime::new().unwrap().block_on(r).map_err(|e| {
-            let e = e.downcast::<tonic::Status>();
-            if let Ok(ts) = e {
-                match ts.code() {
-                    Code::InvalidArgument => StorageDriverError::Unsupported,
-                    Code::NotFound => StorageDriverError::InvalidManifest,
-                    _ => StorageDriverError::Internal,
+        let reference = digest.to_string();
+        self.delete_by_reference(&repo, &reference)
+            .await
+            .map_err(|e| {
+                let e = e.downcast::<tonic::Status>();
+                if let Ok(ts) = e {
+                    match ts.code() {
+                        Code::InvalidArgument => StorageDriverError::Unsupported,
+                        Code::NotFound => StorageDriverError::InvalidManifest,
+                        _ => StorageDriverError::Internal,
+                    }
+                } else {
+                    StorageDriverError::Internal
                 }
-            } else {
-                StorageDriverError::Internal
+            })?;
+        Ok(())
+    }
+
+    async fn delete_tag(&self, name: &str, tag: &str) -> Result<(), StorageDriverError> {
+        let repo = RepoName(name.to_string());
+        self.delete_by_reference(&repo, tag).await.map_err(|e| {
+            match e.downcast::<tonic::Status>().map(|s| s.code()) {
+                Ok(Code::NotFound) | Ok(Code::InvalidArgument) => {
+                    StorageDriverError::InvalidManifest
+                }
+                _ => StorageDriverError::Internal,
             }
         })?;
         Ok(())
     }
 
-    fn has_manifest(&self, _name: &str, _algo: &DigestAlgorithm, _reference: &str) -> bool {
-        todo!()
+    async fn get_manifest_info(
+        &self,
+        name: &str,
+        reference: &str,
+    ) -> Result<ManifestInfo, StorageDriverError> {
+        let rn = RepoName(name.to_string());
+        self.get_info_for_manifest(&rn, reference)
+            .await
+            .map_err(|e| {
+                warn!("Error getting manifest info {:?}", e);
+                StorageDriverError::Internal
+            })
+    }
+
+    async fn get_referrers(
+        &self,
+        name: &str,
+        digest: &Digest,
+        artifact_type: Option<&str>,
+    ) -> Result<ReferrerList, StorageDriverError> {
+        let referrers = self
+            .list_referrers(name, digest, artifact_type.unwrap_or_default())
+            .await
+            .map_err(|e| {
+                warn!("Error getting referrers {:?}", e);
+                StorageDriverError::Internal
+            })?;
+        Ok(ReferrerList {
+            referrers,
+            artifact_type: artifact_type.map(str::to_string),
+        })
     }
 }
 
+#[rocket::async_trait]
 impl BlobStorage for ClientInterface {
-    fn get_blob(&self, name: &str, digest: &Digest) -> Result<BlobReader, StorageDriverError> {
-        let mut rt = Runtime::new().unwrap();
+    async fn get_blob(
+        &self,
+        name: &str,
+        digest: &Digest,
+    ) -> Result<BlobReader, StorageDriverError> {
         let rn = RepoName(name.to_string());
-        let f = self.get_reader_for_blob(&rn, &digest);
-        let br = rt.block_on(f).map_err(|e| {
+        let br = self.get_reader_for_blob(&rn, digest).await.map_err(|e| {
             warn!("Error getting manifest {:?}", e);
             StorageDriverError::Internal
         })?;
@@ -144,21 +439,20 @@ impl BlobStorage for ClientInterface {
         Ok(br)
     }
 
-    fn store_blob_chunk(
+    async fn store_blob_chunk(
         &self,
         name: &str,
         session_id: &str,
         data_info: Option<ContentInfo>,
-        data: &mut Box<dyn Read>,
+        data: &mut (dyn AsyncRead + Unpin + Send),
     ) -> Result<u64, StorageDriverError> {
-        let mut rt = Runtime::new().unwrap();
-        let rn = RepoName(name.to_string());
-        let uuid = Uuid(session_id.to_string());
-        let f = self.get_write_sink_for_upload(&rn, &uuid);
-        let mut sink = rt.block_on(f).map_err(|e| {
-            warn!("Error finding write sink for blob {:?}", e);
-            StorageDriverError::InvalidName(format!("{} {}", name, session_id))
-        })?;
+        let start_index = self
+            .get_upload_status(name, session_id)
+            .await
+            .map_err(|e| {
+                warn!("Error finding upload for blob {:?}", e);
+                StorageDriverError::InvalidName(format!("{} {}", name, session_id))
+            })?;
 
         let have_range = data_info.is_some();
         let info = data_info.unwrap_or(ContentInfo {
@@ -166,7 +460,6 @@ impl BlobStorage for ClientInterface {
             range: (0, 0),
         });
 
-        let start_index = sink.stream_len().unwrap_or(0);
         if have_range && (start_index != info.range.0) {
             warn!(
                 "Asked to store blob with invalid start index. Expected {} got {}",
@@ -175,12 +468,28 @@ impl BlobStorage for ClientInterface {
             return Err(StorageDriverError::InvalidContentRange);
         }
 
-        let len = io::copy(data, &mut sink).map_err(|e| {
-            warn!("Error writing blob {:?}", e);
-            StorageDriverError::Internal
-        })?;
+        let total = self
+            .write_upload(name, session_id, start_index, data)
+            .await
+            .map_err(|e| match e.downcast::<tonic::Status>() {
+                Ok(ts) => match ts.code() {
+                    // Another chunk was written after the start index was checked
+                    Code::OutOfRange => StorageDriverError::InvalidContentRange,
+                    Code::FailedPrecondition => {
+                        StorageDriverError::InvalidName(format!("{} {}", name, session_id))
+                    }
+                    _ => {
+                        warn!("Error writing blob {:?}", ts);
+                        StorageDriverError::Internal
+                    }
+                },
+                Err(e) => {
+                    warn!("Error writing blob {:?}", e);
+                    StorageDriverError::Internal
+                }
+            })?;
 
-        let total = sink.stream_len().unwrap_or(len);
+        let len = total - start_index;
         if have_range {
             if (info.range.1 + 1) != total {
                 warn!("total {} r + 1 {}", total, info.range.1 + 1 + 1);
@@ -195,15 +504,14 @@ impl BlobStorage for ClientInterface {
         Ok(total)
     }
 
-    fn complete_and_verify_blob_upload(
+    async fn complete_and_verify_blob_upload(
         &self,
         name: &str,
         session_id: &str,
         digest: &Digest,
     ) -> Result<(), StorageDriverError> {
-        let mut rt = Runtime::new().unwrap();
-
-        rt.block_on(self.complete_upload(name, session_id, &digest))
+        self.complete_upload(name, session_id, digest)
+            .await
             .map_err(|e| match e.downcast::<tonic::Status>() {
                 Ok(ts) => match ts.code() {
                     Code::InvalidArgument => StorageDriverError::InvalidDigest,
@@ -217,9 +525,8 @@ impl BlobStorage for ClientInterface {
         Ok(())
     }
 
-    fn start_blob_upload(&self, name: &str) -> Result<String, StorageDriverError> {
-        let mut rt = Runtime::new().unwrap();
-        rt.block_on(self.request_upload(name)).map_err(|e| {
+    async fn start_blob_upload(&self, name: &str) -> Result<String, StorageDriverError> {
+        self.request_upload(name).await.map_err(|e| {
             match e.downcast::<tonic::Status>().map(|s| s.code()) {
                 Ok(Code::InvalidArgument) => StorageDriverError::InvalidName(name.to_string()),
                 _ => StorageDriverError::Internal,
@@ -227,34 +534,88 @@ impl BlobStorage for ClientInterface {
         })
     }
 
-    fn delete_blob(&self, name: &str, digest: &Digest) -> Result<(), StorageDriverError> {
+    async fn delete_blob(&self, name: &str, digest: &Digest) -> Result<(), StorageDriverError> {
         info!("Attempting to delete blob {} in {}", digest, name);
         let rn = RepoName(name.to_string());
-        let mut rt = Runtime::new().unwrap();
-        rt.block_on(self.delete_blob_local(&rn, &digest))
-            .map_err(|_| StorageDriverError::InvalidDigest)?;
+        self.delete_blob_local(&rn, digest).await.map_err(|e| {
+            match e.downcast::<tonic::Status>() {
+                Ok(ts) if ts.code() == Code::FailedPrecondition => {
+                    let tags = serde_json::from_slice(ts.details()).unwrap_or_default();
+                    StorageDriverError::BlobReferenced(tags)
+                }
+                _ => StorageDriverError::InvalidDigest,
+            }
+        })?;
         Ok(())
     }
 
-    fn status_blob_upload(
+    async fn status_blob_upload(
         &self,
-        _name: &str,
-        _session_id: &str,
-    ) -> crate::registry_interface::UploadInfo {
-        todo!()
+        name: &str,
+        session_id: &str,
+    ) -> Result<crate::registry_interface::UploadInfo, StorageDriverError> {
+        let uploaded = self
+            .get_upload_status(name, session_id)
+            .await
+            .map_err(|e| match e.downcast::<tonic::Status>() {
+                Ok(ts) if ts.code() == Code::NotFound => {
+                    StorageDriverError::UnknownUpload(session_id.to_string())
+                }
+                Ok(ts) => {
+                    warn!("Error getting upload status {:?}", ts);
+                    StorageDriverError::Internal
+                }
+                Err(e) => {
+                    warn!("Error getting upload status {:?}", e);
+                    StorageDriverError::Internal
+                }
+            })?;
+
+        Ok(crate::registry_interface::UploadInfo {
+            name: name.to_string(),
+            session_id: session_id.to_string(),
+            uploaded,
+        })
     }
 
-    fn cancel_blob_upload(&self, _name: &str, _session_id: &str) -> Result<(), StorageDriverError> {
-        todo!()
+    async fn cancel_blob_upload(
+        &self,
+        name: &str,
+        session_id: &str,
+    ) -> Result<(), StorageDriverError> {
+        self.cancel_upload(name, session_id).await.map_err(|e| {
+            match e.downcast::<tonic::Status>() {
+                Ok(ts) if ts.code() == Code::NotFound => {
+                    StorageDriverError::UnknownUpload(session_id.to_string())
+                }
+                Ok(ts) => {
+                    warn!("Error cancelling upload {:?}", ts);
+                    StorageDriverError::Internal
+                }
+                Err(e) => {
+                    warn!("Error cancelling upload {:?}", e);
+                    StorageDriverError::Internal
+                }
+            }
+        })
     }
 
-    fn has_blob(&self, _name: &str, _digest: &Digest) -> bool {
-        todo!()
+    async fn get_blob_info(
+        &self,
+        name: &str,
+        digest: &Digest,
+    ) -> Result<BlobInfo, StorageDriverError> {
+        let rn = RepoName(name.to_string());
+        self.get_info_for_blob(&rn, digest).await.map_err(|e| {
+            warn!("Error getting blob info {:?}", e);
+            StorageDriverError::Internal
+        })
     }
 }
 
+#[rocket::async_trait]
 impl CatalogOperations for ClientInterface {
-    fn get_catalog(
+    async fn get_catalog(
         &self,
         start_value: Option<&str>,
         num_results: Option<u32>,
@@ -262,14 +623,13 @@ impl CatalogOperations for ClientInterface {
         let num_results = num_results.unwrap_or(u32::MAX);
         let start_value = start_value.unwrap_or_default();
 
-        Runtime::new()
-            .unwrap()
-            .block_on(self.get_catalog_part(num_results, start_value))
+        self.get_catalog_part(num_results, start_value)
+            .await
             .map_err(|_| StorageDriverError::Internal)
             .map(|rc| rc.raw())
     }
 
-    fn get_tags(
+    async fn get_tags(
         &self,
         repo: &str,
         start_value: Option<&str>,
@@ -278,14 +638,13 @@ impl CatalogOperations for ClientInterface {
         let num_results = num_results.unwrap_or(u32::MAX);
         let start_value = start_value.unwrap_or_default();
 
-        Runtime::new()
-            .unwrap()
-            .block_on(self.list_tags(repo, num_results, start_value))
+        self.list_tags(repo, num_results, start_value)
+            .await
             .map_err(|_| StorageDriverError::Internal)
             .map(|rc| rc.raw())
     }
 
-    fn get_history(
+    async fn get_history(
         &self,
         repo: &str,
         name: &str,
@@ -295,67 +654,80 @@ impl CatalogOperations for ClientInterface {
         let num_results = num_results.unwrap_or(u32::MAX);
         let start_value = start_value.unwrap_or_default();
 
-        Runtime::new()
-            .unwrap()
-            .block_on(self.get_manifest_history(repo, name, num_results, start_value))
+        self.get_manifest_history(repo, name, num_results, start_value)
+            .await
             .map_err(|_| StorageDriverError::Internal)
     }
 }
 
+#[rocket::async_trait]
 impl Validation for ClientInterface {
-    fn validate_admission(
+    async fn validate_admission(
         &self,
         admission_req: &validation::AdmissionRequest,
         host_names: &Vec<String>,
     ) -> Result<validation::AdmissionResponse, ValidationError> {
-        Runtime::new()
-            .unwrap()
-            .block_on(self.validate_admission_internal(admission_req, host_names))
+        self.validate_admission_internal(admission_req, host_names)
+            .await
             .map_err(|_| ValidationError::Internal)
     }
 }
 
+#[rocket::async_trait]
 impl Metrics for ClientInterface {
-    fn is_healthy(&self) -> bool {
-        Runtime::new()
-            .unwrap()
-            .block_on(self.is_healthy())
-            .is_healthy
+    async fn is_healthy(&self) -> bool {
+        self.is_healthy().await.is_healthy
+    }
+
+    async fn is_ready(&self) -> bool {
+        self.is_ready().await.is_ready
+    }
+
+    async fn get_metrics(
+        &self,
+    ) -> Result<MetricsResponse, crate::registry_interface::MetricsError> {
+        self.get_metrics().await.map_err(|_| MetricsError::Internal)
     }
+}
 
-    fn is_ready(&self) -> bool {
-        Runtime::new().unwrap().block_on(self.is_ready()).is_ready
+#[rocket::async_trait]
+impl AdminOperations for ClientInterface {
+    async fn collect_garbage(&self, dry_run: bool) -> Result<GarbageCollectionReport, AdminError> {
+        self.collect_garbage_internal(dry_run)
+            .await
+            .map_err(|_| AdminError::Internal)
     }
 
-    fn get_metrics(&self) -> Result<MetricsResponse, crate::registry_interface::MetricsError> {
-        Runtime::new()
-            .unwrap()
-            .block_on(self.get_metrics())
-            .map_err(|_| MetricsError::Internal)
+    async fn fsck(&self, repair: bool) -> Result<FsckReport, AdminError> {
+        self.fsck_internal(repair)
+            .await
+            .map_err(|_| AdminError::Internal)
     }
 }
 
 impl ClientInterface {
-    pub fn new(server: String) -> Result<Self, Error> {
-        Ok(ClientInterface { server })
+    /**
+     * Connects to the backend at the given address, over TLS if a client config is given.
+     * Nothing is connected until the first request, which must be made on the runtime the
+     * handlers run on, as the channel's worker is spawned onto it.
+     */
+    pub fn new(addr: GrpcAddr, tls: Option<ClientConfig>) -> Self {
+        ClientInterface::with_backend(Backend::Grpc(GrpcBackend {
+            addr,
+            tls: tls.map(Arc::new),
+            channel: OnceCell::new(),
+        }))
     }
 
-    async fn connect_registry(
-        &self,
-    ) -> Result<RegistryClient<tonic::transport::Channel>, tonic::transport::Error> {
-        debug!("Connecting to {}", self.server);
-        let x = RegistryClient::connect(self.server.to_string()).await;
-        debug!("Connected to {}", self.server);
-        x
+    /**
+     * Calls the given backend in-process, without going through gRPC.
+     */
+    pub fn embedded(server: TrowServer) -> Self {
+        ClientInterface::with_backend(Backend::Embedded(Box::new(server)))
     }
 
-    async fn connect_admission_controller(
-        &self,
-    ) -> Result<AdmissionControllerClient<tonic::transport::Channel>, tonic::transport::Error> {
-        debug!("Connecting to {}", self.server);
-        let x = AdmissionControllerClient::connect(self.server.to_string()).await;
-        debug!("Connected to {}", self.server);
-        x
+    fn with_backend(backend: Backend) -> Self {
+        ClientInterface { backend }
     }
 
     async fn request_upload(&self, repo_name: &str) -> Result<String, Error> {
@@ -364,12 +736,7 @@ impl ClientInterface {
             repo_name: repo_name.to_string(),
         };
 
-        let response = self
-            .connect_registry()
-            .await?
-            .request_upload(Request::new(req))
-            .await?
-            .into_inner();
+        let response = registry_call!(self, request_upload, req)?;
 
         Ok(response.uuid)
     }
@@ -391,143 +758,153 @@ impl ClientInterface {
             user_digest: digest.to_string(),
         };
 
-        self.connect_registry()
-            .await?
-            .complete_upload(Request::new(req))
-            .await?;
+        registry_call!(self, complete_upload, req)?;
 
         Ok(())
     }
 
-    async fn get_write_sink_for_upload(
+    /**
+     * Streams data to an upload the backend has offset bytes of. Gives the size of the upload
+     * afterwards.
+     */
+    async fn write_upload(
         &self,
-        repo_name: &RepoName,
-        uuid: &Uuid,
-    ) -> Result<impl Write + Seek, Error> {
+        repo_name: &str,
+        uuid: &str,
+        offset: u64,
+        data: &mut (dyn AsyncRead + Unpin + Send),
+    ) -> Result<u64, Error> {
         info!(
-            "Getting write location for blob in repo {} with upload id {}",
-            repo_name, uuid
+            "Writing to upload {} in repo {} from {}",
+            uuid, repo_name, offset
         );
-        let br = UploadRef {
-            uuid: uuid.0.clone(),
-            repo_name: repo_name.0.clone(),
+        let mut upload = Some(UploadRef {
+            uuid: uuid.to_string(),
+            repo_name: repo_name.to_string(),
+        });
+        let (tx, rx) = mpsc::channel(4);
+
+        // The upload and offset go in the first chunk
+        let send = send_chunks(data, tx, |data| UploadChunk {
+            offset: if upload.is_some() { offset } else { 0 },
+            upload: upload.take(),
+            data,
+        });
+        let call = async {
+            let chunks = ReceiverStream::new(rx);
+            match &self.backend {
+                Backend::Grpc(grpc) => {
+                    RegistryClient::new(grpc.channel().await?)
+                        .write_blob(Request::new(chunks))
+                        .await
+                }
+                Backend::Embedded(server) => server.write_blob_stream(chunks.map(Ok)).await,
+            }
         };
+        let (sent, written) = futures::join!(send, call);
 
-        let resp = self
-            .connect_registry()
-            .await?
-            .get_write_location_for_blob(Request::new(br))
-            .await?
-            .into_inner();
+        let size = written?.into_inner().size;
+        sent?;
+        Ok(size)
+    }
+
+    async fn get_upload_status(&self, repo_name: &str, uuid: &str) -> Result<u64, Error> {
+        info!("Getting status of upload {} in repo {}", uuid, repo_name);
+        let ur = UploadRef {
+            uuid: uuid.to_string(),
+            repo_name: repo_name.to_string(),
+        };
 
-        //For the moment we know it's a file location
-        let file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(resp.path)?;
-        Ok(file)
+        let resp = registry_call!(self, get_upload_status, ur)?;
+
+        Ok(resp.size)
     }
 
-    async fn upload_manifest<'a>(
+    async fn cancel_upload(&self, repo_name: &str, uuid: &str) -> Result<(), Error> {
+        info!("Cancelling upload {} in repo {}", uuid, repo_name);
+        let ur = UploadRef {
+            uuid: uuid.to_string(),
+            repo_name: repo_name.to_string(),
+        };
+
+        registry_call!(self, cancel_upload, ur)?;
+        Ok(())
+    }
+
+    async fn upload_manifest(
         &self,
         repo_name: &RepoName,
         reference: &str,
-        manifest: &mut Box<dyn Read + 'a>,
+        digest: Option<&Digest>,
+        manifest: &mut (dyn AsyncRead + Unpin + Send),
     ) -> Result<types::VerifiedManifest, RegistryError> {
-        let (mut sink_loc, uuid) = self
-            .get_write_sink_for_manifest(repo_name, reference)
-            .await
-            .map_err(|e| {
-                let e = e.downcast::<tonic::Status>();
-                if let Ok(ts) = e {
-                    match ts.code() {
-                        Code::InvalidArgument => RegistryError::InvalidName,
-                        _ => RegistryError::Internal,
-                    }
-                } else {
-                    RegistryError::Internal
-                }
-            })?;
-
-        io::copy(manifest, &mut sink_loc).map_err(|e| {
-            warn!("Error wirting out manifest {:?}", e);
-            RegistryError::Internal
-        })?;
-
-        self.verify_manifest(repo_name, reference, &uuid)
+        self.write_manifest(repo_name, reference, digest, manifest)
             .await
             .map_err(|e| {
                 let e = e.downcast::<tonic::Status>();
                 if let Ok(ts) = e {
                     match ts.code() {
+                        Code::PermissionDenied => RegistryError::InvalidName,
                         Code::InvalidArgument => RegistryError::InvalidManifest,
+                        Code::FailedPrecondition => RegistryError::MissingManifests(
+                            serde_json::from_slice(ts.details()).unwrap_or_default(),
+                        ),
                         _ => RegistryError::Internal,
                     }
                 } else {
+                    warn!("Error writing out manifest {:?}", e);
                     RegistryError::Internal
                 }
             })
     }
 
-    async fn get_write_sink_for_manifest(
+    async fn get_reader_for_manifest(
         &self,
         repo_name: &RepoName,
         reference: &str,
-    ) -> Result<(impl Write, String), Error> {
-        info!(
-            "Getting write location for manifest in repo {} with ref {}",
-            repo_name, reference
-        );
+    ) -> Result<ManifestReader, Error> {
+        info!("Reading manifest {} with ref {}", repo_name, reference);
         let mr = ManifestRef {
             reference: reference.to_owned(),
             repo_name: repo_name.0.clone(),
         };
+        let mut chunks = registry_stream!(self, read_manifest, mr)?;
 
-        let resp = self
-            .connect_registry()
-            .await?
-            .get_write_details_for_manifest(Request::new(mr))
+        // The first chunk has the digest and content type
+        let first = chunks
+            .try_next()
             .await?
-            .into_inner();
+            .ok_or_else(|| format_err!("No data for manifest {}:{}", repo_name, reference))?;
+        let mut data = first.data;
+        while let Some(chunk) = chunks.try_next().await? {
+            data.extend(chunk.data);
+        }
 
-        //For the moment we know it's a file location
-        //Manifests don't append; just overwrite
-        let file = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open(resp.path)?;
-        Ok((file, resp.uuid))
+        let mr = ManifestReader {
+            reader: Box::new(Cursor::new(data)),
+            content_type: first.content_type,
+            digest: digest::parse(&first.digest)?,
+        };
+        Ok(mr)
     }
 
-    async fn get_reader_for_manifest(
+    async fn get_info_for_manifest(
         &self,
         repo_name: &RepoName,
         reference: &str,
-    ) -> Result<ManifestReader, Error> {
-        info!(
-            "Getting read location for {} with ref {}",
-            repo_name, reference
-        );
+    ) -> Result<ManifestInfo, Error> {
+        info!("Getting info for {} with ref {}", repo_name, reference);
         let mr = ManifestRef {
             reference: reference.to_owned(),
             repo_name: repo_name.0.clone(),
         };
-        let resp = self
-            .connect_registry()
-            .await?
-            .get_read_location_for_manifest(Request::new(mr))
-            .await?
-            .into_inner();
+        let resp = registry_call!(self, get_manifest_info, mr)?;
 
-        //For the moment we know it's a file location
-        let file = OpenOptions::new().read(true).open(resp.path)?;
-        let digest = digest::parse(&resp.digest)?;
-        let mr = ManifestReader {
-            reader: Box::new(file),
+        Ok(ManifestInfo {
             content_type: resp.content_type,
-            digest,
-        };
-        Ok(mr)
+            digest: digest::parse(&resp.digest)?,
+            size: resp.size,
+        })
     }
 
     async fn get_manifest_history(
@@ -547,15 +924,10 @@ impl ClientInterface {
             limit,
             last_digest: last_digest.to_owned(),
         };
-        let mut stream = self
-            .connect_registry()
-            .await?
-            .get_manifest_history(Request::new(mr))
-            .await?
-            .into_inner();
+        let mut stream = registry_stream!(self, get_manifest_history, mr)?;
         let mut history = ManifestHistory::new(format!("{}:{}", repo_name, reference));
 
-        while let Some(entry) = stream.message().await? {
+        while let Some(entry) = stream.try_next().await? {
             let ts = if let Some(date) = entry.date {
                 chrono::Utc.timestamp(date.seconds, date.nanos.try_into().unwrap())
             } else {
@@ -573,28 +945,55 @@ impl ClientInterface {
         repo_name: &RepoName,
         digest: &Digest,
     ) -> Result<BlobReader, Error> {
-        info!("Getting read location for blob {} in {}", digest, repo_name);
-        let br = BlobRef {
+        info!("Reading blob {} in {}", digest, repo_name);
+        // The response needs the size up front. The data is only streamed once the response is
+        // read, from wherever the reader has been moved to, e.g. the start of a range.
+        let info = self.get_info_for_blob(repo_name, digest).await?;
+        let req = ReadBlobRequest {
             digest: digest.to_string(),
             repo_name: repo_name.0.clone(),
+            offset: 0,
+        };
+        let source = match &self.backend {
+            Backend::Grpc(grpc) => BlobSource::Grpc(grpc.channel().await?),
+            Backend::Embedded(server) => BlobSource::Embedded(server.clone()),
         };
 
-        let resp = self
-            .connect_registry()
-            .await?
-            .get_read_location_for_blob(Request::new(br))
-            .await?
-            .into_inner();
-
-        //For the moment we know it's a file location
-        let file = OpenOptions::new().read(true).open(resp.path)?;
         let reader = BlobReader {
-            reader: Box::new(file),
+            size: info.size,
+            reader: Box::new(BlobStream {
+                source,
+                request: req,
+                size: info.size,
+                pos: 0,
+                opening: None,
+                chunks: None,
+                chunk: Cursor::new(Vec::new()),
+            }),
             digest: digest.clone(),
         };
         Ok(reader)
     }
 
+    async fn get_info_for_blob(
+        &self,
+        repo_name: &RepoName,
+        digest: &Digest,
+    ) -> Result<BlobInfo, Error> {
+        info!("Getting info for blob {} in {}", digest, repo_name);
+        let br = BlobRef {
+            digest: digest.to_string(),
+            repo_name: repo_name.0.clone(),
+        };
+
+        let resp = registry_call!(self, get_blob_info, br)?;
+
+        Ok(BlobInfo {
+            digest: digest.clone(),
+            size: resp.size,
+        })
+    }
+
     async fn delete_blob_local(
         &self,
         repo_name: &RepoName,
@@ -606,60 +1005,83 @@ impl ClientInterface {
             repo_name: repo_name.0.clone(),
         };
 
-        self.connect_registry()
-            .await?
-            .delete_blob(Request::new(br))
-            .await?
-            .into_inner();
+        registry_call!(self, delete_blob, br)?;
         Ok(BlobDeleted {})
     }
 
-    async fn verify_manifest(
+    /**
+     * Streams a manifest to the backend, which checks it before saving and tagging it.
+     */
+    async fn write_manifest(
         &self,
         repo_name: &RepoName,
         reference: &str,
-        uuid: &str,
+        expected_digest: Option<&Digest>,
+        manifest: &mut (dyn AsyncRead + Unpin + Send),
     ) -> Result<types::VerifiedManifest, Error> {
-        info!(
-            "Verifying manifest {} in {} uuid {}",
-            reference, repo_name, uuid
-        );
-        let vmr = VerifyManifestRequest {
-            manifest: Some(ManifestRef {
-                reference: reference.to_owned(),
-                repo_name: repo_name.0.clone(),
-            }),
-            uuid: uuid.to_string(),
+        info!("Writing manifest {} in {}", reference, repo_name);
+        let mut mr = Some(ManifestRef {
+            reference: reference.to_owned(),
+            repo_name: repo_name.0.clone(),
+        });
+        let expected_digest = expected_digest.map(|d| d.to_string()).unwrap_or_default();
+        let (tx, rx) = mpsc::channel(4);
+
+        // The reference and expected digest go in the first chunk
+        let send = send_chunks(manifest, tx, |data| ManifestUploadChunk {
+            digest: if mr.is_some() {
+                expected_digest.clone()
+            } else {
+                String::new()
+            },
+            manifest: mr.take(),
+            data,
+        });
+        let call = async {
+            let chunks = ReceiverStream::new(rx);
+            match &self.backend {
+                Backend::Grpc(grpc) => {
+                    RegistryClient::new(grpc.channel().await?)
+                        .write_manifest(Request::new(chunks))
+                        .await
+                }
+                Backend::Embedded(server) => server.write_manifest_stream(chunks.map(Ok)).await,
+            }
         };
+        let (sent, written) = futures::join!(send, call);
 
-        let resp = self
-            .connect_registry()
-            .await?
-            .verify_manifest(Request::new(vmr))
-            .await?
-            .into_inner();
+        let resp = written?.into_inner();
+        sent?;
 
         let digest = digest::parse(&resp.digest)?;
-        let vm = create_verified_manifest(repo_name.clone(), digest, reference.to_string());
+        let subject = if resp.subject.is_empty() {
+            None
+        } else {
+            Some(digest::parse(&resp.subject)?)
+        };
+        let vm =
+            create_verified_manifest(repo_name.clone(), digest, reference.to_string(), subject);
         Ok(vm)
     }
 
-    async fn delete_by_manifest(
+    /**
+     * Deletes a manifest by digest, or just the tag if the reference is a tag.
+     */
+    async fn delete_by_reference(
         &self,
         repo_name: &RepoName,
-        digest: &Digest,
+        reference: &str,
     ) -> Result<ManifestDeleted, Error> {
-        info!("Attempting to delete manifest {} in {}", digest, repo_name);
+        info!(
+            "Attempting to delete manifest {} in {}",
+            reference, repo_name
+        );
         let mr = ManifestRef {
-            reference: digest.to_string(),
+            reference: reference.to_string(),
             repo_name: repo_name.0.clone(),
         };
 
-        self.connect_registry()
-            .await?
-            .delete_manifest(Request::new(mr))
-            .await?
-            .into_inner();
+        registry_call!(self, delete_manifest, mr)?;
         Ok(ManifestDeleted {})
     }
 
@@ -673,15 +1095,10 @@ impl ClientInterface {
             limit,
             last_repo: last_repo.to_string(),
         };
-        let mut stream = self
-            .connect_registry()
-            .await?
-            .get_catalog(Request::new(cr))
-            .await?
-            .into_inner();
+        let mut stream = registry_stream!(self, get_catalog, cr)?;
         let mut catalog = RepoCatalog::new();
 
-        while let Some(ce) = stream.message().await? {
+        while let Some(ce) = stream.try_next().await? {
             catalog.insert(ce.repo_name.to_owned());
         }
 
@@ -704,21 +1121,48 @@ impl ClientInterface {
             last_tag: last_tag.to_string(),
         };
 
-        let mut stream = self
-            .connect_registry()
-            .await?
-            .list_tags(Request::new(ltr))
-            .await?
-            .into_inner();
+        let mut stream = registry_stream!(self, list_tags, ltr)?;
         let mut list = TagList::new(repo_name.to_string());
 
-        while let Some(tag) = stream.message().await? {
+        while let Some(tag) = stream.try_next().await? {
             list.insert(tag.tag.to_owned());
         }
 
         Ok(list)
     }
 
+    async fn list_referrers(
+        &self,
+        repo_name: &str,
+        digest: &Digest,
+        artifact_type: &str,
+    ) -> Result<Vec<Referrer>, Error> {
+        info!(
+            "Getting referrers of {} in {} artifact type {}",
+            digest, repo_name, artifact_type
+        );
+        let rr = ReferrersRequest {
+            repo_name: repo_name.to_string(),
+            digest: digest.to_string(),
+            artifact_type: artifact_type.to_string(),
+        };
+
+        let mut stream = registry_stream!(self, list_referrers, rr)?;
+        let mut referrers = Vec::new();
+
+        while let Some(r) = stream.try_next().await? {
+            referrers.push(Referrer {
+                media_type: r.media_type,
+                digest: r.digest,
+                size: r.size,
+                artifact_type: Some(r.artifact_type).filter(|t| !t.is_empty()),
+                annotations: r.annotations,
+            });
+        }
+
+        Ok(referrers)
+    }
+
     /**
      * Returns an AdmissionReview object with the AdmissionResponse completed with details of vaildation.
      */
@@ -742,12 +1186,17 @@ impl ClientInterface {
             host_names: host_names.to_vec(),
         };
 
-        let resp = self
-            .connect_admission_controller()
-            .await?
-            .validate_admission(Request::new(ar))
-            .await?
-            .into_inner();
+        let resp = match &self.backend {
+            Backend::Grpc(grpc) => {
+                AdmissionControllerClient::new(grpc.channel().await?)
+                    .validate_admission(Request::new(ar))
+                    .await?
+            }
+            Backend::Embedded(server) => {
+                AdmissionController::validate_admission(&**server, Request::new(ar)).await?
+            }
+        }
+        .into_inner();
 
         //TODO: again, this should be an automatic conversion
         let st = if resp.is_allowed {
@@ -778,18 +1227,8 @@ impl ClientInterface {
     */
     async fn is_healthy(&self) -> types::HealthResponse {
         debug!("Calling health check");
-        let mut client = match self.connect_registry().await {
-            Ok(cl) => cl,
-            Err(_) => {
-                return types::HealthResponse {
-                    is_healthy: false,
-                    message: "Failed to connect to registry".to_string(),
-                }
-            }
-        };
-
-        let req = Request::new(HealthRequest {});
-        let resp = match client.is_healthy(req).await {
+        let req = HealthRequest {};
+        let resp = match registry_call!(self, is_healthy, req) {
             Ok(r) => r,
             Err(e) => {
                 return types::HealthResponse {
@@ -798,11 +1237,10 @@ impl ClientInterface {
                 }
             }
         };
-        let response_value = resp.into_inner();
 
         types::HealthResponse {
             is_healthy: true,
-            message: response_value.message,
+            message: resp.message,
         }
     }
 
@@ -813,18 +1251,8 @@ impl ClientInterface {
     */
     async fn is_ready(&self) -> types::ReadinessResponse {
         debug!("Calling readiness check");
-        let mut client = match self.connect_registry().await {
-            Ok(cl) => cl,
-            Err(_) => {
-                return types::ReadinessResponse {
-                    is_ready: false,
-                    message: "Failed to connect to registry".to_string(),
-                }
-            }
-        };
-
-        let req = Request::new(ReadinessRequest {});
-        let resp = match client.is_ready(req).await {
+        let req = ReadinessRequest {};
+        let resp = match registry_call!(self, is_ready, req) {
             Ok(r) => r,
             Err(e) => {
                 return types::ReadinessResponse {
@@ -833,10 +1261,9 @@ impl ClientInterface {
                 }
             }
         };
-        let response_value = resp.into_inner();
         types::ReadinessResponse {
             is_ready: true,
-            message: response_value.message,
+            message: resp.message,
         }
     }
 
@@ -847,16 +1274,52 @@ impl ClientInterface {
     */
     async fn get_metrics(&self) -> Result<MetricsResponse, Error> {
         debug!("Getting metrics");
-        let req = Request::new(MetricsRequest {});
-        let resp = self
-            .connect_registry()
-            .await?
-            .get_metrics(req)
-            .await?
-            .into_inner();
+        let req = MetricsRequest {};
+        let resp = registry_call!(self, get_metrics, req)?;
 
         Ok(MetricsResponse {
             metrics: resp.metrics,
         })
     }
+    /**
+     Garbage collection call.
+
+     Removes blobs no longer referenced by any manifest, or just reports them for a dry run.
+    */
+    async fn collect_garbage_internal(
+        &self,
+        dry_run: bool,
+    ) -> Result<GarbageCollectionReport, Error> {
+        info!("Collecting garbage (dry run: {})", dry_run);
+        let req = GarbageCollectionRequest { dry_run };
+        let resp = registry_call!(self, collect_garbage, req)?;
+
+        Ok(GarbageCollectionReport {
+            dry_run: resp.dry_run,
+            referenced_blobs: resp.referenced_blobs,
+            removed_blobs: resp.removed_blobs,
+            reclaimed_bytes: resp.reclaimed_bytes,
+            skipped_recent_blobs: resp.skipped_recent_blobs,
+        })
+    }
+
+    /**
+     Storage integrity check.
+
+     Re-hashes all blobs and checks tags and manifests are intact.
+    */
+    async fn fsck_internal(&self, repair: bool) -> Result<FsckReport, Error> {
+        info!("Checking storage (repair: {})", repair);
+        let req = FsckRequest { repair };
+        let resp = registry_call!(self, fsck, req)?;
+
+        Ok(FsckReport {
+            checked_blobs: resp.checked_blobs,
+            checked_tags: resp.checked_tags,
+            corrupt_blobs: resp.corrupt_blobs,
+            quarantined_blobs: resp.quarantined_blobs,
+            errors: resp.errors,
+            orphaned_scratch_files: resp.orphaned_scratch_files,
+        })
+    }
 }
@@ -1,17 +1,10 @@
-#![feature(proc_macro_hygiene, decl_macro)]
-#![feature(plugin)]
-#![feature(seek_stream_len)]
-
 #[macro_use]
 extern crate failure;
-extern crate base64;
 extern crate futures;
 extern crate hostname;
 extern crate hyper;
 #[macro_use]
 extern crate rocket;
-#[macro_use]
-extern crate rocket_contrib;
 extern crate argon2;
 extern crate chrono;
 extern crate data_encoding;
@@ -36,12 +29,15 @@ extern crate serde_derive;
 extern crate quickcheck;
 
 use failure::Error;
-use rand::rngs::OsRng;
 use rocket::fairing;
 use std::env;
 use std::fs;
+use std::net::ToSocketAddrs;
 use std::path::Path;
 use std::thread;
+use std::time::Duration;
+use trow_server::transport::{self, GrpcAddr};
+use trow_server::S3Config;
 use uuid::Uuid;
 
 mod client_interface;
@@ -56,7 +52,6 @@ mod users;
 
 use chrono::Utc;
 use client_interface::ClientInterface;
-use rand::RngCore;
 use std::io::Write;
 
 //TODO: Make this take a cause or description
@@ -80,6 +75,7 @@ pub struct TrowConfig {
     addr: NetAddr,
     tls: Option<TlsConfig>,
     grpc: GrpcConfig,
+    embedded_backend: bool,
     host_names: Vec<String>,
     proxy_hub: bool,
     hub_user: Option<String>,
@@ -88,6 +84,9 @@ pub struct TrowConfig {
     allow_images: Vec<String>,
     deny_prefixes: Vec<String>,
     deny_images: Vec<String>,
+    s3: Option<S3Config>,
+    upload_timeout: Option<Duration>,
+    allowed_artifact_types: Vec<String>,
     dry_run: bool,
     token_secret: String,
     user: Option<UserConfig>,
@@ -95,7 +94,21 @@ pub struct TrowConfig {
 
 #[derive(Clone, Debug)]
 struct GrpcConfig {
-    listen: String,
+    listen: GrpcAddr,
+    // Backend started separately, which the frontend connects to instead of starting its own
+    backend: Option<GrpcAddr>,
+    tls: Option<GrpcTlsConfig>,
+}
+
+/*
+ * Mutual TLS between the frontend and backend. The backend serves with the cert and only accepts
+ * frontends with a cert signed by the CA, and vice versa.
+ */
+#[derive(Clone, Debug)]
+struct GrpcTlsConfig {
+    cert_file: String,
+    key_file: String,
+    ca_file: String,
 }
 
 #[derive(Clone, Debug)]
@@ -110,16 +123,14 @@ struct UserConfig {
     hash_encoded: String, //Surprised not bytes
 }
 
-fn init_trow_server(config: TrowConfig) -> Result<std::thread::JoinHandle<()>, Error> {
-    debug!("Starting Trow server");
-
+fn build_trow_server(config: TrowConfig) -> Result<trow_server::TrowServerBuilder, Error> {
     //Could pass full config here.
     //Pros: less work, new args added automatically
     //-s: ties frontend to backend, some uneeded/unwanted vars
 
     let ts = trow_server::build_server(
         &config.data_dir,
-        config.grpc.listen.parse::<std::net::SocketAddr>()?,
+        config.grpc.listen,
         config.proxy_hub,
         config.hub_user,
         config.hub_pass,
@@ -128,12 +139,28 @@ fn init_trow_server(config: TrowConfig) -> Result<std::thread::JoinHandle<()>, E
         config.deny_prefixes,
         config.deny_images,
     );
-    //TODO: probably shouldn't be reusing this cert
-    let ts = if let Some(tls) = config.tls {
+    let ts = if let Some(tls) = config.grpc.tls {
         ts.add_tls(fs::read(tls.cert_file)?, fs::read(tls.key_file)?)
+            .add_root_cert(fs::read(tls.ca_file)?)
+    } else {
+        ts
+    };
+    let ts = if let Some(s3) = config.s3 {
+        ts.add_s3_storage(s3)
     } else {
         ts
     };
+    let ts = if let Some(timeout) = config.upload_timeout {
+        ts.set_upload_timeout(timeout)
+    } else {
+        ts
+    };
+    Ok(ts.set_allowed_artifact_types(config.allowed_artifact_types))
+}
+
+fn init_trow_server(config: TrowConfig) -> Result<std::thread::JoinHandle<()>, Error> {
+    debug!("Starting Trow server");
+    let ts = build_trow_server(config)?;
 
     Ok(thread::spawn(move || {
         ts.start_trow_sync();
@@ -175,7 +202,7 @@ impl TrowBuilder {
     pub fn new(
         data_dir: String,
         addr: NetAddr,
-        listen: String,
+        listen: GrpcAddr,
         host_names: Vec<String>,
         proxy_hub: bool,
         allow_prefixes: Vec<String>,
@@ -188,7 +215,12 @@ impl TrowBuilder {
             data_dir,
             addr,
             tls: None,
-            grpc: GrpcConfig { listen },
+            grpc: GrpcConfig {
+                listen,
+                backend: None,
+                tls: None,
+            },
+            embedded_backend: false,
             host_names,
             proxy_hub,
             hub_user: None,
@@ -197,6 +229,9 @@ impl TrowBuilder {
             allow_images,
             deny_prefixes,
             deny_images,
+            s3: None,
+            upload_timeout: None,
+            allowed_artifact_types: vec![],
             dry_run,
             token_secret: Uuid::new_v4().to_string(),
             user: None,
@@ -223,6 +258,50 @@ impl TrowBuilder {
         self
     }
 
+    pub fn with_s3_storage(&mut self, config: S3Config) -> &mut TrowBuilder {
+        self.config.s3 = Some(config);
+        self
+    }
+
+    pub fn with_upload_timeout(&mut self, timeout: Duration) -> &mut TrowBuilder {
+        self.config.upload_timeout = Some(timeout);
+        self
+    }
+
+    /// Only artifacts of these types can be pushed, besides images. Any type is allowed if empty.
+    pub fn with_allowed_artifact_types(&mut self, artifact_types: Vec<String>) -> &mut TrowBuilder {
+        self.config.allowed_artifact_types = artifact_types;
+        self
+    }
+
+    /// Calls the backend in-process, instead of serving it over gRPC on the listen address.
+    pub fn with_embedded_backend(&mut self) -> &mut TrowBuilder {
+        self.config.embedded_backend = true;
+        self
+    }
+
+    /// Connects to a backend started elsewhere, e.g. with start_backend, rather than starting one.
+    pub fn with_remote_backend(&mut self, addr: GrpcAddr) -> &mut TrowBuilder {
+        self.config.grpc.backend = Some(addr);
+        self
+    }
+
+    /// Uses mutual TLS between the frontend and backend, each presenting the PEM encoded cert and
+    /// checking the other's was signed by the CA.
+    pub fn with_grpc_tls(
+        &mut self,
+        cert_file: String,
+        key_file: String,
+        ca_file: String,
+    ) -> &mut TrowBuilder {
+        self.config.grpc.tls = Some(GrpcTlsConfig {
+            cert_file,
+            key_file,
+            ca_file,
+        });
+        self
+    }
+
     pub fn with_hub_auth(&mut self, hub_user: String, token: String) -> &mut TrowBuilder {
         self.config.hub_pass = Some(token);
         self.config.hub_user = Some(hub_user);
@@ -230,42 +309,41 @@ impl TrowBuilder {
     }
 
     fn build_rocket_config(&self) -> Result<rocket::config::Config, Error> {
-        // When run in production, Rocket wants a secret key for private cookies.
-        // As we don't use private cookies, we just generate it here.
-        let mut key = [0u8; 32];
-        OsRng.fill_bytes(&mut key);
-        let secret_key = base64::encode(&key);
-
-        /*
-        Keep Alive has to be turned off to mitigate issue whereby some transfers would be cut off.
-        Seems to be caused by an old version of hyper in Rocket.
-        Keep Alive can be restored when Rocket is upgraded or by moving to different framework.
-        See #24
-        */
-        let mut cfg = rocket::config::Config::build(rocket::config::Environment::Production)
-            .address(self.config.addr.host.clone())
-            .port(self.config.addr.port)
-            .keep_alive(0) // Needed to mitigate #24. See above.
-            .secret_key(secret_key)
-            .workers(256);
-
-        if let Some(ref tls) = self.config.tls {
-            if !(Path::new(&tls.cert_file).is_file() && Path::new(&tls.key_file).is_file()) {
-                return  Err(format_err!("Trow requires a TLS certificate and key, but failed to find them. \nExpected to find TLS certificate at {} and key at {}", tls.cert_file, tls.key_file));
+        let address = (self.config.addr.host.as_str(), self.config.addr.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| format_err!("Failed to resolve {}", self.config.addr.host))?;
+
+        let tls = match self.config.tls {
+            Some(ref tls) => {
+                if !(Path::new(&tls.cert_file).is_file() && Path::new(&tls.key_file).is_file()) {
+                    return  Err(format_err!("Trow requires a TLS certificate and key, but failed to find them. \nExpected to find TLS certificate at {} and key at {}", tls.cert_file, tls.key_file));
+                }
+                Some(rocket::config::TlsConfig::from_paths(
+                    &tls.cert_file,
+                    &tls.key_file,
+                ))
             }
-            cfg = cfg.tls(tls.cert_file.clone(), tls.key_file.clone());
-        }
-        let cfg = cfg.finalize()?;
-        Ok(cfg)
+            None => None,
+        };
+
+        Ok(rocket::config::Config {
+            address: address.ip(),
+            port: self.config.addr.port,
+            tls,
+            ..rocket::config::Config::release_default()
+        })
     }
 
     pub fn start(&self) -> Result<(), Error> {
         init_logger()?;
 
-        let rocket_config = &self.build_rocket_config()?;
+        let rocket_config = self.build_rocket_config()?;
 
         // Start GRPC Backend thread.
-        let _backend_thread = init_trow_server(self.config.clone())?;
+        if !self.config.embedded_backend && self.config.grpc.backend.is_none() {
+            init_trow_server(self.config.clone())?;
+        }
 
         println!(
             "Starting Trow {} on {}:{}",
@@ -305,48 +383,74 @@ impl TrowBuilder {
             println!("Dry run, exiting.");
             std::process::exit(0);
         }
-        let s = format!("https://{}", self.config.grpc.listen);
-        let ci: ClientInterface = build_handlers(s)?;
-
-        rocket::custom(rocket_config.clone())
-            .manage(self.config.clone())
-            .manage(ci)
-            .attach(fairing::AdHoc::on_attach(
-                "SIGTERM handler",
-                |r| match attach_sigterm() {
-                    Ok(_) => Ok(r),
-                    Err(_) => Err(r),
-                },
-            ))
-            .attach(fairing::AdHoc::on_response(
-                "Set API Version Header",
-                |_, resp| {
-                    //Only serve v2. If we also decide to support older clients, this will to be dropped on some paths
-                    resp.set_raw_header("Docker-Distribution-API-Version", "registry/2.0");
-                },
-            ))
-            .attach(fairing::AdHoc::on_launch("Launch Message", |_| {
-                println!("Trow is up and running!");
-            }))
-            .mount("/", routes::routes())
-            .register(routes::catchers())
-            .launch();
+        let config = self.config.clone();
+
+        rocket::execute(async move {
+            // The client has to be created on the runtime that serves the requests
+            let ci: ClientInterface = if config.embedded_backend {
+                ClientInterface::embedded(build_trow_server(config.clone())?.build_embedded()?)
+            } else {
+                build_handlers(&config.grpc)?
+            };
+
+            rocket::custom(rocket_config)
+                .manage(config)
+                .manage(ci)
+                .attach(fairing::AdHoc::on_response(
+                    "Set API Version Header",
+                    |_, resp| {
+                        Box::pin(async move {
+                            //Only serve v2. If we also decide to support older clients, this will to be dropped on some paths
+                            resp.set_raw_header("Docker-Distribution-API-Version", "registry/2.0");
+                        })
+                    },
+                ))
+                .attach(fairing::AdHoc::on_liftoff("Launch Message", |_| {
+                    Box::pin(async move {
+                        println!("Trow is up and running!");
+                    })
+                }))
+                .mount("/", routes::routes())
+                .register("/", routes::catchers())
+                .launch()
+                .await
+                .map_err(|e| format_err!("Failed to run Trow: {}", e))?;
+
+            Ok(())
+        })
+    }
 
+    /**
+     * Runs only the backend, serving gRPC on the listen address for frontends started with
+     * with_remote_backend. Blocks until the backend stops.
+     */
+    pub fn start_backend(&self) -> Result<(), Error> {
+        init_logger()?;
+        println!(
+            "Starting Trow {} backend on {}",
+            env!("CARGO_PKG_VERSION"),
+            self.config.grpc.listen
+        );
+        if self.config.dry_run {
+            println!("Dry run, exiting.");
+            std::process::exit(0);
+        }
+        build_trow_server(self.config.clone())?.start_trow_sync();
         Ok(())
     }
 }
 
-fn attach_sigterm() -> Result<(), Error> {
-    ctrlc::set_handler(|| {
-        info!("SIGTERM caught, shutting down...");
-        std::process::exit(0);
-    })
-    .map_err(|e| e.into())
-}
-
-pub fn build_handlers(listen_addr: String) -> Result<ClientInterface, Error> {
-    debug!("Address for backend: {}", listen_addr);
-
-    //TODO this function is useless currently
-    ClientInterface::new(listen_addr)
+fn build_handlers(grpc: &GrpcConfig) -> Result<ClientInterface, Error> {
+    let addr = grpc.backend.as_ref().unwrap_or(&grpc.listen);
+    debug!("Address for backend: {}", addr);
+
+    let tls = match grpc.tls {
+        Some(ref tls) => Some(transport::client_tls(
+            &fs::read(&tls.cert_file)?,
+            &fs::read(&tls.key_file)?,
+            &fs::read(&tls.ca_file)?,
+        )?),
+        None => None,
+    };
+    Ok(ClientInterface::new(addr.clone(), tls))
 }
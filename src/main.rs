@@ -1,11 +1,15 @@
 extern crate clap;
 extern crate trow;
+extern crate trow_server;
 
 use clap::{Arg, ArgMatches};
 use std::env;
 use std::fs::File;
 use std::io::prelude::*;
+use std::time::Duration;
 use trow::{NetAddr, TrowBuilder};
+use trow_server::transport::GrpcAddr;
+use trow_server::S3Config;
 
 const PROGRAM_NAME: &str = "Trow";
 const PROGRAM_DESC: &str = "\nThe Cluster Registry";
@@ -82,6 +86,23 @@ fn parse_args<'a>() -> ArgMatches<'a> {
             .help("Don't acutally run Trow, just validate arguments. For testing purposes.")
             .takes_value(false),
         )
+        .arg(
+            Arg::with_name("fsck")
+            .long("fsck")
+            .value_name("fsck")
+            .help("Don't run Trow, instead check the data directory for corrupt blobs and broken tags and manifests.
+Exits with a non-zero status if problems are found.")
+            .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("repair")
+            .long("repair")
+            .value_name("repair")
+            .help("Used with --fsck. Moves corrupt blobs into a quarantine directory alongside the blobs directory.
+Trow must not be running on the same data directory.")
+            .takes_value(false)
+            .requires("fsck"),
+        )
         .arg(
             Arg::with_name("allow-docker-official")
             .long("allow-docker-official")
@@ -196,6 +217,121 @@ Must be used with --hub-token or --hub-token-file")
             .help("Location of file with token that can be used for accessing the Docker Hub, used when proxying Docker Hub images")
             .takes_value(true)
         )
+        .arg(
+            Arg::with_name("upload-timeout")
+            .long("upload-timeout")
+            .value_name("upload-timeout")
+            .help(&format!("Seconds an upload can be idle before it is abandoned and its data removed. Defaults to {}.",
+                trow_server::DEFAULT_UPLOAD_TIMEOUT.as_secs()))
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("allow-artifact-types")
+            .long("allow-artifact-types")
+            .value_name("allow-artifact-types")
+            .help("Comma separated list of artifact types that can be pushed besides container images, e.g. 'application/vnd.cncf.helm.config.v1+json'.
+An artifact's type is its artifactType, or the media type of its config if it has none. By default any type can be pushed")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("grpc-backend")
+            .long("grpc-backend")
+            .value_name("grpc-backend")
+            .help("Serve the backend over gRPC on the --grpc-listen address and have the frontend connect to it, instead of calling it in-process.")
+            .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("grpc-listen")
+            .long("grpc-listen")
+            .value_name("grpc-listen")
+            .help("Address the backend serves gRPC on, as host:port or unix:<path> for a Unix domain socket. Implies --grpc-backend. Defaults to 127.0.0.1:51000")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("backend-addr")
+            .long("backend-addr")
+            .value_name("backend-addr")
+            .help("Connect to a backend started with --backend-only at this address, as host:port or unix:<path>, instead of starting one.")
+            .takes_value(true)
+            .conflicts_with("backend-only")
+        )
+        .arg(
+            Arg::with_name("backend-only")
+            .long("backend-only")
+            .value_name("backend-only")
+            .help("Only run the backend, serving gRPC on the --grpc-listen address for frontends started with --backend-addr.")
+            .takes_value(false)
+        )
+        .arg(
+            Arg::with_name("grpc-cert")
+            .long("grpc-cert")
+            .value_name("grpc-cert")
+            .help("Path to the TLS certificate the frontend and backend present to each other over gRPC. The backend's must be for its host, or localhost over a Unix socket. Must be used with --grpc-key and --grpc-ca")
+            .takes_value(true)
+            .requires_all(&["grpc-key", "grpc-ca"])
+        )
+        .arg(
+            Arg::with_name("grpc-key")
+            .long("grpc-key")
+            .value_name("grpc-key")
+            .help("Path to the key for --grpc-cert")
+            .takes_value(true)
+            .requires("grpc-cert")
+        )
+        .arg(
+            Arg::with_name("grpc-ca")
+            .long("grpc-ca")
+            .value_name("grpc-ca")
+            .help("Path to the CA certificate the other side of the gRPC connection must be signed by. Must be used with --grpc-cert")
+            .takes_value(true)
+            .requires("grpc-cert")
+        )
+        .arg(
+            Arg::with_name("s3-endpoint")
+            .long("s3-endpoint")
+            .value_name("s3-endpoint")
+            .help("Store images in an S3 compatible object store at this URL (e.g. https://s3.eu-west-1.amazonaws.com) instead of the data directory.
+Uploads in progress and a cache of downloaded blobs are still kept in the data directory. Must be used with --s3-bucket")
+            .takes_value(true)
+            .requires("s3-bucket")
+        )
+        .arg(
+            Arg::with_name("s3-bucket")
+            .long("s3-bucket")
+            .value_name("s3-bucket")
+            .help("Name of the bucket to store images in. Must be used with --s3-endpoint")
+            .takes_value(true)
+            .requires("s3-endpoint")
+        )
+        .arg(
+            Arg::with_name("s3-region")
+            .long("s3-region")
+            .value_name("s3-region")
+            .help("Region of the S3 bucket. Defaults to us-east-1")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("s3-access-key")
+            .long("s3-access-key")
+            .value_name("s3-access-key")
+            .help("Access key for the S3 bucket. Defaults to the AWS_ACCESS_KEY_ID environment variable")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("s3-secret-key")
+            .long("s3-secret-key")
+            .value_name("s3-secret-key")
+            .help("Secret key for the S3 bucket. Defaults to the AWS_SECRET_ACCESS_KEY environment variable")
+            .takes_value(true)
+        )
+        .arg(
+            Arg::with_name("s3-cache-size")
+            .long("s3-cache-size")
+            .value_name("s3-cache-size")
+            .help(&format!("Megabytes of blobs downloaded from the S3 bucket to keep in the data directory. The least recently used are removed first. Defaults to {}.",
+                trow_server::DEFAULT_S3_CACHE_SIZE / (1024 * 1024)))
+            .takes_value(true)
+        )
         .get_matches()
 }
 
@@ -205,6 +341,48 @@ fn parse_list(names: &str) -> Vec<String> {
     ret_str.split_whitespace().map(|x| x.to_owned()).collect()
 }
 
+fn parse_grpc_addr(addr: &str) -> GrpcAddr {
+    addr.parse().unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+fn parse_s3_config(matches: &ArgMatches) -> Option<S3Config> {
+    let endpoint = matches.value_of("s3-endpoint")?;
+    let arg_or_env = |arg: &str, var: &str| {
+        matches
+            .value_of(arg)
+            .map(|v| v.to_string())
+            .or_else(|| env::var(var).ok())
+            .unwrap_or_else(|| {
+                eprintln!("Either --{} or {} must be set to use S3 storage", arg, var);
+                std::process::exit(1);
+            })
+    };
+
+    Some(S3Config {
+        endpoint: endpoint.to_string(),
+        bucket: matches.value_of("s3-bucket").unwrap().to_string(),
+        region: matches
+            .value_of("s3-region")
+            .unwrap_or("us-east-1")
+            .to_string(),
+        access_key: arg_or_env("s3-access-key", "AWS_ACCESS_KEY_ID"),
+        secret_key: arg_or_env("s3-secret-key", "AWS_SECRET_ACCESS_KEY"),
+        cache_size: matches
+            .value_of("s3-cache-size")
+            .map(|size| match size.parse::<u64>() {
+                Ok(size) => size * 1024 * 1024,
+                Err(_) => {
+                    eprintln!("--s3-cache-size must be a number of megabytes");
+                    std::process::exit(1);
+                }
+            })
+            .unwrap_or(trow_server::DEFAULT_S3_CACHE_SIZE),
+    })
+}
+
 fn main() {
     let matches = parse_args();
 
@@ -226,6 +404,20 @@ fn main() {
     let host_names_str = matches.value_of("names").unwrap_or(host);
     let host_names = parse_list(&host_names_str);
     let dry_run = matches.is_present("dry-run");
+    let s3 = parse_s3_config(&matches);
+
+    if matches.is_present("fsck") {
+        match trow_server::check_data_dir(data_path, s3, matches.is_present("repair")) {
+            Ok(report) => {
+                print!("{}", report);
+                std::process::exit(if report.has_errors() { 1 } else { 0 });
+            }
+            Err(e) => {
+                eprintln!("Error checking data directory {}:\n\n{}", data_path, e);
+                std::process::exit(1);
+            }
+        }
+    }
     let proxy_hub = matches.is_present("proxy-docker-hub");
 
     let mut allow_prefixes = parse_list(matches.value_of("allow-prefixes").unwrap_or(""));
@@ -244,10 +436,11 @@ fn main() {
         host: host.to_string(),
         port,
     };
+    let grpc_listen = parse_grpc_addr(matches.value_of("grpc-listen").unwrap_or("127.0.0.1:51000"));
     let mut builder = TrowBuilder::new(
         data_path.to_string(),
         addr,
-        "127.0.0.1:51000".to_string(),
+        grpc_listen,
         host_names,
         proxy_hub,
         allow_prefixes,
@@ -259,6 +452,40 @@ fn main() {
     if !no_tls {
         builder.with_tls(cert_path.to_string(), key_path.to_string());
     }
+    let grpc_backend = [
+        "grpc-backend",
+        "grpc-listen",
+        "backend-addr",
+        "backend-only",
+    ]
+    .iter()
+    .any(|arg| matches.is_present(arg));
+    if !grpc_backend {
+        builder.with_embedded_backend();
+    }
+    if let Some(backend) = matches.value_of("backend-addr") {
+        builder.with_remote_backend(parse_grpc_addr(backend));
+    }
+    if let Some(cert) = matches.value_of("grpc-cert") {
+        builder.with_grpc_tls(
+            cert.to_string(),
+            matches.value_of("grpc-key").unwrap().to_string(),
+            matches.value_of("grpc-ca").unwrap().to_string(),
+        );
+    }
+    if let Some(s3) = s3 {
+        builder.with_s3_storage(s3);
+    }
+    if let Some(timeout) = matches.value_of("upload-timeout") {
+        let secs = timeout.parse().unwrap_or_else(|_| {
+            eprintln!("--upload-timeout must be a number of seconds");
+            std::process::exit(1);
+        });
+        builder.with_upload_timeout(Duration::from_secs(secs));
+    }
+    if let Some(types) = matches.value_of("allow-artifact-types") {
+        builder.with_allowed_artifact_types(parse_list(types));
+    }
     if matches.is_present("user") {
         let user = matches.value_of("user").expect("Failed to read user name");
 
@@ -325,7 +552,12 @@ fn main() {
             std::process::exit(1);
         }
     }
-    builder.start().unwrap_or_else(|e| {
+    let started = if matches.is_present("backend-only") {
+        builder.start_backend()
+    } else {
+        builder.start()
+    };
+    started.unwrap_or_else(|e| {
         eprintln!("Error launching Trow:\n\n{}", e);
         std::process::exit(1);
     });
@@ -0,0 +1,36 @@
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AdminError {
+    #[error("Internal admin error")]
+    Internal,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct GarbageCollectionReport {
+    pub dry_run: bool,
+    pub referenced_blobs: u32,
+    // Removed blobs, or blobs that would have been removed in a dry run
+    pub removed_blobs: Vec<String>,
+    pub reclaimed_bytes: u64,
+    pub skipped_recent_blobs: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct FsckReport {
+    pub checked_blobs: u32,
+    pub checked_tags: u32,
+    pub corrupt_blobs: Vec<String>,
+    pub quarantined_blobs: Vec<String>,
+    pub errors: Vec<String>,
+    pub orphaned_scratch_files: Vec<String>,
+}
+
+#[rocket::async_trait]
+pub trait AdminOperations {
+    /// Remove all blobs not reachable from a tag. Nothing is deleted if dry_run is set.
+    async fn collect_garbage(&self, dry_run: bool) -> Result<GarbageCollectionReport, AdminError>;
+
+    /// Check the storage is consistent. Corrupt blobs are quarantined if repair is set.
+    async fn fsck(&self, repair: bool) -> Result<FsckReport, AdminError>;
+}
@@ -1,23 +1,29 @@
 use super::digest::Digest;
 use super::SeekRead;
 use super::StorageDriverError;
-use std::io::Read;
+use tokio::io::AsyncRead;
 
 pub struct ContentInfo {
     pub length: u64,
     pub range: (u64, u64),
 }
 
-#[allow(dead_code)]
 pub struct UploadInfo {
-    name: String,
-    session_id: String,
-    uploaded: u32,
-    size: u32,
+    pub name: String,
+    pub session_id: String,
+    /// Bytes received so far
+    pub uploaded: u64,
+}
+
+/// What a HEAD request reports about a blob
+pub struct BlobInfo {
+    pub digest: Digest,
+    pub size: u64,
 }
 
 pub struct BlobReader {
     pub digest: Digest,
+    pub size: u64,
     pub reader: Box<dyn SeekRead>,
 }
 
@@ -29,26 +35,35 @@ impl BlobReader {
     pub fn digest(&self) -> &Digest {
         &self.digest
     }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
 }
 
+#[rocket::async_trait]
 pub trait BlobStorage {
     /// Retrieve the blob from the registry identified by digest.
-    /// A HEAD request can also be issued to this endpoint to obtain resource information without receiving all data.
     /// GET: /v2/<name>/blobs/<digest>
-    fn get_blob(&self, name: &str, digest: &Digest) -> Result<BlobReader, StorageDriverError>;
+    async fn get_blob(&self, name: &str, digest: &Digest)
+        -> Result<BlobReader, StorageDriverError>;
 
     /// Delete the blob identified by name and digest
     /// DELETE: /v2/<name>/blobs/<digest>
-    fn delete_blob(&self, name: &str, digest: &Digest) -> Result<(), StorageDriverError>;
+    async fn delete_blob(&self, name: &str, digest: &Digest) -> Result<(), StorageDriverError>;
 
     /// Requests to start a resumable upload for the given repository.
     /// Returns a session identifier for the upload.
-    fn start_blob_upload(&self, name: &str) -> Result<String, StorageDriverError>;
+    async fn start_blob_upload(&self, name: &str) -> Result<String, StorageDriverError>;
 
     /// Retrieve status of upload identified by session_id.
     /// The primary purpose of this endpoint is to resolve the current status of a resumable upload.
     /// GET: /v2/<name>/blobs/uploads/<session_id>
-    fn status_blob_upload(&self, name: &str, session_id: &str) -> UploadInfo;
+    async fn status_blob_upload(
+        &self,
+        name: &str,
+        session_id: &str,
+    ) -> Result<UploadInfo, StorageDriverError>;
 
     /// Upload a chunk of data for the specified upload.
     /// PATCH: /v2/<name>/blobs/uploads/<session_id>
@@ -57,17 +72,17 @@ pub trait BlobStorage {
     /// as its identifier.
     /// Passed optional ContentInfo which describes range of data.
     /// Returns current size of blob, including any previous chunks
-    fn store_blob_chunk(
+    async fn store_blob_chunk(
         &self,
         name: &str,
         session_id: &str,
         data_info: Option<ContentInfo>,
-        data: &mut Box<dyn Read>,
+        data: &mut (dyn AsyncRead + Unpin + Send),
     ) -> Result<u64, StorageDriverError>;
 
     /// Finalises the upload of the given session_id.
     /// Also verfies uploaded blob matches user digest
-    fn complete_and_verify_blob_upload(
+    async fn complete_and_verify_blob_upload(
         &self,
         name: &str,
         session_id: &str,
@@ -78,9 +93,17 @@ pub trait BlobStorage {
     /// If this is not called, the unfinished uploads will eventually timeout.
     /// DELETE: /v2/<name>/blobs/uploads/<session_id>
     /// Here we need to delete the existing temporary file/location based on its identifier: the session_id
-    fn cancel_blob_upload(&self, name: &str, session_id: &str) -> Result<(), StorageDriverError>;
+    async fn cancel_blob_upload(
+        &self,
+        name: &str,
+        session_id: &str,
+    ) -> Result<(), StorageDriverError>;
 
-    /// Whether the specific blob exists
-    /// AM: Assume this is for HEAD requests?
-    fn has_blob(&self, name: &str, digest: &Digest) -> bool;
+    /// Digest and size of the blob, without reading its data.
+    /// HEAD: /v2/<name>/blobs/<digest>
+    async fn get_blob_info(
+        &self,
+        name: &str,
+        digest: &Digest,
+    ) -> Result<BlobInfo, StorageDriverError>;
 }
@@ -1,7 +1,8 @@
+use super::Digest;
 use super::SeekRead;
 use super::StorageDriverError;
-use super::{Digest, DigestAlgorithm};
-use std::io::Read;
+use std::collections::HashMap;
+use tokio::io::AsyncRead;
 
 pub struct ManifestReader {
     pub content_type: String,
@@ -23,35 +24,102 @@ impl ManifestReader {
     }
 }
 
+/// What a HEAD request reports about a manifest
+pub struct ManifestInfo {
+    pub content_type: String,
+    pub digest: Digest,
+    pub size: u64,
+}
+
+/// What is known about a manifest once it has been stored
+pub struct StoredManifest {
+    pub digest: Digest,
+    /// The manifest this one refers to, if it has a subject
+    pub subject: Option<Digest>,
+}
+
+/// Descriptor of a manifest that refers to another through its subject field
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Referrer {
+    pub media_type: String,
+    pub digest: String,
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub artifact_type: Option<String>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub annotations: HashMap<String, String>,
+}
+
+/// A manifest an index refers to that hasn't been pushed to the repository
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct MissingManifest {
+    pub digest: String,
+    pub platform: Option<String>,
+}
+
+/// The referrers of a manifest, along with the artifact type they were filtered by, if any
+pub struct ReferrerList {
+    pub referrers: Vec<Referrer>,
+    pub artifact_type: Option<String>,
+}
+
 // This trait handles all the necessary Manifest Operations (get, save delete)
+#[rocket::async_trait]
 pub trait ManifestStorage {
     /// Fetch the manifest identified by name and reference where reference can be a tag or digest.
-    /// A HEAD request can also be issued to this endpoint to obtain resource information without receiving all data.
     /// GET: /v2/<name>/manifests/<reference>
-    /// HEAD: /v2/<name>/manifests/<reference>
-    fn get_manifest(&self, name: &str, tag: &str) -> Result<ManifestReader, StorageDriverError>;
+    async fn get_manifest(
+        &self,
+        name: &str,
+        tag: &str,
+    ) -> Result<ManifestReader, StorageDriverError>;
 
     // Stores should take a reader that has the data, possibly a second method that returns byte array
 
     /// Put the manifest identified by name and tag. (Note that manifests cannot be pushed by digest)
     /// data is a link to reader for supplying the bytes of the manifest.
-    /// Returns digest of the manifest.
-    /// PUT: /v2/<name>/manifests/<tag>
-    fn store_manifest(
+    /// If the client gave the digest it expects, the manifest is hashed with the same algorithm
+    /// and must match it.
+    /// Returns digest of the manifest, and of its subject if it has one.
+    /// PUT: /v2/<name>/manifests/<tag>?digest=<digest>
+    async fn store_manifest(
         &self,
         name: &str,
         tag: &str,
-        data: &mut Box<dyn Read>,
-    ) -> Result<Digest, StorageDriverError>;
+        digest: Option<&Digest>,
+        data: &mut (dyn AsyncRead + Unpin + Send),
+    ) -> Result<StoredManifest, StorageDriverError>;
 
     // Store a manifest via Writer trait for drivers which support it
     // AM: I think this was just for Trow, so we can remove, right?
     //fn store_manifest_with_writer(&self, name: &str, tag: &str) -> Result<Box<dyn Write>>;
 
-    /// Delete the manifest identified by name and reference. Note that a manifest can only be deleted by digest.
-    /// DELETE: /v2/<name>/manifests/<reference>
-    fn delete_manifest(&self, name: &str, digest: &Digest) -> Result<(), StorageDriverError>;
+    /// Delete the manifest identified by name and digest, removing every tag in the repository
+    /// that points to it.
+    /// DELETE: /v2/<name>/manifests/<digest>
+    async fn delete_manifest(&self, name: &str, digest: &Digest) -> Result<(), StorageDriverError>;
+
+    /// Remove a single tag, leaving the manifest and any other tags pointing to it.
+    /// DELETE: /v2/<name>/manifests/<tag>
+    async fn delete_tag(&self, name: &str, tag: &str) -> Result<(), StorageDriverError>;
 
-    /// Whether the specific manifest exists
-    fn has_manifest(&self, name: &str, algo: &DigestAlgorithm, reference: &str) -> bool;
+    /// Digest, size and media type of the manifest identified by name and reference, without
+    /// reading it.
+    /// HEAD: /v2/<name>/manifests/<reference>
+    async fn get_manifest_info(
+        &self,
+        name: &str,
+        reference: &str,
+    ) -> Result<ManifestInfo, StorageDriverError>;
+
+    /// Manifests in the repository whose subject is the manifest with the given digest,
+    /// optionally only those of the given artifact type.
+    /// GET: /v2/<name>/referrers/<digest>
+    async fn get_referrers(
+        &self,
+        name: &str,
+        digest: &Digest,
+        artifact_type: Option<&str>,
+    ) -> Result<ReferrerList, StorageDriverError>;
 }
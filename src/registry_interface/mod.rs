@@ -1,14 +1,18 @@
-use std::io::Read;
-use std::io::Seek;
 use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncSeek};
 
-pub use blob_storage::{BlobReader, BlobStorage, ContentInfo, UploadInfo};
+pub use admin::{AdminError, AdminOperations, FsckReport, GarbageCollectionReport};
+pub use blob_storage::{BlobInfo, BlobReader, BlobStorage, ContentInfo, UploadInfo};
 pub use catalog_operations::{CatalogOperations, ManifestHistory};
-pub use digest::{Digest, DigestAlgorithm};
-pub use manifest_storage::{ManifestReader, ManifestStorage};
+pub use digest::Digest;
+pub use manifest_storage::{
+    ManifestInfo, ManifestReader, ManifestStorage, MissingManifest, Referrer, ReferrerList,
+    StoredManifest,
+};
 pub use metrics::{Metrics, MetricsError, MetricsResponse};
 pub use validation::{AdmissionRequest, AdmissionResponse, Validation, ValidationError};
 
+pub mod admin;
 pub mod blob_storage;
 pub mod catalog_operations;
 #[allow(dead_code)]
@@ -24,20 +28,27 @@ pub enum StorageDriverError {
     InvalidName(String),
     #[error("manifest is not valid")]
     InvalidManifest,
+    #[error("manifests {0:?} referred to by the index are missing")]
+    MissingManifests(Vec<MissingManifest>),
     #[error("Digest did not match content")]
     InvalidDigest,
     #[error("Unsupported Operation")]
     Unsupported,
+    #[error("upload `{0}` is not known")]
+    UnknownUpload(String),
     #[error("Requested index does not match actual")]
     InvalidContentRange,
+    #[error("blob is referenced by {0:?}")]
+    BlobReferenced(Vec<String>),
     #[error("Internal storage error")]
     Internal,
 }
 
 //If there's a better solution, please let me know.
 //I'd much rather not have to write an impl for every class :(
-pub trait SeekRead: Read + Seek {}
-impl SeekRead for std::fs::File {}
+pub trait SeekRead: AsyncRead + AsyncSeek + Send + Unpin {}
+impl SeekRead for tokio::fs::File {}
+impl SeekRead for std::io::Cursor<Vec<u8>> {}
 
 // Super trait
 pub trait RegistryStorage: ManifestStorage + BlobStorage + CatalogOperations {
@@ -4,8 +4,8 @@ use rocket::http::{Header, Status};
 use rocket::request::Request;
 use rocket::response::{Responder, Response};
 
-impl<'r> Responder<'r> for AcceptedUpload {
-    fn respond_to(self, req: &Request) -> Result<Response<'r>, Status> {
+impl<'r, 'o: 'r> Responder<'r, 'o> for AcceptedUpload {
+    fn respond_to(self, req: &'r Request<'_>) -> Result<Response<'o>, Status> {
         let location = format!(
             "{}/v2/{}/blobs/{}",
             get_base_url(req),
@@ -32,12 +32,16 @@ impl<'r> Responder<'r> for AcceptedUpload {
 #[cfg(test)]
 mod test {
     use crate::registry_interface::Digest;
+    use crate::types::RepoName;
     use crate::types::{create_accepted_upload, AcceptedUpload};
-    use crate::types::{RepoName, Uuid};
-    use crate::{registry_interface::DigestAlgorithm, response::test_helper::test_route};
+    use crate::{registry_interface::digest::DigestAlgorithm, response::test_helper::test_route};
     use rocket::http::Status;
 
     fn build_response() -> AcceptedUpload {
+        build_response_with_range((0, 0))
+    }
+
+    fn build_response_with_range(range: (u64, u64)) -> AcceptedUpload {
         create_accepted_upload(
             Digest {
                 algo: DigestAlgorithm::Sha256,
@@ -45,8 +49,7 @@ mod test {
                     .to_string(),
             },
             RepoName("moredhel/test".to_owned()),
-            Uuid("whatever".to_owned()),
-            (0, 0),
+            range,
         )
     }
 
@@ -59,5 +62,17 @@ mod test {
         assert!(headers.contains("Range"));
         assert!(headers.contains("Docker-Content-Digest"));
         assert!(headers.contains("Content-Length"));
+        // The upload is finished, or there never was one for a mount
+        assert!(!headers.contains("Docker-Upload-UUID"));
+    }
+
+    #[test]
+    fn range_of_large_blob() {
+        let size = 5 * 1024 * 1024 * 1024;
+        let response = test_route(build_response_with_range((0, size)));
+        assert_eq!(
+            response.headers().get_one("Range"),
+            Some(format!("0-{}", size).as_str())
+        );
     }
 }
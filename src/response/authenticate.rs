@@ -10,8 +10,8 @@ use rocket::response::{Responder, Response};
 #[derive(Debug, Serialize)]
 pub struct Authenticate {}
 
-impl<'r> Responder<'r> for Authenticate {
-    fn respond_to(self, req: &Request) -> Result<Response<'r>, Status> {
+impl<'r, 'o: 'r> Responder<'r, 'o> for Authenticate {
+    fn respond_to(self, req: &'r Request<'_>) -> Result<Response<'o>, Status> {
         let realm = get_base_url(req);
         let authenticate_header = Header::new(
             "www-authenticate",
@@ -3,8 +3,8 @@ use rocket::http::Status;
 use rocket::request::Request;
 use rocket::response::{self, Responder, Response};
 
-impl<'r> Responder<'r> for BlobDeleted {
-    fn respond_to(self, _req: &Request) -> response::Result<'r> {
+impl<'r, 'o: 'r> Responder<'r, 'o> for BlobDeleted {
+    fn respond_to(self, _req: &'r Request<'_>) -> response::Result<'o> {
         Response::build().status(Status::Accepted).ok()
     }
 }
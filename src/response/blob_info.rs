@@ -0,0 +1,47 @@
+use crate::registry_interface::BlobInfo;
+use rocket::http::Header;
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use std::io::Cursor;
+
+impl<'r, 'o: 'r> Responder<'r, 'o> for BlobInfo {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'o> {
+        let ct = Header::new("Content-Type", "application/octet-stream");
+        let digest = Header::new("Docker-Content-Digest", self.digest.to_string());
+        let accept_ranges = Header::new("Accept-Ranges", "bytes");
+
+        // An empty body of the blob's size gives the right Content-Length without reading it
+        Response::build()
+            .header(ct)
+            .header(digest)
+            .header(accept_ranges)
+            .sized_body(self.size as usize, Cursor::new(Vec::new()))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::registry_interface::digest::{Digest, DigestAlgorithm};
+    use crate::registry_interface::BlobInfo;
+    use crate::response::test_helper::test_route;
+    use rocket::http::Status;
+
+    #[test]
+    fn blob_info() {
+        let response = test_route(BlobInfo {
+            digest: Digest {
+                algo: DigestAlgorithm::Sha256,
+                hash: "05c6e08f1d9fdafa03147fcb8f82f124c76d2f70e3d989dc8aadb5e7d7450bec"
+                    .to_string(),
+            },
+            size: 1024,
+        });
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.headers().get_one("Docker-Content-Digest"),
+            Some("sha256:05c6e08f1d9fdafa03147fcb8f82f124c76d2f70e3d989dc8aadb5e7d7450bec")
+        );
+        assert_eq!(response.body().preset_size(), Some(1024));
+    }
+}
@@ -1,18 +1,390 @@
-use crate::registry_interface::BlobReader;
-use rocket::http::Header;
+use crate::registry_interface::{BlobReader, SeekRead};
+use futures::ready;
+use rocket::http::{ContentType, Header, Status};
 use rocket::request::Request;
 use rocket::response::{self, Responder, Response};
+use std::collections::VecDeque;
+use std::io::{self, Cursor, SeekFrom};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
 
-impl<'r> Responder<'r> for BlobReader {
-    fn respond_to(self, _: &Request) -> response::Result<'r> {
-        let ct = Header::new("Content-Type", "application/octet-stream");
+// Inclusive start and end of a range of bytes
+type ByteRange = (u64, u64);
+
+// Most ranges sent for one request, once overlapping ones are merged
+const MAX_RANGES: usize = 32;
+
+#[derive(Debug, PartialEq)]
+struct UnsatisfiableRange;
+
+/**
+ * Parses a Range header for a blob of the given size.
+ *
+ * Returns None if the whole blob should be sent, as for units other than bytes. As in RFC 7233,
+ * a header that doesn't parse is ignored too, ranges that start past the end are dropped and ends
+ * past the end are truncated. It is an error if no ranges are left.
+ *
+ * Overlapping and adjacent ranges are merged, and the rest are sent in order of where they start.
+ * If that leaves more than MAX_RANGES, the header is ignored rather than sending many small parts.
+ */
+fn parse_range(header: &str, size: u64) -> Result<Option<Vec<ByteRange>>, UnsatisfiableRange> {
+    let specs = match header.trim().strip_prefix("bytes=") {
+        Some(specs) => specs,
+        None => return Ok(None),
+    };
+
+    let specs: Vec<&str> = specs
+        .split(',')
+        .map(str::trim)
+        .filter(|spec| !spec.is_empty())
+        .collect();
+    if specs.is_empty() {
+        return Ok(None);
+    }
+
+    let mut ranges = Vec::new();
+    for spec in specs {
+        match parse_range_spec(spec, size) {
+            Some(Some(range)) => ranges.push(range),
+            Some(None) => (),
+            None => return Ok(None),
+        }
+    }
+    if ranges.is_empty() {
+        return Err(UnsatisfiableRange);
+    }
+
+    ranges.sort_unstable();
+    let mut merged: Vec<ByteRange> = Vec::new();
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    if merged.len() > MAX_RANGES {
+        return Ok(None);
+    }
+    Ok(Some(merged))
+}
+
+/*
+ * Parses one range of a Range header, giving None if it is malformed or Some(None) if it is
+ * outside a blob of the given size.
+ */
+fn parse_range_spec(spec: &str, size: u64) -> Option<Option<ByteRange>> {
+    let (first, last) = spec.split_once('-')?;
+    let parse = |n: &str| n.parse::<u64>().ok();
+
+    let range = match (first, last) {
+        ("", suffix) => {
+            // Last n bytes
+            let n = parse(suffix)?;
+            if n == 0 || size == 0 {
+                return Some(None);
+            }
+            (size - n.min(size), size - 1)
+        }
+        (start, "") => (parse(start)?, size.saturating_sub(1)),
+        (start, end) => {
+            let (start, end) = (parse(start)?, parse(end)?);
+            if end < start {
+                return None;
+            }
+            (start, end.min(size.saturating_sub(1)))
+        }
+    };
+    Some(if range.0 < size { Some(range) } else { None })
+}
+
+fn content_range((start, end): ByteRange, size: u64) -> String {
+    format!("bytes {}-{}/{}", start, end, size)
+}
+
+enum Segment {
+    Bytes(Cursor<Vec<u8>>),
+    Range { start: u64, remaining: u64 },
+}
+
+#[derive(PartialEq)]
+enum Position {
+    Unpositioned,
+    Seeking,
+    Positioned,
+}
+
+/**
+ * Reads ranges of a blob, seeking to each range in turn rather than buffering them. For a
+ * multipart/byteranges body the ranges are interleaved with their part headers.
+ */
+struct RangesReader {
+    reader: Box<dyn SeekRead>,
+    segments: VecDeque<Segment>,
+    // Whether the reader is positioned at the current range
+    position: Position,
+    read: u64,
+}
+
+impl RangesReader {
+    fn new(reader: Box<dyn SeekRead>, segments: VecDeque<Segment>) -> RangesReader {
+        RangesReader {
+            reader,
+            segments,
+            position: Position::Unpositioned,
+            read: 0,
+        }
+    }
+
+    fn single(reader: Box<dyn SeekRead>, (start, end): ByteRange) -> RangesReader {
+        let mut segments = VecDeque::new();
+        segments.push_back(Segment::Range {
+            start,
+            remaining: end - start + 1,
+        });
+        RangesReader::new(reader, segments)
+    }
+
+    fn multipart(
+        reader: Box<dyn SeekRead>,
+        ranges: &[ByteRange],
+        size: u64,
+        boundary: &str,
+    ) -> RangesReader {
+        let mut segments = VecDeque::new();
+        for (i, range) in ranges.iter().enumerate() {
+            let part_header = format!(
+                "{}--{}\r\nContent-Type: application/octet-stream\r\nContent-Range: {}\r\n\r\n",
+                if i == 0 { "" } else { "\r\n" },
+                boundary,
+                content_range(*range, size)
+            );
+            segments.push_back(Segment::Bytes(Cursor::new(part_header.into_bytes())));
+            segments.push_back(Segment::Range {
+                start: range.0,
+                remaining: range.1 - range.0 + 1,
+            });
+        }
+        let trailer = format!("\r\n--{}--\r\n", boundary);
+        segments.push_back(Segment::Bytes(Cursor::new(trailer.into_bytes())));
+
+        RangesReader::new(reader, segments)
+    }
+
+    fn len(&self) -> u64 {
+        self.segments
+            .iter()
+            .map(|s| match s {
+                Segment::Bytes(b) => b.get_ref().len() as u64,
+                Segment::Range { remaining, .. } => *remaining,
+            })
+            .sum()
+    }
+}
+
+impl AsyncRead for RangesReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            if buf.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+            let n = match this.segments.front_mut() {
+                None => return Poll::Ready(Ok(())),
+                Some(Segment::Bytes(bytes)) => {
+                    let before = buf.filled().len();
+                    ready!(Pin::new(bytes).poll_read(cx, buf))?;
+                    buf.filled().len() - before
+                }
+                Some(Segment::Range { start, remaining }) => {
+                    if *remaining == 0 {
+                        0
+                    } else {
+                        if this.position == Position::Unpositioned {
+                            Pin::new(&mut this.reader).start_seek(SeekFrom::Start(*start))?;
+                            this.position = Position::Seeking;
+                        }
+                        if this.position == Position::Seeking {
+                            ready!(Pin::new(&mut this.reader).poll_complete(cx))?;
+                            this.position = Position::Positioned;
+                        }
+                        let max = (buf.remaining() as u64).min(*remaining) as usize;
+                        let mut part = ReadBuf::new(buf.initialize_unfilled_to(max));
+                        ready!(Pin::new(&mut this.reader).poll_read(cx, &mut part))?;
+                        let n = part.filled().len();
+                        if n == 0 {
+                            return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                        }
+                        buf.advance(n);
+                        *remaining -= n as u64;
+                        n
+                    }
+                }
+            };
+            if n > 0 {
+                this.read += n as u64;
+                return Poll::Ready(Ok(()));
+            }
+            this.segments.pop_front();
+            this.position = Position::Unpositioned;
+        }
+    }
+}
+
+/**
+ * Rocket only seeks to find the length of a body, which is always given up front here, so this
+ * just reports how far the body has been read.
+ */
+impl AsyncSeek for RangesReader {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        match position {
+            SeekFrom::Current(0) => Ok(()),
+            _ => Err(io::ErrorKind::Unsupported.into()),
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Ok(self.read))
+    }
+}
+
+/**
+ * Sends the blob, or the parts of it asked for in a Range header with 206 Partial Content.
+ * Several ranges are sent as multipart/byteranges.
+ */
+impl<'r, 'o: 'r> Responder<'r, 'o> for BlobReader {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'o> {
         let digest = Header::new("Docker-Content-Digest", self.digest().to_string());
+        let accept_ranges = Header::new("Accept-Ranges", "bytes");
+        let size = self.size();
+        let reader = self.get_reader();
+
+        let ranges = match req.headers().get_one("Range") {
+            None => None,
+            Some(header) => match parse_range(header, size) {
+                Ok(ranges) => ranges,
+                Err(_) => {
+                    warn!("Unsatisfiable range {} for blob of {} bytes", header, size);
+                    return Response::build()
+                        .status(Status::RangeNotSatisfiable)
+                        .header(digest)
+                        .header(Header::new("Content-Range", format!("bytes */{}", size)))
+                        .ok();
+                }
+            },
+        };
+
+        let mut resp = Response::build();
+        resp.header(digest).header(accept_ranges);
+        match ranges {
+            None => {
+                // Important to used sized_body in order to have content length set correctly
+                resp.header(ContentType::Binary)
+                    .sized_body(size as usize, reader);
+            }
+            Some(ranges) if ranges.len() == 1 => {
+                let body = RangesReader::single(reader, ranges[0]);
+                let len = body.len();
+                resp.status(Status::PartialContent)
+                    .header(ContentType::Binary)
+                    .header(Header::new("Content-Range", content_range(ranges[0], size)))
+                    .sized_body(len as usize, body);
+            }
+            Some(ranges) => {
+                let boundary = uuid::Uuid::new_v4().to_simple().to_string();
+                let body = RangesReader::multipart(reader, &ranges, size, &boundary);
+                let len = body.len();
+                resp.status(Status::PartialContent)
+                    .header(Header::new(
+                        "Content-Type",
+                        format!("multipart/byteranges; boundary={}", boundary),
+                    ))
+                    .sized_body(len as usize, body);
+            }
+        }
+        resp.ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_range, RangesReader, UnsatisfiableRange, MAX_RANGES};
+    use std::io::Cursor;
+    use tokio::io::AsyncReadExt;
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(parse_range("bytes=0-9", 100), Ok(Some(vec![(0, 9)])));
+        assert_eq!(parse_range("bytes=90-", 100), Ok(Some(vec![(90, 99)])));
+        assert_eq!(parse_range("bytes=-10", 100), Ok(Some(vec![(90, 99)])));
+        assert_eq!(parse_range("bytes=-200", 100), Ok(Some(vec![(0, 99)])));
+        assert_eq!(parse_range("bytes=50-200", 100), Ok(Some(vec![(50, 99)])));
+        assert_eq!(
+            parse_range("bytes=0-0, 100-, -1", 100),
+            Ok(Some(vec![(0, 0), (99, 99)]))
+        );
+        assert_eq!(parse_range("items=0-9", 100), Ok(None));
+
+        // Headers that don't parse are ignored
+        assert_eq!(parse_range("bytes=9-0", 100), Ok(None));
+        assert_eq!(parse_range("bytes=a-b", 100), Ok(None));
+        assert_eq!(parse_range("bytes=0-9, 5", 100), Ok(None));
+        assert_eq!(parse_range("bytes=", 100), Ok(None));
+
+        assert_eq!(parse_range("bytes=100-", 100), Err(UnsatisfiableRange));
+        assert_eq!(parse_range("bytes=-0", 100), Err(UnsatisfiableRange));
+        assert_eq!(parse_range("bytes=0-9", 0), Err(UnsatisfiableRange));
+    }
+
+    #[test]
+    fn merges_and_limits_ranges() {
+        assert_eq!(
+            parse_range("bytes=50-59, 0-9, 5-14, 15-19, -10", 100),
+            Ok(Some(vec![(0, 19), (50, 59), (90, 99)]))
+        );
+        assert_eq!(
+            parse_range("bytes=0-99, 10-19, 20-29", 100),
+            Ok(Some(vec![(0, 99)]))
+        );
+
+        let many = |n: u64| {
+            let specs: Vec<String> = (0..n).map(|i| format!("{}-{}", i * 2, i * 2)).collect();
+            format!("bytes={}", specs.join(","))
+        };
+        assert_eq!(
+            parse_range(&many(MAX_RANGES as u64), 100)
+                .unwrap()
+                .unwrap()
+                .len(),
+            MAX_RANGES
+        );
+        assert_eq!(parse_range(&many(MAX_RANGES as u64 + 1), 100), Ok(None));
+    }
 
-        // Important to used sized_body in order to have content length set correctly
-        let mut resp = Response::build().sized_body(self.get_reader()).ok()?;
-        resp.set_header(ct);
-        resp.set_header(digest);
+    #[tokio::test]
+    async fn reads_multiple_ranges() {
+        let data: Vec<u8> = (0..100).collect();
+        let mut body =
+            RangesReader::multipart(Box::new(Cursor::new(data)), &[(0, 1), (98, 99)], 100, "b");
+        let len = body.len();
+        let mut out = Vec::new();
+        body.read_to_end(&mut out).await.unwrap();
+        assert_eq!(out.len() as u64, len);
 
-        Ok(resp)
+        let mut expected = b"--b\r\nContent-Type: application/octet-stream\r\n\
+            Content-Range: bytes 0-1/100\r\n\r\n"
+            .to_vec();
+        expected.extend(&[0, 1]);
+        expected.extend(
+            &b"\r\n--b\r\nContent-Type: application/octet-stream\r\n\
+            Content-Range: bytes 98-99/100\r\n\r\n"[..],
+        );
+        expected.extend(&[98, 99]);
+        expected.extend(&b"\r\n--b--\r\n"[..]);
+        assert_eq!(out, expected);
     }
 }
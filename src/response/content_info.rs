@@ -1,28 +1,29 @@
 use crate::registry_interface::blob_storage::ContentInfo;
 use crate::response::errors::Error;
 use rocket::http::Status;
+use rocket::outcome::Outcome;
 use rocket::request::{self, FromRequest, Request};
-use rocket::Outcome;
 
 /**
  * ContentInfo should always be wrapped an Option in routes to avoid failure returns.
  */
-impl<'a, 'r> FromRequest<'a, 'r> for ContentInfo {
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ContentInfo {
     type Error = Error;
 
-    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Error> {
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Error> {
         let length = match request.headers().get_one("Content-Length") {
             Some(l) => match l.parse::<u64>() {
                 Ok(i) => i,
                 Err(_) => {
                     warn!("Received request with invalid Content-Length header");
-                    return Outcome::Failure((Status::BadRequest, Error::BlobUploadInvalid));
+                    return Outcome::Error((Status::BadRequest, Error::BlobUploadInvalid));
                 }
             },
             None => {
                 // This probably just means we don't have ContentInfo
                 // Should be caught by an option in the RequestGuard
-                return Outcome::Failure((Status::BadRequest, Error::BlobUploadInvalid));
+                return Outcome::Error((Status::BadRequest, Error::BlobUploadInvalid));
             }
         };
 
@@ -40,6 +41,6 @@ impl<'a, 'r> FromRequest<'a, 'r> for ContentInfo {
             }
         }
         warn!("Received request with invalid Content-Range header");
-        Outcome::Failure((rocket::http::Status::BadRequest, Error::BlobUploadInvalid))
+        Outcome::Error((rocket::http::Status::BadRequest, Error::BlobUploadInvalid))
     }
 }
@@ -1,3 +1,4 @@
+use crate::registry_interface::MissingManifest;
 use rocket::http::{ContentType, Status};
 use rocket::request::Request;
 use rocket::response;
@@ -16,25 +17,26 @@ pub enum Error {
 
     BLOB_UPLOAD_UNKNOWN,
     DIGEST_INVALID,
-    MANIFEST_BLOB_UNKNOWN,
     ,
     MANIFEST_UNVERIFIED,
     NAME_UNKNOWN,
     SIZE_INVALID,
     TAG_INVALID,
     UNAUTHORIZED,
-    DENIED,
     */
     NameInvalid(String),
     BlobUploadInvalid,
     ManifestUnknown(String),
     ManifestInvalid,
+    ManifestTooLarge(usize),
+    ManifestBlobUnknown(Vec<MissingManifest>),
     Unauthorized,
     BlobUnknown,
     BlobUploadUnknown,
     Unsupported,
     InternalError,
     DigestInvalid,
+    BlobReferenced(Vec<String>),
 }
 
 // Create ErrorMsg struct that serializes to json of appropriate type
@@ -53,7 +55,9 @@ impl fmt::Display for Error {
                 format_error_json(f, "UNAUTHORIZED", "Authorization required", None)
             }
             Error::BlobUnknown => format_error_json(f, "BLOB_UNKNOWN", "Blob Unknown", None),
-            Error::BlobUploadUnknown => write!(f, "Blob Upload Unknown"),
+            Error::BlobUploadUnknown => {
+                format_error_json(f, "BLOB_UPLOAD_UNKNOWN", "Blob Upload Unknown", None)
+            }
             Error::BlobUploadInvalid => format_error_json(
                 f,
                 "BLOB_UPLOAD_INVALID",
@@ -73,6 +77,18 @@ impl fmt::Display for Error {
             Error::ManifestInvalid => {
                 format_error_json(f, "MANIFEST_INVALID", "Manifest invalid", None)
             }
+            Error::ManifestTooLarge(max_size) => format_error_json(
+                f,
+                "MANIFEST_INVALID",
+                "Manifest too large",
+                Some(json!({ "MaxSize": max_size })),
+            ),
+            Error::ManifestBlobUnknown(ref missing) => format_error_json(
+                f,
+                "MANIFEST_BLOB_UNKNOWN",
+                "Manifest references unknown manifests",
+                Some(json!({ "Missing": missing })),
+            ),
             Error::ManifestUnknown(ref tag) => format_error_json(
                 f,
                 "MANIFEST_UNKNOWN",
@@ -85,6 +101,12 @@ impl fmt::Display for Error {
                 "Invalid repository name",
                 Some(json!({ "Repository": name })),
             ),
+            Error::BlobReferenced(ref tags) => format_error_json(
+                f,
+                "DENIED",
+                "Blob is referenced by one or more manifests",
+                Some(json!({ "ReferencedBy": tags })),
+            ),
         }
     }
 }
@@ -119,31 +141,37 @@ impl error::Error for Error {
             Error::InternalError => "An internal error occured, please consult the logs for more details.",
             Error::DigestInvalid => "When a blob is uploaded, the registry will check that the content matches the digest provided by the client. The error may include a detail structure with the key \"digest\", including the invalid digest string. This error may also be returned when a manifest includes an invalid layer digest.",
             Error::ManifestInvalid => "During upload, manifests undergo several checks ensuring validity. If those checks fail, this error may be returned, unless a more specific error is included. The detail will contain information the failed validation.",
+            Error::ManifestTooLarge(_) => "The manifest is larger than the registry accepts. The detail gives the maximum size in bytes.",
+            Error::ManifestBlobUnknown(_) => "This error may be returned when a manifest blob is unknown to the registry. For an index, the detail lists the manifests it refers to that are missing, with their platforms.",
             Error::ManifestUnknown(_) => "This error is returned when the manifest, identified by name and tag is unknown to the repository.",
-            Error::NameInvalid(_) => "Invalid repository name encountered either during manifest validation or any API operation."
+            Error::NameInvalid(_) => "Invalid repository name encountered either during manifest validation or any API operation.",
+            Error::BlobReferenced(_) => "The blob is still referenced by the listed tags and manifests. Delete the manifests referencing it first."
 
         }
     }
 }
 
-impl<'r> Responder<'r> for Error {
-    fn respond_to(self, _req: &Request) -> response::Result<'r> {
+impl<'r, 'o: 'r> Responder<'r, 'o> for Error {
+    fn respond_to(self, _req: &'r Request<'_>) -> response::Result<'o> {
         let json = format!("{}", self);
 
         let status = match self {
             Error::Unsupported => Status::MethodNotAllowed,
             Error::Unauthorized => Status::Unauthorized,
+            Error::BlobReferenced(_) => Status::Forbidden,
             Error::BlobUploadUnknown | Error::ManifestUnknown(_) => Status::NotFound,
             Error::InternalError => Status::InternalServerError,
             Error::BlobUploadInvalid => Status::RangeNotSatisfiable,
+            Error::ManifestTooLarge(_) => Status::PayloadTooLarge,
             Error::DigestInvalid
             | Error::ManifestInvalid
+            | Error::ManifestBlobUnknown(_)
             | Error::BlobUnknown
             | Error::NameInvalid(_) => Status::BadRequest,
         };
         Response::build()
             .header(ContentType::JSON)
-            .sized_body(Cursor::new(json))
+            .sized_body(json.len(), Cursor::new(json))
             .status(status)
             .ok()
     }
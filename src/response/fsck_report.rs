@@ -0,0 +1,46 @@
+use std::io::Cursor;
+
+use rocket::http::ContentType;
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{Responder, Response};
+
+use crate::registry_interface::FsckReport;
+
+impl<'r, 'o: 'r> Responder<'r, 'o> for FsckReport {
+    fn respond_to(self, _req: &'r Request<'_>) -> Result<Response<'o>, Status> {
+        let json = serde_json::to_string(&self).unwrap_or_else(|_| "{}".to_string());
+
+        Response::build()
+            .header(ContentType::JSON)
+            .sized_body(json.len(), Cursor::new(json))
+            .status(Status::Ok)
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::registry_interface::FsckReport;
+    use crate::response::test_helper::test_route;
+    use rocket::http::Status;
+
+    fn build_fsck_report() -> FsckReport {
+        FsckReport {
+            checked_blobs: 3,
+            checked_tags: 1,
+            corrupt_blobs: vec![],
+            quarantined_blobs: vec![],
+            errors: vec![String::from(
+                "alpine:latest: manifest sha256:05c6e08f1d9fdafa03147fcb8f82f124c76d2f70e3d989dc8aadb5e7d7450bec does not exist",
+            )],
+            orphaned_scratch_files: vec![],
+        }
+    }
+
+    #[test]
+    fn test_fsck_report_resp() {
+        let response = test_route(build_fsck_report());
+        assert_eq!(response.status(), Status::Ok);
+    }
+}
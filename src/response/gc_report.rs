@@ -0,0 +1,45 @@
+use std::io::Cursor;
+
+use rocket::http::ContentType;
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{Responder, Response};
+
+use crate::registry_interface::GarbageCollectionReport;
+
+impl<'r, 'o: 'r> Responder<'r, 'o> for GarbageCollectionReport {
+    fn respond_to(self, _req: &'r Request<'_>) -> Result<Response<'o>, Status> {
+        let json = serde_json::to_string(&self).unwrap_or_else(|_| "{}".to_string());
+
+        Response::build()
+            .header(ContentType::JSON)
+            .sized_body(json.len(), Cursor::new(json))
+            .status(Status::Ok)
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::registry_interface::GarbageCollectionReport;
+    use crate::response::test_helper::test_route;
+    use rocket::http::Status;
+
+    fn build_report() -> GarbageCollectionReport {
+        GarbageCollectionReport {
+            dry_run: true,
+            referenced_blobs: 3,
+            removed_blobs: vec![String::from(
+                "sha256:05c6e08f1d9fdafa03147fcb8f82f124c76d2f70e3d989dc8aadb5e7d7450bec",
+            )],
+            reclaimed_bytes: 1024,
+            skipped_recent_blobs: 0,
+        }
+    }
+
+    #[test]
+    fn test_gc_report_resp() {
+        let response = test_route(build_report());
+        assert_eq!(response.status(), Status::Ok);
+    }
+}
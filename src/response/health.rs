@@ -7,19 +7,19 @@ use rocket::response::{Responder, Response};
 
 use crate::types::HealthResponse;
 
-impl<'r> Responder<'r> for HealthResponse {
-    fn respond_to(self, _req: &Request) -> Result<Response<'r>, Status> {
+impl<'r, 'o: 'r> Responder<'r, 'o> for HealthResponse {
+    fn respond_to(self, _req: &'r Request<'_>) -> Result<Response<'o>, Status> {
         let json = serde_json::to_string(&self).unwrap_or_else(|_| "{}".to_string());
 
         match self.is_healthy {
             true => Response::build()
                 .header(ContentType::JSON)
-                .sized_body(Cursor::new(json))
+                .sized_body(json.len(), Cursor::new(json))
                 .status(Status::Ok)
                 .ok(),
             false => Response::build()
                 .header(ContentType::JSON)
-                .sized_body(Cursor::new(json))
+                .sized_body(json.len(), Cursor::new(json))
                 .status(Status::ServiceUnavailable)
                 .ok(),
         }
@@ -6,11 +6,11 @@ use rocket::response::{Responder, Response};
 
 pub struct HTML<'a>(pub &'a str);
 
-impl<'a> Responder<'a> for HTML<'a> {
-    fn respond_to(self, _: &Request) -> Result<Response<'a>, Status> {
+impl<'r, 'o: 'r> Responder<'r, 'o> for HTML<'o> {
+    fn respond_to(self, _: &'r Request<'_>) -> Result<Response<'o>, Status> {
         Response::build()
             .header(ContentType::HTML)
-            .sized_body(Cursor::new(self.0))
+            .sized_body(self.0.len(), Cursor::new(self.0))
             .ok()
     }
 }
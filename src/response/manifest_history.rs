@@ -5,13 +5,13 @@ use rocket::http::ContentType;
 use rocket::request::Request;
 use rocket::response::{self, Responder, Response};
 
-impl<'r> Responder<'r> for ManifestHistory {
-    fn respond_to(self, _req: &Request) -> response::Result<'r> {
+impl<'r, 'o: 'r> Responder<'r, 'o> for ManifestHistory {
+    fn respond_to(self, _req: &'r Request<'_>) -> response::Result<'o> {
         let json = serde_json::to_string(&self).unwrap();
 
         Response::build()
             .header(ContentType::JSON)
-            .sized_body(Cursor::new(json))
+            .sized_body(json.len(), Cursor::new(json))
             .ok()
     }
 }
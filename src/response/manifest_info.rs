@@ -0,0 +1,47 @@
+use crate::registry_interface::ManifestInfo;
+use rocket::http::Header;
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use std::io::Cursor;
+
+impl<'r, 'o: 'r> Responder<'r, 'o> for ManifestInfo {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'o> {
+        let ct = Header::new("Content-Type", self.content_type);
+        let digest = Header::new("Docker-Content-Digest", self.digest.to_string());
+
+        // An empty body of the manifest's size gives the right Content-Length without reading it
+        Response::build()
+            .header(ct)
+            .header(digest)
+            .sized_body(self.size as usize, Cursor::new(Vec::new()))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::registry_interface::digest::{Digest, DigestAlgorithm};
+    use crate::registry_interface::ManifestInfo;
+    use crate::response::test_helper::test_route;
+    use rocket::http::Status;
+
+    #[test]
+    fn manifest_info() {
+        let response = test_route(ManifestInfo {
+            content_type: "application/vnd.oci.image.manifest.v1+json".to_string(),
+            digest: Digest {
+                algo: DigestAlgorithm::Sha256,
+                hash: "05c6e08f1d9fdafa03147fcb8f82f124c76d2f70e3d989dc8aadb5e7d7450bec"
+                    .to_string(),
+            },
+            size: 358,
+        });
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.headers().get_one("Content-Type"),
+            Some("application/vnd.oci.image.manifest.v1+json")
+        );
+        assert!(response.headers().contains("Docker-Content-Digest"));
+        assert_eq!(response.body().preset_size(), Some(358));
+    }
+}
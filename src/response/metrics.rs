@@ -7,11 +7,11 @@ use rocket::response::{Responder, Response};
 
 use crate::registry_interface::MetricsResponse;
 
-impl<'r> Responder<'r> for MetricsResponse {
-    fn respond_to(self, _req: &Request) -> Result<Response<'r>, Status> {
+impl<'r, 'o: 'r> Responder<'r, 'o> for MetricsResponse {
+    fn respond_to(self, _req: &'r Request<'_>) -> Result<Response<'o>, Status> {
         Response::build()
             .header(ContentType::Plain)
-            .sized_body(Cursor::new(self.metrics))
+            .sized_body(self.metrics.len(), Cursor::new(self.metrics))
             .status(Status::Ok)
             .ok()
     }
@@ -4,33 +4,41 @@ use rocket::request::Request;
 pub mod accepted_upload;
 pub mod authenticate;
 pub mod blob_deleted;
+pub mod blob_info;
 pub mod blob_reader;
 pub mod content_info;
 pub mod empty;
 pub mod errors;
+pub mod fsck_report;
+pub mod gc_report;
 pub mod health;
 pub mod html;
 pub mod manifest_deleted;
 pub mod manifest_history;
+pub mod manifest_info;
 pub mod manifest_reader;
 pub mod metrics;
 pub mod readiness;
+pub mod referrer_list;
 pub mod repo_catalog;
 pub mod tag_list;
 mod test_helper;
 pub mod trow_token;
+pub mod upload_cancelled;
 pub mod upload_info;
+pub mod upload_status;
 pub mod verified_manifest;
 
 /// Gets the base URL e.g. <http://registry:8000> using the HOST value from the request header.
 /// Falls back to hostname if it doesn't exist.
 ///
 /// Move this.
-fn get_base_url(req: &Request) -> String {
+fn get_base_url(req: &Request<'_>) -> String {
     let host = get_domain_name(req);
 
     let config = req
-        .guard::<rocket::State<TrowConfig>>()
+        .rocket()
+        .state::<TrowConfig>()
         .expect("TrowConfig not present!");
 
     // Check if we have an upstream load balancer doing TLS termination
@@ -48,7 +56,7 @@ fn get_base_url(req: &Request) -> String {
     }
 }
 
-fn get_domain_name(req: &Request) -> String {
+fn get_domain_name(req: &Request<'_>) -> String {
     match req.headers().get("HOST").next() {
         None => hostname::get()
             .expect("Server has no name; cannot give clients my address")
@@ -0,0 +1,90 @@
+use crate::registry_interface::{Referrer, ReferrerList};
+use rocket::http::{ContentType, Header};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use std::io::Cursor;
+
+const OCI_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Index<'a> {
+    schema_version: u8,
+    media_type: &'a str,
+    manifests: &'a [Referrer],
+}
+
+/**
+ * Sends the referrers as an OCI image index. If they were filtered by artifact type, the
+ * OCI-Filters-Applied header tells the client so.
+ */
+impl<'r, 'o: 'r> Responder<'r, 'o> for ReferrerList {
+    fn respond_to(self, _req: &'r Request<'_>) -> response::Result<'o> {
+        let index = Index {
+            schema_version: 2,
+            media_type: OCI_INDEX,
+            manifests: &self.referrers,
+        };
+        let json = serde_json::to_string(&index).unwrap();
+
+        let mut resp = Response::build();
+        resp.header(ContentType::new(
+            "application",
+            "vnd.oci.image.index.v1+json",
+        ))
+        .sized_body(json.len(), Cursor::new(json));
+        if self.artifact_type.is_some() {
+            resp.header(Header::new("OCI-Filters-Applied", "artifactType"));
+        }
+        resp.ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::registry_interface::{Referrer, ReferrerList};
+    use crate::response::test_helper::test_route;
+    use rocket::http::Status;
+    use serde_json::Value;
+
+    #[test]
+    fn referrers_index() {
+        let referrers = vec![Referrer {
+            media_type: "application/vnd.oci.image.manifest.v1+json".to_string(),
+            digest: "sha256:05c6e08f1d9fdafa03147fcb8f82f124c76d2f70e3d989dc8aadb5e7d7450bec"
+                .to_string(),
+            size: 100,
+            artifact_type: Some("application/vnd.example.sbom".to_string()),
+            annotations: Default::default(),
+        }];
+        let mut response = test_route(ReferrerList {
+            referrers,
+            artifact_type: Some("application/vnd.example.sbom".to_string()),
+        });
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(
+            response.headers().get_one("Content-Type"),
+            Some("application/vnd.oci.image.index.v1+json")
+        );
+        assert_eq!(
+            response.headers().get_one("OCI-Filters-Applied"),
+            Some("artifactType")
+        );
+
+        let body = rocket::execute(response.body_mut().to_string()).unwrap();
+        let index: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(index["schemaVersion"], 2);
+        assert_eq!(index["manifests"][0]["size"], 100);
+        assert_eq!(
+            index["manifests"][0]["artifactType"],
+            "application/vnd.example.sbom"
+        );
+        assert!(index["manifests"][0].get("annotations").is_none());
+
+        let response = test_route(ReferrerList {
+            referrers: vec![],
+            artifact_type: None,
+        });
+        assert_eq!(response.headers().get_one("OCI-Filters-Applied"), None);
+    }
+}
@@ -6,12 +6,17 @@ use crate::NetAddr;
 use crate::TrowConfig;
 #[cfg(test)]
 #[cfg(test)]
-use rocket::local::Client;
+use rocket::local::blocking::Client;
 #[cfg(test)]
 use rocket::response::Responder;
+#[cfg(test)]
+use trow_server::transport::GrpcAddr;
 
 #[cfg(test)]
-pub fn test_route<'r, A: Responder<'r>>(handler: A) -> rocket::Response<'r> {
+pub fn test_route<A>(handler: A) -> rocket::Response<'static>
+where
+    A: for<'r> Responder<'r, 'static>,
+{
     let trow_config = TrowConfig {
         data_dir: "".to_string(),
         addr: NetAddr {
@@ -20,8 +25,11 @@ pub fn test_route<'r, A: Responder<'r>>(handler: A) -> rocket::Response<'r> {
         },
         tls: None,
         grpc: GrpcConfig {
-            listen: "trow:51000".to_owned(),
+            listen: GrpcAddr::Tcp("trow:51000".to_owned()),
+            backend: None,
+            tls: None,
         },
+        embedded_backend: false,
         proxy_hub: true,
         hub_user: None,
         hub_pass: None,
@@ -30,14 +38,16 @@ pub fn test_route<'r, A: Responder<'r>>(handler: A) -> rocket::Response<'r> {
         allow_images: vec![],
         deny_prefixes: vec![],
         deny_images: vec![],
+        s3: None,
+        upload_timeout: None,
+        allowed_artifact_types: vec![],
         dry_run: false,
         token_secret: "secret".to_string(),
         user: None,
     };
-    let rocket = rocket::Rocket::ignite().manage(trow_config);
-    let client = Client::new(rocket).expect("valid rocket instance");
+    let rocket = rocket::build().manage(trow_config);
+    let client = Client::tracked(rocket).expect("valid rocket instance");
     let request = client.get("/");
-    let request = request.inner();
 
-    handler.respond_to(&request).unwrap()
+    handler.respond_to(request.inner()).unwrap()
 }
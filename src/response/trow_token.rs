@@ -3,9 +3,10 @@ use crate::UserConfig;
 use frank_jwt::{decode, encode, Algorithm, ValidationOptions};
 use rocket::http::ContentType;
 use rocket::http::Status;
+use rocket::outcome::Outcome;
 use rocket::request::{self, FromRequest, Request};
 use rocket::response::{Responder, Response};
-use rocket::{Outcome, State};
+use rocket::State;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::io::Cursor;
@@ -20,25 +21,27 @@ pub struct ValidBasicToken {
     user: String,
 }
 
-impl<'a, 'r> FromRequest<'a, 'r> for ValidBasicToken {
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ValidBasicToken {
     type Error = ();
-    fn from_request(req: &'a Request<'r>) -> request::Outcome<ValidBasicToken, ()> {
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<ValidBasicToken, ()> {
         let config = req
-            .guard::<rocket::State<TrowConfig>>()
+            .rocket()
+            .state::<TrowConfig>()
             .expect("TrowConfig not present!");
 
         let user_cfg = match config.user {
             Some(ref user_cfg) => user_cfg,
             None => {
                 warn!("Attempted login, but no users are configured");
-                return Outcome::Failure((Status::Unauthorized, ()));
+                return Outcome::Error((Status::Unauthorized, ()));
             }
         };
 
         // As Authorization is a standard header
         let auth_val = match req.headers().get_one(AUTHORIZATION) {
             Some(a) => a,
-            None => return Outcome::Failure((Status::Unauthorized, ())),
+            None => return Outcome::Error((Status::Unauthorized, ())),
         };
 
         // The value of the header is the type of the auth (Basic or Bearer), followed by an
@@ -46,12 +49,12 @@ impl<'a, 'r> FromRequest<'a, 'r> for ValidBasicToken {
         let auth_strings: Vec<String> = auth_val.split_whitespace().map(String::from).collect();
         if auth_strings.len() != 2 {
             //TODO: Should this be BadRequest?
-            return Outcome::Failure((Status::Unauthorized, ()));
+            return Outcome::Error((Status::Unauthorized, ()));
         }
         // We're looking for a Basic token
         if auth_strings[0] != "Basic" {
             //TODO: This probably isn't right, maybe check if bearer?
-            return Outcome::Failure((Status::Unauthorized, ()));
+            return Outcome::Error((Status::Unauthorized, ()));
         }
 
         match base64::decode(&auth_strings[1]) {
@@ -61,10 +64,10 @@ impl<'a, 'r> FromRequest<'a, 'r> for ValidBasicToken {
                         user: user_cfg.user.clone(),
                     })
                 } else {
-                    Outcome::Failure((Status::Unauthorized, ()))
+                    Outcome::Error((Status::Unauthorized, ()))
                 }
             }
-            Err(_) => Outcome::Failure((Status::Unauthorized, ())),
+            Err(_) => Outcome::Error((Status::Unauthorized, ())),
         }
     }
 }
@@ -125,7 +128,7 @@ struct TokenClaim {
  * Create new jsonwebtoken.
  * Token consists of a string with 3 comma separated fields header, payload, signature
  */
-pub fn new(vbt: ValidBasicToken, tc: State<TrowConfig>) -> Result<TrowToken, frank_jwt::Error> {
+pub fn new(vbt: ValidBasicToken, tc: &State<TrowConfig>) -> Result<TrowToken, frank_jwt::Error> {
     let current_time = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .expect("Time went backwards");
@@ -155,14 +158,14 @@ pub fn new(vbt: ValidBasicToken, tc: State<TrowConfig>) -> Result<TrowToken, fra
 /*
  * Responder returns token as JSON body
  */
-impl<'r> Responder<'r> for TrowToken {
-    fn respond_to(self, _: &Request) -> Result<Response<'r>, Status> {
+impl<'r, 'o: 'r> Responder<'r, 'o> for TrowToken {
+    fn respond_to(self, _: &'r Request<'_>) -> Result<Response<'o>, Status> {
         //TODO: would be better to use serde here
         let formatted_body = Cursor::new(format!("{{\"token\": \"{}\"}}", self.token));
         Response::build()
             .status(Status::Ok)
             .header(ContentType::JSON)
-            .sized_body(formatted_body)
+            .sized_body(None, formatted_body)
             .ok()
     }
 }
@@ -170,11 +173,13 @@ impl<'r> Responder<'r> for TrowToken {
 /*
  *
  */
-impl<'a, 'r> FromRequest<'a, 'r> for TrowToken {
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TrowToken {
     type Error = ();
-    fn from_request(req: &'a Request<'r>) -> request::Outcome<TrowToken, ()> {
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<TrowToken, ()> {
         let config = req
-            .guard::<rocket::State<TrowConfig>>()
+            .rocket()
+            .state::<TrowConfig>()
             .expect("TrowConfig not present!");
 
         if config.user.is_none() {
@@ -188,19 +193,19 @@ impl<'a, 'r> FromRequest<'a, 'r> for TrowToken {
         }
         let auth_val = match req.headers().get_one("Authorization") {
             Some(a) => a,
-            None => return Outcome::Failure((Status::Unauthorized, ())),
+            None => return Outcome::Error((Status::Unauthorized, ())),
         };
 
         // Check header handling - isn't there a next?
         // split the header on white space
         let auth_strings: Vec<String> = auth_val.split_whitespace().map(String::from).collect();
         if auth_strings.len() != 2 {
-            return Outcome::Failure((Status::BadRequest, ()));
+            return Outcome::Error((Status::BadRequest, ()));
         }
         // We're looking for a Bearer token
         //TODO: Maybe should forward or something on Basic
         if auth_strings[0] != "Bearer" {
-            return Outcome::Failure((Status::Unauthorized, ()));
+            return Outcome::Error((Status::Unauthorized, ()));
         }
 
         // parse for bearer token
@@ -215,7 +220,7 @@ impl<'a, 'r> FromRequest<'a, 'r> for TrowToken {
             Ok((_, payload)) => payload,
             Err(_) => {
                 warn!("Failed to decode user token");
-                return Outcome::Failure((Status::Unauthorized, ()));
+                return Outcome::Error((Status::Unauthorized, ()));
             }
         };
 
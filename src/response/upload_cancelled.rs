@@ -0,0 +1,10 @@
+use crate::types::UploadCancelled;
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+
+impl<'r, 'o: 'r> Responder<'r, 'o> for UploadCancelled {
+    fn respond_to(self, _req: &'r Request<'_>) -> response::Result<'o> {
+        Response::build().status(Status::NoContent).ok()
+    }
+}
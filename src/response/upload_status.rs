@@ -0,0 +1,58 @@
+use crate::response::get_base_url;
+pub use crate::types::{create_upload_status, UploadStatus};
+use rocket::http::{Header, Status};
+use rocket::request::Request;
+use rocket::response::{Responder, Response};
+
+impl<'r, 'o: 'r> Responder<'r, 'o> for UploadStatus {
+    fn respond_to(self, req: &'r Request<'_>) -> Result<Response<'o>, Status> {
+        let location_url = format!(
+            "{}/v2/{}/blobs/uploads/{}",
+            get_base_url(req),
+            self.repo_name(),
+            self.uuid()
+        );
+        // Range is inclusive, so the client resumes from the byte after the end.
+        // The spec has "0-0" for an upload with no data.
+        let end = self.uploaded().saturating_sub(1);
+        let upload_uuid = Header::new("Docker-Upload-UUID", self.uuid().0.clone());
+        let range = Header::new("Range", format!("0-{}", end));
+        let location = Header::new("Location", location_url);
+
+        Response::build()
+            .header(upload_uuid)
+            .header(location)
+            .header(range)
+            .status(Status::NoContent)
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::response::upload_status::{create_upload_status, UploadStatus};
+    use crate::types::{RepoName, Uuid};
+    use rocket::http::Status;
+
+    use crate::response::test_helper::test_route;
+    fn build_response() -> UploadStatus {
+        create_upload_status(
+            Uuid("whatever".to_owned()),
+            RepoName("moredhel/test".to_owned()),
+            10,
+        )
+    }
+
+    #[test]
+    fn upload_status() {
+        let response = test_route(build_response());
+        let headers = response.headers();
+        assert_eq!(response.status(), Status::NoContent);
+        assert_eq!(headers.get_one("Docker-Upload-UUID"), Some("whatever"));
+        assert_eq!(headers.get_one("Range"), Some("0-9"));
+        assert!(headers
+            .get_one("Location")
+            .unwrap()
+            .ends_with("/v2/moredhel/test/blobs/uploads/whatever"));
+    }
+}
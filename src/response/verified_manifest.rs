@@ -5,8 +5,8 @@ use rocket::http::Status;
 use rocket::request::Request;
 use rocket::response::{Responder, Response};
 
-impl<'r> Responder<'r> for VerifiedManifest {
-    fn respond_to(self, req: &Request) -> Result<Response<'r>, Status> {
+impl<'r, 'o: 'r> Responder<'r, 'o> for VerifiedManifest {
+    fn respond_to(self, req: &'r Request<'_>) -> Result<Response<'o>, Status> {
         //The front end is responsible for assembling URLs, backend should deal in arguments
         let location = format!(
             "{}/v2/{}/manifests/{}",
@@ -16,17 +16,21 @@ impl<'r> Responder<'r> for VerifiedManifest {
         );
         let location_header = Header::new("Location", location);
         let digest = Header::new("Docker-Content-Digest", self.digest().to_string());
-        Response::build()
-            .status(Status::Created)
+        let mut resp = Response::build();
+        resp.status(Status::Created)
             .header(location_header)
-            .header(digest)
-            .ok()
+            .header(digest);
+        // Tells clients the subject was indexed, so they needn't fall back to tagging referrers
+        if let Some(subject) = self.subject() {
+            resp.header(Header::new("OCI-Subject", subject.to_string()));
+        }
+        resp.ok()
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::registry_interface::{Digest, DigestAlgorithm};
+    use crate::registry_interface::digest::{Digest, DigestAlgorithm};
     use crate::response::test_helper::test_route;
     use crate::types::{create_verified_manifest, RepoName};
     use rocket::http::Status;
@@ -41,7 +45,27 @@ mod test {
                     .to_string(),
             },
             "ref".to_string(),
+            None,
         ));
         assert_eq!(response.status(), Status::Created);
+        assert_eq!(response.headers().get_one("OCI-Subject"), None);
+    }
+
+    #[test]
+    fn reports_subject() {
+        let digest = Digest {
+            algo: DigestAlgorithm::Sha256,
+            hash: "05c6e08f1d9fdafa03147fcb8f82f124c76d2f70e3d989dc8aadb5e7d7450bec".to_string(),
+        };
+        let response = test_route(create_verified_manifest(
+            RepoName("repo_name".to_string()),
+            digest.clone(),
+            "ref".to_string(),
+            Some(digest.clone()),
+        ));
+        assert_eq!(
+            response.headers().get_one("OCI-Subject"),
+            Some(digest.to_string().as_str())
+        );
     }
 }
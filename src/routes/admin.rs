@@ -0,0 +1,45 @@
+use crate::client_interface::ClientInterface;
+use crate::registry_interface::{AdminOperations, FsckReport, GarbageCollectionReport};
+use crate::response::errors::Error;
+use crate::response::trow_token::TrowToken;
+
+use rocket::State;
+
+/*
+ * Remove blobs that can no longer be reached from a current tag, directly or through an index.
+ * POST /admin/gc?dry_run=true
+ *
+ * Unlike deleting a single blob, manifests that are only in the history of a tag or deleted tag,
+ * or were pushed by digest, don't keep blobs; they are removed along with the blobs only they use.
+ * With dry_run set, nothing is deleted and the blobs that would be removed are reported. Blobs
+ * written, checked for or mounted within the last hour are never removed, so this is safe to run
+ * during pushes.
+ */
+#[post("/admin/gc?<dry_run>")]
+pub async fn collect_garbage(
+    _auth_user: TrowToken,
+    ci: &State<ClientInterface>,
+    dry_run: Option<bool>,
+) -> Result<GarbageCollectionReport, Error> {
+    ci.collect_garbage(dry_run.unwrap_or(false))
+        .await
+        .map_err(|_| Error::InternalError)
+}
+
+/*
+ * Check the storage for corrupt blobs and broken tags and manifests.
+ * POST /admin/fsck?repair=true
+ *
+ * Every blob is re-hashed, so this can take a long time on large registries. With repair set,
+ * corrupt blobs are moved out of the catalog into a quarantine directory.
+ */
+#[post("/admin/fsck?<repair>")]
+pub async fn fsck(
+    _auth_user: TrowToken,
+    ci: &State<ClientInterface>,
+    repair: Option<bool>,
+) -> Result<FsckReport, Error> {
+    ci.fsck(repair.unwrap_or(false))
+        .await
+        .map_err(|_| Error::InternalError)
+}
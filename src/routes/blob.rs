@@ -1,14 +1,19 @@
+use super::query_param;
 use crate::client_interface::ClientInterface;
-use crate::registry_interface::{digest, BlobReader, BlobStorage, ContentInfo, StorageDriverError};
+use crate::registry_interface::{
+    digest, BlobInfo, BlobReader, BlobStorage, ContentInfo, StorageDriverError,
+};
 use crate::response::errors::Error;
 use crate::response::trow_token::TrowToken;
 use crate::response::upload_info::UploadInfo;
+use crate::response::upload_status::UploadStatus;
 use crate::types::{
-    create_accepted_upload, create_upload_info, AcceptedUpload, BlobDeleted, RepoName, Upload, Uuid,
+    create_accepted_upload, create_upload_info, create_upload_status, AcceptedUpload, BlobDeleted,
+    RepoName, Upload, UploadCancelled, Uuid,
 };
-use rocket::http::uri::{Origin, Uri};
-
-use std::io::Read;
+use rocket::data::ByteUnit;
+use rocket::http::uri::Origin;
+use trow_server::is_writable_repo;
 
 /*
 ---
@@ -23,15 +28,15 @@ digest - unique identifier for the blob to be downoaded
  */
 
 #[get("/v2/<name_repo>/blobs/<digest>")]
-pub fn get_blob(
+pub async fn get_blob(
     _auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     name_repo: String,
     digest: String,
 ) -> Option<BlobReader> {
     let digest = digest::parse(&digest);
     match digest {
-        Ok(d) => ci.get_blob(&name_repo, &d).ok(),
+        Ok(d) => ci.get_blob(&name_repo, &d).await.ok(),
         Err(_) => None,
     }
 }
@@ -41,38 +46,38 @@ pub fn get_blob(
  */
 
 #[get("/v2/<name>/<repo>/blobs/<digest>")]
-pub fn get_blob_2level(
+pub async fn get_blob_2level(
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     name: String,
     repo: String,
     digest: String,
 ) -> Option<BlobReader> {
-    get_blob(auth_user, ci, format!("{}/{}", name, repo), digest)
+    get_blob(auth_user, ci, format!("{}/{}", name, repo), digest).await
 }
 
 /*
  * Parse 3 level <org>/<repo>/<name> style path and pass it to get_blob
  */
 #[get("/v2/<org>/<name>/<repo>/blobs/<digest>")]
-pub fn get_blob_3level(
+pub async fn get_blob_3level(
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     org: String,
     name: String,
     repo: String,
     digest: String,
 ) -> Option<BlobReader> {
-    get_blob(auth_user, ci, format!("{}/{}/{}", org, name, repo), digest)
+    get_blob(auth_user, ci, format!("{}/{}/{}", org, name, repo), digest).await
 }
 
 /*
  * Parse 4 level <org>/<repo>/<name> style path and pass it to get_blob
  */
 #[get("/v2/<fourth>/<org>/<name>/<repo>/blobs/<digest>")]
-pub fn get_blob_4level(
+pub async fn get_blob_4level(
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     fourth: String,
     org: String,
     name: String,
@@ -85,6 +90,75 @@ pub fn get_blob_4level(
         format!("{}/{}/{}/{}", fourth, org, name, repo),
         digest,
     )
+    .await
+}
+
+/*
+---
+Checking for a Layer
+HEAD /v2/<name>/blobs/<digest>
+
+Clients check for each layer before pushing it. Returns the blob's Content-Length and
+Docker-Content-Digest without reading it.
+
+# Responses
+200 - blob exists
+404 - blob is unknown
+ */
+#[head("/v2/<name_repo>/blobs/<digest>")]
+pub async fn head_blob(
+    _auth_user: TrowToken,
+    ci: &rocket::State<ClientInterface>,
+    name_repo: String,
+    digest: String,
+) -> Option<BlobInfo> {
+    let digest = digest::parse(&digest);
+    match digest {
+        Ok(d) => ci.get_blob_info(&name_repo, &d).await.ok(),
+        Err(_) => None,
+    }
+}
+
+#[head("/v2/<name>/<repo>/blobs/<digest>")]
+pub async fn head_blob_2level(
+    auth_user: TrowToken,
+    ci: &rocket::State<ClientInterface>,
+    name: String,
+    repo: String,
+    digest: String,
+) -> Option<BlobInfo> {
+    head_blob(auth_user, ci, format!("{}/{}", name, repo), digest).await
+}
+
+#[head("/v2/<org>/<name>/<repo>/blobs/<digest>")]
+pub async fn head_blob_3level(
+    auth_user: TrowToken,
+    ci: &rocket::State<ClientInterface>,
+    org: String,
+    name: String,
+    repo: String,
+    digest: String,
+) -> Option<BlobInfo> {
+    head_blob(auth_user, ci, format!("{}/{}/{}", org, name, repo), digest).await
+}
+
+#[head("/v2/<fourth>/<org>/<name>/<repo>/blobs/<digest>")]
+pub async fn head_blob_4level(
+    auth_user: TrowToken,
+    ci: &rocket::State<ClientInterface>,
+    fourth: String,
+    org: String,
+    name: String,
+    repo: String,
+    digest: String,
+) -> Option<BlobInfo> {
+    head_blob(
+        auth_user,
+        ci,
+        format!("{}/{}/{}/{}", fourth, org, name, repo),
+        digest,
+    )
+    .await
 }
 
 /*
@@ -101,17 +175,20 @@ Content-Type: application/octet-stream
  * Completes the upload.
  */
 #[put("/v2/<repo_name>/blobs/uploads/<uuid>?<digest>", data = "<chunk>")]
-pub fn put_blob(
+pub async fn put_blob(
     _auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     repo_name: String,
     uuid: String,
     digest: String,
-    chunk: rocket::data::Data,
+    chunk: rocket::data::Data<'_>,
 ) -> Result<AcceptedUpload, Error> {
-    let mut data: Box<dyn Read> = Box::new(chunk.open());
+    let mut data = chunk.open(ByteUnit::max_value());
 
-    let size = match ci.store_blob_chunk(&repo_name, &uuid, None, &mut data) {
+    let size = match ci
+        .store_blob_chunk(&repo_name, &uuid, None, &mut data)
+        .await
+    {
         Ok(size) => size,
         Err(StorageDriverError::InvalidName(name)) => return Err(Error::NameInvalid(name)),
         Err(StorageDriverError::InvalidContentRange) => return Err(Error::BlobUploadInvalid),
@@ -120,6 +197,7 @@ pub fn put_blob(
 
     let digest_obj = digest::parse(&digest).map_err(|_| Error::DigestInvalid)?;
     ci.complete_and_verify_blob_upload(&repo_name, &uuid, &digest_obj)
+        .await
         .map_err(|e| match e {
             StorageDriverError::InvalidDigest => Error::DigestInvalid,
             _ => Error::InternalError,
@@ -128,8 +206,7 @@ pub fn put_blob(
     Ok(create_accepted_upload(
         digest_obj,
         RepoName(repo_name),
-        Uuid(uuid),
-        (0, (size as u32)),
+        (0, size),
     ))
 }
 
@@ -137,14 +214,14 @@ pub fn put_blob(
  * Parse 2 level <repo>/<name> style path and pass it to put_blob
  */
 #[put("/v2/<repo>/<name>/blobs/uploads/<uuid>?<digest>", data = "<chunk>")]
-pub fn put_blob_2level(
+pub async fn put_blob_2level(
     auth_user: TrowToken,
-    config: rocket::State<ClientInterface>,
+    config: &rocket::State<ClientInterface>,
     repo: String,
     name: String,
     uuid: String,
     digest: String,
-    chunk: rocket::data::Data,
+    chunk: rocket::data::Data<'_>,
 ) -> Result<AcceptedUpload, Error> {
     put_blob(
         auth_user,
@@ -154,6 +231,7 @@ pub fn put_blob_2level(
         digest,
         chunk,
     )
+    .await
 }
 
 /*
@@ -163,15 +241,15 @@ pub fn put_blob_2level(
     "/v2/<org>/<repo>/<name>/blobs/uploads/<uuid>?<digest>",
     data = "<chunk>"
 )]
-pub fn put_blob_3level(
+pub async fn put_blob_3level(
     auth_user: TrowToken,
-    config: rocket::State<ClientInterface>,
+    config: &rocket::State<ClientInterface>,
     org: String,
     repo: String,
     name: String,
     uuid: String,
     digest: String,
-    chunk: rocket::data::Data,
+    chunk: rocket::data::Data<'_>,
 ) -> Result<AcceptedUpload, Error> {
     put_blob(
         auth_user,
@@ -181,6 +259,7 @@ pub fn put_blob_3level(
         digest,
         chunk,
     )
+    .await
 }
 
 /*
@@ -190,9 +269,9 @@ pub fn put_blob_3level(
     "/v2/<fourth>/<org>/<repo>/<name>/blobs/uploads/<uuid>?<digest>",
     data = "<chunk>"
 )]
-pub fn put_blob_4level(
+pub async fn put_blob_4level(
     auth_user: TrowToken,
-    config: rocket::State<ClientInterface>,
+    config: &rocket::State<ClientInterface>,
 
     fourth: String,
     org: String,
@@ -200,7 +279,7 @@ pub fn put_blob_4level(
     name: String,
     uuid: String,
     digest: String,
-    chunk: rocket::data::Data,
+    chunk: rocket::data::Data<'_>,
 ) -> Result<AcceptedUpload, Error> {
     put_blob(
         auth_user,
@@ -210,6 +289,7 @@ pub fn put_blob_4level(
         digest,
         chunk,
     )
+    .await
 }
 
 /*
@@ -231,21 +311,24 @@ Checks UUID. Returns UploadInfo with range set to correct position.
 
 */
 #[patch("/v2/<repo_name>/blobs/uploads/<uuid>", data = "<chunk>")]
-pub fn patch_blob(
+pub async fn patch_blob(
     _auth_user: TrowToken,
     info: Option<ContentInfo>,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     repo_name: String,
     uuid: String,
-    chunk: rocket::data::Data,
+    chunk: rocket::data::Data<'_>,
 ) -> Result<UploadInfo, Error> {
-    let mut data: Box<dyn Read> = Box::new(chunk.open());
+    let mut data = chunk.open(ByteUnit::max_value());
 
-    match ci.store_blob_chunk(&repo_name, &uuid, info, &mut data) {
+    match ci
+        .store_blob_chunk(&repo_name, &uuid, info, &mut data)
+        .await
+    {
         Ok(size) => {
             let repo_name = RepoName(repo_name);
             let uuid = Uuid(uuid);
-            Ok(create_upload_info(uuid, repo_name, (0, size as u32)))
+            Ok(create_upload_info(uuid, repo_name, (0, size)))
         }
         Err(StorageDriverError::InvalidName(name)) => Err(Error::NameInvalid(name)),
         Err(StorageDriverError::InvalidContentRange) => Err(Error::BlobUploadInvalid),
@@ -257,14 +340,14 @@ pub fn patch_blob(
  * Parse 2 level <repo>/<name> style path and pass it to patch_blob
  */
 #[patch("/v2/<repo>/<name>/blobs/uploads/<uuid>", data = "<chunk>")]
-pub fn patch_blob_2level(
+pub async fn patch_blob_2level(
     auth_user: TrowToken,
     info: Option<ContentInfo>,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     repo: String,
     name: String,
     uuid: String,
-    chunk: rocket::data::Data,
+    chunk: rocket::data::Data<'_>,
 ) -> Result<UploadInfo, Error> {
     patch_blob(
         auth_user,
@@ -274,21 +357,22 @@ pub fn patch_blob_2level(
         uuid,
         chunk,
     )
+    .await
 }
 
 /*
  * Parse 3 level <org>/<repo>/<name> style path and pass it to patch_blob
  */
 #[patch("/v2/<org>/<repo>/<name>/blobs/uploads/<uuid>", data = "<chunk>")]
-pub fn patch_blob_3level(
+pub async fn patch_blob_3level(
     auth_user: TrowToken,
     info: Option<ContentInfo>,
-    handler: rocket::State<ClientInterface>,
+    handler: &rocket::State<ClientInterface>,
     org: String,
     repo: String,
     name: String,
     uuid: String,
-    chunk: rocket::data::Data,
+    chunk: rocket::data::Data<'_>,
 ) -> Result<UploadInfo, Error> {
     patch_blob(
         auth_user,
@@ -298,6 +382,7 @@ pub fn patch_blob_3level(
         uuid,
         chunk,
     )
+    .await
 }
 
 /*
@@ -307,16 +392,16 @@ pub fn patch_blob_3level(
     "/v2/<fourth>/<org>/<repo>/<name>/blobs/uploads/<uuid>",
     data = "<chunk>"
 )]
-pub fn patch_blob_4level(
+pub async fn patch_blob_4level(
     auth_user: TrowToken,
     info: Option<ContentInfo>,
-    handler: rocket::State<ClientInterface>,
+    handler: &rocket::State<ClientInterface>,
     fourth: String,
     org: String,
     repo: String,
     name: String,
     uuid: String,
-    chunk: rocket::data::Data,
+    chunk: rocket::data::Data<'_>,
 ) -> Result<UploadInfo, Error> {
     patch_blob(
         auth_user,
@@ -326,6 +411,183 @@ pub fn patch_blob_4level(
         uuid,
         chunk,
     )
+    .await
+}
+
+/*
+---
+Upload Progress
+GET /v2/<name>/blobs/uploads/<uuid>
+
+Reports how much of the upload has been received, so an interrupted upload can be resumed.
+
+# Responses
+204 - Range header gives the bytes received so far
+404 - upload is unknown e.g. was completed, cancelled or expired
+*/
+#[get("/v2/<repo_name>/blobs/uploads/<uuid>")]
+pub async fn get_upload_status(
+    _auth_user: TrowToken,
+    ci: &rocket::State<ClientInterface>,
+    repo_name: String,
+    uuid: String,
+) -> Result<UploadStatus, Error> {
+    match ci.status_blob_upload(&repo_name, &uuid).await {
+        Ok(info) => Ok(create_upload_status(
+            Uuid(info.session_id),
+            RepoName(info.name),
+            info.uploaded,
+        )),
+        Err(StorageDriverError::UnknownUpload(_)) => Err(Error::BlobUploadUnknown),
+        Err(_) => Err(Error::InternalError),
+    }
+}
+
+/*
+ * Parse 2 level <repo>/<name> style path and pass it to get_upload_status
+ */
+#[get("/v2/<repo>/<name>/blobs/uploads/<uuid>")]
+pub async fn get_upload_status_2level(
+    auth_user: TrowToken,
+    ci: &rocket::State<ClientInterface>,
+    repo: String,
+    name: String,
+    uuid: String,
+) -> Result<UploadStatus, Error> {
+    get_upload_status(auth_user, ci, format!("{}/{}", repo, name), uuid).await
+}
+
+/*
+ * Parse 3 level <org>/<repo>/<name> style path and pass it to get_upload_status
+ */
+#[get("/v2/<org>/<repo>/<name>/blobs/uploads/<uuid>")]
+pub async fn get_upload_status_3level(
+    auth_user: TrowToken,
+    ci: &rocket::State<ClientInterface>,
+    org: String,
+    repo: String,
+    name: String,
+    uuid: String,
+) -> Result<UploadStatus, Error> {
+    get_upload_status(auth_user, ci, format!("{}/{}/{}", org, repo, name), uuid).await
+}
+
+/*
+ * Parse 4 level <fourth>/<org>/<repo>/<name> style path and pass it to get_upload_status
+ */
+#[get("/v2/<fourth>/<org>/<repo>/<name>/blobs/uploads/<uuid>")]
+pub async fn get_upload_status_4level(
+    auth_user: TrowToken,
+    ci: &rocket::State<ClientInterface>,
+    fourth: String,
+    org: String,
+    repo: String,
+    name: String,
+    uuid: String,
+) -> Result<UploadStatus, Error> {
+    get_upload_status(
+        auth_user,
+        ci,
+        format!("{}/{}/{}/{}", fourth, org, repo, name),
+        uuid,
+    )
+    .await
+}
+
+/*
+---
+Canceling an Upload
+DELETE /v2/<name>/blobs/uploads/<uuid>
+
+Removes the upload and any data received so far.
+
+# Responses
+204 - upload cancelled
+404 - upload is unknown
+*/
+#[delete("/v2/<repo_name>/blobs/uploads/<uuid>")]
+pub async fn delete_upload(
+    _auth_user: TrowToken,
+    ci: &rocket::State<ClientInterface>,
+    repo_name: String,
+    uuid: String,
+) -> Result<UploadCancelled, Error> {
+    match ci.cancel_blob_upload(&repo_name, &uuid).await {
+        Ok(_) => Ok(UploadCancelled {}),
+        Err(StorageDriverError::UnknownUpload(_)) => Err(Error::BlobUploadUnknown),
+        Err(_) => Err(Error::InternalError),
+    }
+}
+
+#[delete("/v2/<repo>/<name>/blobs/uploads/<uuid>")]
+pub async fn delete_upload_2level(
+    auth_user: TrowToken,
+    ci: &rocket::State<ClientInterface>,
+    repo: String,
+    name: String,
+    uuid: String,
+) -> Result<UploadCancelled, Error> {
+    delete_upload(auth_user, ci, format!("{}/{}", repo, name), uuid).await
+}
+
+#[delete("/v2/<org>/<repo>/<name>/blobs/uploads/<uuid>")]
+pub async fn delete_upload_3level(
+    auth_user: TrowToken,
+    ci: &rocket::State<ClientInterface>,
+    org: String,
+    repo: String,
+    name: String,
+    uuid: String,
+) -> Result<UploadCancelled, Error> {
+    delete_upload(auth_user, ci, format!("{}/{}/{}", org, repo, name), uuid).await
+}
+
+#[delete("/v2/<fourth>/<org>/<repo>/<name>/blobs/uploads/<uuid>")]
+pub async fn delete_upload_4level(
+    auth_user: TrowToken,
+    ci: &rocket::State<ClientInterface>,
+    fourth: String,
+    org: String,
+    repo: String,
+    name: String,
+    uuid: String,
+) -> Result<UploadCancelled, Error> {
+    delete_upload(
+        auth_user,
+        ci,
+        format!("{}/{}/{}/{}", fourth, org, repo, name),
+        uuid,
+    )
+    .await
+}
+
+/*
+ * If the blob is already stored, accepts the upload without any data being transferred.
+ *
+ * Blobs are shared by all repositories and any authenticated user can read every repository, so
+ * mounting from another repository only requires the blob to exist. Nothing is accepted into
+ * repositories that can't be pushed to; starting the upload then fails as for any other push.
+ */
+async fn accept_existing_blob(
+    ci: &ClientInterface,
+    from_repo: &str,
+    repo_name: &str,
+    digest: &str,
+) -> Option<AcceptedUpload> {
+    if !is_writable_repo(repo_name) {
+        return None;
+    }
+    let digest = digest::parse(digest).ok()?;
+    let info = ci.get_blob_info(from_repo, &digest).await.ok()?;
+    info!(
+        "Blob {} already stored, accepting upload to {} without data",
+        info.digest, repo_name
+    );
+    Some(create_accepted_upload(
+        info.digest,
+        RepoName(repo_name.to_string()),
+        (0, info.size),
+    ))
 }
 
 /*
@@ -335,15 +597,32 @@ pub fn patch_blob_4level(
 
  No data is being transferred _unless_ the request ends with "?digest".
  In this case the whole blob is attached.
+
+ With "?mount=<digest>&from=<repo>" the client asks to reuse a blob from another repository.
+ If we have the blob we respond with 201 Created, otherwise a normal upload is started. The same
+ applies to "?digest" for a blob we already have, in which case the attached data is ignored.
 */
 #[post("/v2/<repo_name>/blobs/uploads", data = "<data>")]
-pub fn post_blob_upload(
-    uri: &Origin, // This is a mess, but needed to check for ?digest
+pub async fn post_blob_upload(
+    uri: &Origin<'_>, // This is a mess, but needed to check for ?digest
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     repo_name: String,
-    data: rocket::data::Data,
+    data: rocket::data::Data<'_>,
 ) -> Result<Upload, Error> {
+    let digest = query_param(uri, "digest");
+    let existing = match (query_param(uri, "mount"), &digest) {
+        (Some(mount), _) => {
+            let from = query_param(uri, "from").unwrap_or_else(|| repo_name.clone());
+            accept_existing_blob(ci, &from, &repo_name, &mount).await
+        }
+        (None, Some(digest)) => accept_existing_blob(ci, &repo_name, &repo_name, digest).await,
+        (None, None) => None,
+    };
+    if let Some(accepted) = existing {
+        return Ok(Upload::Accepted(accepted));
+    }
+
     /*
     Ask the backend for a UUID.
 
@@ -355,26 +634,19 @@ pub fn post_blob_upload(
     optimisation, but is arguably less flexible.
     */
 
-    let uuid = ci.start_blob_upload(&repo_name).map_err(|e| match e {
-        StorageDriverError::InvalidName(n) => Error::NameInvalid(n),
-        _ => Error::InternalError,
-    })?;
+    let uuid = ci
+        .start_blob_upload(&repo_name)
+        .await
+        .map_err(|e| match e {
+            StorageDriverError::InvalidName(n) => Error::NameInvalid(n),
+            _ => Error::InternalError,
+        })?;
 
-    if let Some(digest) = uri.query() {
-        if digest.starts_with("digest=") {
-            //Have a monolithic upload with data
-
-            let digest = &Uri::percent_decode_lossy(&digest["digest=".len()..].as_bytes());
-            return put_blob(
-                auth_user,
-                ci,
-                repo_name.to_string(),
-                uuid,
-                digest.to_string(),
-                data,
-            )
-            .map(|r| Upload::Accepted(r));
-        }
+    if let Some(digest) = digest {
+        //Have a monolithic upload with data
+        return put_blob(auth_user, ci, repo_name, uuid, digest, data)
+            .await
+            .map(Upload::Accepted);
     }
 
     Ok(Upload::Info(create_upload_info(
@@ -388,31 +660,31 @@ pub fn post_blob_upload(
  * Parse 2 level <repo>/<name> style path and pass it to put_blob_upload_onename
  */
 #[post("/v2/<repo>/<name>/blobs/uploads", data = "<data>")]
-pub fn post_blob_upload_2level(
+pub async fn post_blob_upload_2level(
     //digest: PossibleDigest, //create requestguard to handle /?digest
-    uri: &Origin,
+    uri: &Origin<'_>,
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     repo: String,
     name: String,
-    data: rocket::data::Data,
+    data: rocket::data::Data<'_>,
 ) -> Result<Upload, Error> {
-    post_blob_upload(uri, auth_user, ci, format!("{}/{}", repo, name), data)
+    post_blob_upload(uri, auth_user, ci, format!("{}/{}", repo, name), data).await
 }
 
 /*
  * Parse 3 level <org>/<repo>/<name> style path and pass it to put_blob_upload_onename
  */
 #[post("/v2/<org>/<repo>/<name>/blobs/uploads", data = "<data>")]
-pub fn post_blob_upload_3level(
+pub async fn post_blob_upload_3level(
     //digest: PossibleDigest, //create requestguard to handle /?digest
-    uri: &Origin,
+    uri: &Origin<'_>,
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     org: String,
     repo: String,
     name: String,
-    data: rocket::data::Data,
+    data: rocket::data::Data<'_>,
 ) -> Result<Upload, Error> {
     post_blob_upload(
         uri,
@@ -421,22 +693,23 @@ pub fn post_blob_upload_3level(
         format!("{}/{}/{}", org, repo, name),
         data,
     )
+    .await
 }
 
 /*
  * Parse 4 level <fourth>/<org>/<repo>/<name> style path
  */
 #[post("/v2/<fourth>/<org>/<repo>/<name>/blobs/uploads", data = "<data>")]
-pub fn post_blob_upload_4level(
+pub async fn post_blob_upload_4level(
     //digest: PossibleDigest, //create requestguard to handle /?digest
-    uri: &Origin,
+    uri: &Origin<'_>,
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     fourth: String,
     org: String,
     repo: String,
     name: String,
-    data: rocket::data::Data,
+    data: rocket::data::Data<'_>,
 ) -> Result<Upload, Error> {
     post_blob_upload(
         uri,
@@ -445,6 +718,7 @@ pub fn post_blob_upload_4level(
         format!("{}/{}/{}/{}", fourth, org, repo, name),
         data,
     )
+    .await
 }
 
 /*
@@ -459,68 +733,71 @@ pub fn post_blob_upload_4level(
     "/v2/<fifth>/<fourth>/<org>/<repo>/<name>/blobs/uploads",
     data = "<_data>"
 )]
-pub fn post_blob_upload_5level(
+pub async fn post_blob_upload_5level(
     _auth_user: TrowToken,
     fifth: String,
     fourth: String,
     org: String,
     repo: String,
     name: String,
-    _data: rocket::data::Data,
+    _data: rocket::data::Data<'_>,
 ) -> rocket::response::status::BadRequest<String> {
-    rocket::response::status::BadRequest(Some(format!(
+    rocket::response::status::BadRequest(format!(
         "Repository names are limited to 4 levels: {}/{}/{}/{}/{} is not allowed",
         fifth, fourth, org, repo, name
-    )))
+    ))
 }
 
 /**
  * Deletes the given blob.
  *
  * Really unsure about this method - why should the user delete a blob?
- * TODO: This should probably be denied if the blob is referenced by any manifests
- * (manifest should be deleted first)
+ * Denied if any stored manifest refers to the blob: one a tag points to, one in the history of a
+ * current or deleted tag, or an untagged one such as the child of an index. Those manifests should
+ * be deleted first.
  */
 #[delete("/v2/<repo>/blobs/<digest>")]
-pub fn delete_blob(
+pub async fn delete_blob(
     _auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     repo: String,
     digest: String,
 ) -> Result<BlobDeleted, Error> {
     let digest = digest::parse(&digest).map_err(|_| Error::DigestInvalid)?;
-    ci.delete_blob(&repo, &digest)
-        .map_err(|_| Error::BlobUnknown)?;
+    ci.delete_blob(&repo, &digest).await.map_err(|e| match e {
+        StorageDriverError::BlobReferenced(tags) => Error::BlobReferenced(tags),
+        _ => Error::BlobUnknown,
+    })?;
     Ok(BlobDeleted {})
 }
 
 #[delete("/v2/<user>/<repo>/blobs/<digest>")]
-pub fn delete_blob_2level(
+pub async fn delete_blob_2level(
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     user: String,
     repo: String,
     digest: String,
 ) -> Result<BlobDeleted, Error> {
-    delete_blob(auth_user, ci, format!("{}/{}", user, repo), digest)
+    delete_blob(auth_user, ci, format!("{}/{}", user, repo), digest).await
 }
 
 #[delete("/v2/<org>/<user>/<repo>/blobs/<digest>")]
-pub fn delete_blob_3level(
+pub async fn delete_blob_3level(
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     org: String,
     user: String,
     repo: String,
     digest: String,
 ) -> Result<BlobDeleted, Error> {
-    delete_blob(auth_user, ci, format!("{}/{}/{}", org, user, repo), digest)
+    delete_blob(auth_user, ci, format!("{}/{}/{}", org, user, repo), digest).await
 }
 
 #[delete("/v2/<fourth>/<org>/<user>/<repo>/blobs/<digest>")]
-pub fn delete_blob_4level(
+pub async fn delete_blob_4level(
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     fourth: String,
     org: String,
     user: String,
@@ -533,4 +810,5 @@ pub fn delete_blob_4level(
         format!("{}/{}/{}/{}", fourth, org, user, repo),
         digest,
     )
+    .await
 }
@@ -5,9 +5,9 @@ use crate::response::trow_token::TrowToken;
 use crate::types::{RepoCatalog, TagList};
 
 #[get("/v2/_catalog?<n>&<last>")]
-pub fn get_catalog(
+pub async fn get_catalog(
     _auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     n: Option<u32>,
     last: Option<String>,
 ) -> Result<RepoCatalog, Error> {
@@ -16,15 +16,16 @@ pub fn get_catalog(
 
     let cat = ci
         .get_catalog(Some(&last_repo), Some(limit))
+        .await
         .map_err(|_| Error::InternalError)?;
 
     Ok(RepoCatalog::from(cat))
 }
 
 #[get("/v2/<repo_name>/tags/list?<last>&<n>")]
-pub fn list_tags(
+pub async fn list_tags(
     _auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     repo_name: String,
     last: Option<String>,
     n: Option<u32>,
@@ -34,39 +35,40 @@ pub fn list_tags(
 
     let tags = ci
         .get_tags(&repo_name, Some(&last_tag), Some(limit))
+        .await
         .map_err(|_| Error::InternalError)?;
     Ok(TagList::new_filled(repo_name, tags))
 }
 
 #[get("/v2/<user>/<repo>/tags/list?<last>&<n>")]
-pub fn list_tags_2level(
+pub async fn list_tags_2level(
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     user: String,
     repo: String,
     last: Option<String>,
     n: Option<u32>,
 ) -> Result<TagList, Error> {
-    list_tags(auth_user, ci, format!("{}/{}", user, repo), last, n)
+    list_tags(auth_user, ci, format!("{}/{}", user, repo), last, n).await
 }
 
 #[get("/v2/<org>/<user>/<repo>/tags/list?<last>&<n>")]
-pub fn list_tags_3level(
+pub async fn list_tags_3level(
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     org: String,
     user: String,
     repo: String,
     last: Option<String>,
     n: Option<u32>,
 ) -> Result<TagList, Error> {
-    list_tags(auth_user, ci, format!("{}/{}/{}", org, user, repo), last, n)
+    list_tags(auth_user, ci, format!("{}/{}/{}", org, user, repo), last, n).await
 }
 
 #[get("/v2/<fourth>/<org>/<user>/<repo>/tags/list?<last>&<n>")]
-pub fn list_tags_4level(
+pub async fn list_tags_4level(
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     fourth: String,
     org: String,
     user: String,
@@ -81,13 +83,14 @@ pub fn list_tags_4level(
         last,
         n,
     )
+    .await
 }
 
 // TODO add support for pagination
 #[get("/<onename>/manifest_history/<reference>?<last>&<n>")]
-pub fn get_manifest_history(
+pub async fn get_manifest_history(
     _auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     onename: String,
     reference: String,
     last: Option<String>,
@@ -98,14 +101,15 @@ pub fn get_manifest_history(
 
     let mh = ci
         .get_history(&onename, &reference, Some(&last_digest), Some(limit))
+        .await
         .map_err(|_| Error::InternalError)?;
     Ok(mh)
 }
 
 #[get("/<user>/<repo>/manifest_history/<reference>?<last>&<n>")]
-pub fn get_manifest_history_2level(
+pub async fn get_manifest_history_2level(
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     user: String,
     repo: String,
     reference: String,
@@ -120,12 +124,13 @@ pub fn get_manifest_history_2level(
         last,
         n,
     )
+    .await
 }
 
 #[get("/<org>/<user>/<repo>/manifest_history/<reference>?<last>&<n>")]
-pub fn get_manifest_history_3level(
+pub async fn get_manifest_history_3level(
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     org: String,
     user: String,
     repo: String,
@@ -141,12 +146,13 @@ pub fn get_manifest_history_3level(
         last,
         n,
     )
+    .await
 }
 
 #[get("/<fourth>/<org>/<user>/<repo>/manifest_history/<reference>?<last>&<n>")]
-pub fn get_manifest_history_4level(
+pub async fn get_manifest_history_4level(
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     fourth: String,
     org: String,
     user: String,
@@ -163,4 +169,5 @@ pub fn get_manifest_history_4level(
         last,
         n,
     )
+    .await
 }
@@ -10,9 +10,9 @@ use rocket::State;
 */
 
 #[get("/healthz")]
-pub fn healthz(ci: State<ClientInterface>) -> HealthResponse {
+pub async fn healthz(ci: &State<ClientInterface>) -> HealthResponse {
     HealthResponse {
         message: "".to_string(),
-        is_healthy: ci.is_healthy(),
+        is_healthy: ci.is_healthy().await,
     }
 }
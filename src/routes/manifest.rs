@@ -1,10 +1,15 @@
 use crate::client_interface::ClientInterface;
-use crate::registry_interface::{digest, ManifestReader, ManifestStorage, StorageDriverError};
+use crate::registry_interface::{
+    digest, ManifestInfo, ManifestReader, ManifestStorage, ReferrerList, StorageDriverError,
+};
 use crate::response::errors::Error;
 use crate::response::trow_token::TrowToken;
 use crate::types::{create_verified_manifest, ManifestDeleted, RepoName, VerifiedManifest};
+use rocket::data::ByteUnit;
+use rocket::http::uri::Origin;
+use trow_server::MAX_MANIFEST_SIZE;
 
-use std::io::Read;
+use super::query_param;
 
 /*
 ---
@@ -27,34 +32,35 @@ Accept: manifest-version
 404 - manifest not known to the registry
  */
 #[get("/v2/<onename>/manifests/<reference>")]
-pub fn get_manifest(
+pub async fn get_manifest(
     _auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     onename: String,
     reference: String,
 ) -> Result<ManifestReader, Error> {
     ci.get_manifest(&onename, &reference)
+        .await
         .map_err(|_| Error::ManifestUnknown(reference))
 }
 
 #[get("/v2/<user>/<repo>/manifests/<reference>")]
-pub fn get_manifest_2level(
+pub async fn get_manifest_2level(
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     user: String,
     repo: String,
     reference: String,
 ) -> Result<ManifestReader, Error> {
-    get_manifest(auth_user, ci, format!("{}/{}", user, repo), reference)
+    get_manifest(auth_user, ci, format!("{}/{}", user, repo), reference).await
 }
 
 /*
  * Process 3 level manifest path
  */
 #[get("/v2/<org>/<user>/<repo>/manifests/<reference>")]
-pub fn get_manifest_3level(
+pub async fn get_manifest_3level(
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     org: String,
     user: String,
     repo: String,
@@ -66,15 +72,16 @@ pub fn get_manifest_3level(
         format!("{}/{}/{}", org, user, repo),
         reference,
     )
+    .await
 }
 
 /*
  * Process 4 level manifest path
  */
 #[get("/v2/<fourth>/<org>/<user>/<repo>/manifests/<reference>")]
-pub fn get_manifest_4level(
+pub async fn get_manifest_4level(
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     fourth: String,
     org: String,
     user: String,
@@ -87,34 +94,133 @@ pub fn get_manifest_4level(
         format!("{}/{}/{}/{}", fourth, org, user, repo),
         reference,
     )
+    .await
+}
+
+/*
+---
+Checking for an image
+HEAD /v2/<name>/manifests/<reference>
+
+As GET, but only the headers are returned, which doesn't require reading the manifest.
+
+# Returns
+200 - manifest exists, with Content-Length, Content-Type and Docker-Content-Digest headers
+404 - manifest not known to the registry
+ */
+#[head("/v2/<onename>/manifests/<reference>")]
+pub async fn head_manifest(
+    _auth_user: TrowToken,
+    ci: &rocket::State<ClientInterface>,
+    onename: String,
+    reference: String,
+) -> Result<ManifestInfo, Error> {
+    ci.get_manifest_info(&onename, &reference)
+        .await
+        .map_err(|_| Error::ManifestUnknown(reference))
+}
+
+#[head("/v2/<user>/<repo>/manifests/<reference>")]
+pub async fn head_manifest_2level(
+    auth_user: TrowToken,
+    ci: &rocket::State<ClientInterface>,
+    user: String,
+    repo: String,
+    reference: String,
+) -> Result<ManifestInfo, Error> {
+    head_manifest(auth_user, ci, format!("{}/{}", user, repo), reference).await
+}
+
+#[head("/v2/<org>/<user>/<repo>/manifests/<reference>")]
+pub async fn head_manifest_3level(
+    auth_user: TrowToken,
+    ci: &rocket::State<ClientInterface>,
+    org: String,
+    user: String,
+    repo: String,
+    reference: String,
+) -> Result<ManifestInfo, Error> {
+    head_manifest(
+        auth_user,
+        ci,
+        format!("{}/{}/{}", org, user, repo),
+        reference,
+    )
+    .await
+}
+
+#[head("/v2/<fourth>/<org>/<user>/<repo>/manifests/<reference>")]
+pub async fn head_manifest_4level(
+    auth_user: TrowToken,
+    ci: &rocket::State<ClientInterface>,
+    fourth: String,
+    org: String,
+    user: String,
+    repo: String,
+    reference: String,
+) -> Result<ManifestInfo, Error> {
+    head_manifest(
+        auth_user,
+        ci,
+        format!("{}/{}/{}/{}", fourth, org, user, repo),
+        reference,
+    )
+    .await
 }
 
 /*
 
 ---
 Pushing an image manifest
-PUT /v2/<name>/manifests/<reference>
+PUT /v2/<name>/manifests/<reference>?digest=<digest>
 Content-Type: <manifest media type>
 
+The manifest's digest is calculated with the algorithm of the digest parameter, or of the reference
+if it is a digest, and must match it. Otherwise sha256 is used. Manifests over 4 MiB are refused
+with 413.
  */
 #[put("/v2/<repo_name>/manifests/<reference>", data = "<chunk>")]
-pub fn put_image_manifest(
+pub async fn put_image_manifest(
+    uri: &Origin<'_>,
     _auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     repo_name: String,
     reference: String,
-    chunk: rocket::data::Data,
+    chunk: rocket::data::Data<'_>,
 ) -> Result<VerifiedManifest, Error> {
-    let mut data: Box<dyn Read> = Box::new(chunk.open());
+    let digest = match query_param(uri, "digest") {
+        Some(digest) => Some(digest::parse(&digest).map_err(|_| Error::DigestInvalid)?),
+        None => None,
+    };
+    let data = chunk
+        .open(ByteUnit::from(MAX_MANIFEST_SIZE))
+        .into_bytes()
+        .await
+        .map_err(|_| Error::InternalError)?;
+    if !data.is_complete() {
+        return Err(Error::ManifestTooLarge(MAX_MANIFEST_SIZE));
+    }
 
-    match ci.store_manifest(&repo_name, &reference, &mut data) {
-        Ok(digest) => Ok(create_verified_manifest(
+    match ci
+        .store_manifest(
+            &repo_name,
+            &reference,
+            digest.as_ref(),
+            &mut data.as_slice(),
+        )
+        .await
+    {
+        Ok(stored) => Ok(create_verified_manifest(
             RepoName(repo_name),
-            digest,
+            stored.digest,
             reference,
+            stored.subject,
         )),
         Err(StorageDriverError::InvalidName(name)) => Err(Error::NameInvalid(name)),
         Err(StorageDriverError::InvalidManifest) => Err(Error::ManifestInvalid),
+        Err(StorageDriverError::MissingManifests(missing)) => {
+            Err(Error::ManifestBlobUnknown(missing))
+        }
         Err(_) => Err(Error::InternalError),
     }
 }
@@ -123,43 +229,49 @@ pub fn put_image_manifest(
  * Parse 2 level <user>/<repo> style path and pass it to put_image_manifest
  */
 #[put("/v2/<user>/<repo>/manifests/<reference>", data = "<chunk>")]
-pub fn put_image_manifest_2level(
+pub async fn put_image_manifest_2level(
+    uri: &Origin<'_>,
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     user: String,
     repo: String,
     reference: String,
-    chunk: rocket::data::Data,
+    chunk: rocket::data::Data<'_>,
 ) -> Result<VerifiedManifest, Error> {
     put_image_manifest(
+        uri,
         auth_user,
         ci,
         format!("{}/{}", user, repo),
         reference,
         chunk,
     )
+    .await
 }
 
 /*
  * Parse 3 level <org>/<user>/<repo> style path and pass it to put_image_manifest
  */
 #[put("/v2/<org>/<user>/<repo>/manifests/<reference>", data = "<chunk>")]
-pub fn put_image_manifest_3level(
+pub async fn put_image_manifest_3level(
+    uri: &Origin<'_>,
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     org: String,
     user: String,
     repo: String,
     reference: String,
-    chunk: rocket::data::Data,
+    chunk: rocket::data::Data<'_>,
 ) -> Result<VerifiedManifest, Error> {
     put_image_manifest(
+        uri,
         auth_user,
         ci,
         format!("{}/{}/{}", org, user, repo),
         reference,
         chunk,
     )
+    .await
 }
 
 /*
@@ -169,84 +281,181 @@ pub fn put_image_manifest_3level(
     "/v2/<fourth>/<org>/<user>/<repo>/manifests/<reference>",
     data = "<chunk>"
 )]
-pub fn put_image_manifest_4level(
+pub async fn put_image_manifest_4level(
+    uri: &Origin<'_>,
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     fourth: String,
     org: String,
     user: String,
     repo: String,
     reference: String,
-    chunk: rocket::data::Data,
+    chunk: rocket::data::Data<'_>,
 ) -> Result<VerifiedManifest, Error> {
     put_image_manifest(
+        uri,
         auth_user,
         ci,
         format!("{}/{}/{}/{}", fourth, org, user, repo),
         reference,
         chunk,
     )
+    .await
 }
 
 /*
 ---
 Deleting an Image
 DELETE /v2/<name>/manifests/<reference>
+
+Deleting by digest removes every tag in the repository pointing to the manifest. Deleting by tag
+removes only that tag. The manifest itself is left for garbage collection.
+
+# Returns
+202 - manifest or tag deleted
+404 - manifest or tag not known to the registry
 */
 
-#[delete("/v2/<repo>/manifests/<digest>")]
-pub fn delete_image_manifest(
+#[delete("/v2/<repo>/manifests/<reference>")]
+pub async fn delete_image_manifest(
     _auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     repo: String,
-    digest: String,
+    reference: String,
 ) -> Result<ManifestDeleted, Error> {
-    let digest = digest::parse(&digest).map_err(|_| Error::Unsupported)?;
-    match ci.delete_manifest(&repo, &digest) {
+    // Anything that isn't a digest is a tag, which is removed on its own
+    let res = match digest::parse(&reference) {
+        Ok(digest) => ci.delete_manifest(&repo, &digest).await,
+        Err(_) => ci.delete_tag(&repo, &reference).await,
+    };
+    match res {
         Ok(_) => Ok(ManifestDeleted {}),
         Err(StorageDriverError::Unsupported) => Err(Error::Unsupported),
-        Err(StorageDriverError::InvalidManifest) => Err(Error::ManifestUnknown(repo)),
+        Err(StorageDriverError::InvalidManifest) => Err(Error::ManifestUnknown(reference)),
         Err(_) => Err(Error::InternalError),
     }
 }
 
-#[delete("/v2/<user>/<repo>/manifests/<digest>")]
-pub fn delete_image_manifest_2level(
+#[delete("/v2/<user>/<repo>/manifests/<reference>")]
+pub async fn delete_image_manifest_2level(
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     user: String,
     repo: String,
-    digest: String,
+    reference: String,
 ) -> Result<ManifestDeleted, Error> {
-    delete_image_manifest(auth_user, ci, format!("{}/{}", user, repo), digest)
+    delete_image_manifest(auth_user, ci, format!("{}/{}", user, repo), reference).await
 }
 
-#[delete("/v2/<org>/<user>/<repo>/manifests/<digest>")]
-pub fn delete_image_manifest_3level(
+#[delete("/v2/<org>/<user>/<repo>/manifests/<reference>")]
+pub async fn delete_image_manifest_3level(
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     org: String,
     user: String,
     repo: String,
-    digest: String,
+    reference: String,
 ) -> Result<ManifestDeleted, Error> {
-    delete_image_manifest(auth_user, ci, format!("{}/{}/{}", org, user, repo), digest)
+    delete_image_manifest(
+        auth_user,
+        ci,
+        format!("{}/{}/{}", org, user, repo),
+        reference,
+    )
+    .await
 }
 
-#[delete("/v2/<fourth>/<org>/<user>/<repo>/manifests/<digest>")]
-pub fn delete_image_manifest_4level(
+#[delete("/v2/<fourth>/<org>/<user>/<repo>/manifests/<reference>")]
+pub async fn delete_image_manifest_4level(
     auth_user: TrowToken,
-    ci: rocket::State<ClientInterface>,
+    ci: &rocket::State<ClientInterface>,
     fourth: String,
     org: String,
     user: String,
     repo: String,
-    digest: String,
+    reference: String,
 ) -> Result<ManifestDeleted, Error> {
     delete_image_manifest(
+        auth_user,
+        ci,
+        format!("{}/{}/{}/{}", fourth, org, user, repo),
+        reference,
+    )
+    .await
+}
+
+/*
+---
+Listing referrers
+GET /v2/<name>/referrers/<digest>?artifactType=<type>
+
+Returns an image index of the manifests whose subject is the given manifest, which needn't exist.
+If artifactType is given, only manifests of that type are listed.
+*/
+#[get("/v2/<onename>/referrers/<digest>")]
+pub async fn get_referrers(
+    uri: &Origin<'_>,
+    _auth_user: TrowToken,
+    ci: &rocket::State<ClientInterface>,
+    onename: String,
+    digest: String,
+) -> Result<ReferrerList, Error> {
+    let digest = digest::parse(&digest).map_err(|_| Error::DigestInvalid)?;
+    let artifact_type = query_param(uri, "artifactType").filter(|t| !t.is_empty());
+    ci.get_referrers(&onename, &digest, artifact_type.as_deref())
+        .await
+        .map_err(|_| Error::InternalError)
+}
+
+#[get("/v2/<user>/<repo>/referrers/<digest>")]
+pub async fn get_referrers_2level(
+    uri: &Origin<'_>,
+    auth_user: TrowToken,
+    ci: &rocket::State<ClientInterface>,
+    user: String,
+    repo: String,
+    digest: String,
+) -> Result<ReferrerList, Error> {
+    get_referrers(uri, auth_user, ci, format!("{}/{}", user, repo), digest).await
+}
+
+#[get("/v2/<org>/<user>/<repo>/referrers/<digest>")]
+pub async fn get_referrers_3level(
+    uri: &Origin<'_>,
+    auth_user: TrowToken,
+    ci: &rocket::State<ClientInterface>,
+    org: String,
+    user: String,
+    repo: String,
+    digest: String,
+) -> Result<ReferrerList, Error> {
+    get_referrers(
+        uri,
+        auth_user,
+        ci,
+        format!("{}/{}/{}", org, user, repo),
+        digest,
+    )
+    .await
+}
+
+#[get("/v2/<fourth>/<org>/<user>/<repo>/referrers/<digest>")]
+pub async fn get_referrers_4level(
+    uri: &Origin<'_>,
+    auth_user: TrowToken,
+    ci: &rocket::State<ClientInterface>,
+    fourth: String,
+    org: String,
+    user: String,
+    repo: String,
+    digest: String,
+) -> Result<ReferrerList, Error> {
+    get_referrers(
+        uri,
         auth_user,
         ci,
         format!("{}/{}/{}/{}", fourth, org, user, repo),
         digest,
     )
+    .await
 }
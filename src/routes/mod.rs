@@ -4,11 +4,13 @@ use crate::response::html::HTML;
 use crate::response::trow_token::ValidBasicToken;
 use crate::response::trow_token::{self, TrowToken};
 use crate::TrowConfig;
+use rocket::http::uri::Origin;
 use rocket::request::Request;
+use rocket::serde::json::{json, Json, Value};
 use rocket::State;
-use rocket_contrib::json::{Json, JsonValue};
 use std::str;
 
+mod admin;
 mod blob;
 mod catalog;
 mod health;
@@ -26,6 +28,14 @@ pub fn routes() -> Vec<rocket::Route> {
         manifest::get_manifest_2level,
         manifest::get_manifest_3level,
         manifest::get_manifest_4level,
+        manifest::head_manifest,
+        manifest::head_manifest_2level,
+        manifest::head_manifest_3level,
+        manifest::head_manifest_4level,
+        manifest::get_referrers,
+        manifest::get_referrers_2level,
+        manifest::get_referrers_3level,
+        manifest::get_referrers_4level,
         manifest::put_image_manifest,
         manifest::put_image_manifest_2level,
         manifest::put_image_manifest_3level,
@@ -38,6 +48,10 @@ pub fn routes() -> Vec<rocket::Route> {
         blob::get_blob_2level,
         blob::get_blob_3level,
         blob::get_blob_4level,
+        blob::head_blob,
+        blob::head_blob_2level,
+        blob::head_blob_3level,
+        blob::head_blob_4level,
         blob::put_blob,
         blob::put_blob_2level,
         blob::put_blob_3level,
@@ -46,6 +60,14 @@ pub fn routes() -> Vec<rocket::Route> {
         blob::patch_blob_2level,
         blob::patch_blob_3level,
         blob::patch_blob_4level,
+        blob::get_upload_status,
+        blob::get_upload_status_2level,
+        blob::get_upload_status_3level,
+        blob::get_upload_status_4level,
+        blob::delete_upload,
+        blob::delete_upload_2level,
+        blob::delete_upload_3level,
+        blob::delete_upload_4level,
         blob::post_blob_upload,
         blob::post_blob_upload_2level,
         blob::post_blob_upload_3level,
@@ -67,7 +89,9 @@ pub fn routes() -> Vec<rocket::Route> {
         validation::validate_image,
         health::healthz,
         readiness::readiness,
-        metrics::metrics
+        metrics::metrics,
+        admin::collect_garbage,
+        admin::fsck
     ]
 }
 
@@ -79,7 +103,7 @@ pub fn catchers() -> Vec<rocket::Catcher> {
  * v2 - throw Empty
  */
 #[get("/v2")]
-fn get_v2root(_auth_user: TrowToken) -> Json<JsonValue> {
+fn get_v2root(_auth_user: TrowToken) -> Json<Value> {
     Json(json!({}))
 }
 /*
@@ -96,12 +120,12 @@ fn get_homepage<'a>() -> HTML<'a> {
 
 // Want non HTML return for 404 for docker client
 #[catch(404)]
-fn not_found(_: &Request) -> Json<String> {
+fn not_found(_: &Request<'_>) -> Json<String> {
     Json("404 page not found".to_string())
 }
 
 #[catch(401)]
-fn no_auth(_req: &Request) -> Authenticate {
+fn no_auth(_req: &Request<'_>) -> Authenticate {
     Authenticate {}
 }
 
@@ -111,6 +135,16 @@ fn no_auth(_req: &Request) -> Authenticate {
  * If login is called with a valid bearer token, return session token
  */
 #[get("/login")]
-fn login(auth_user: ValidBasicToken, tc: State<TrowConfig>) -> Result<TrowToken, Error> {
+fn login(auth_user: ValidBasicToken, tc: &State<TrowConfig>) -> Result<TrowToken, Error> {
     trow_token::new(auth_user, tc).map_err(|_| Error::InternalError)
 }
+
+/*
+ * Returns the decoded value of the given query parameter.
+ */
+fn query_param(uri: &Origin<'_>, name: &str) -> Option<String> {
+    uri.query()?
+        .segments()
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+}
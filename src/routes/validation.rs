@@ -3,15 +3,14 @@ use crate::registry_interface::validation::{self, Validation};
 
 use crate::types::AdmissionReview;
 use crate::TrowConfig;
-use rocket_contrib::json::Json;
+use rocket::serde::json::Json;
 
 //Kubernetes webhooks for admitting images
-//Update to use rocket_contrib::Json
 //Just using String for debugging
 #[post("/validate-image", data = "<image_data>")]
-pub fn validate_image(
-    ci: rocket::State<ClientInterface>,
-    tc: rocket::State<TrowConfig>,
+pub async fn validate_image(
+    ci: &rocket::State<ClientInterface>,
+    tc: &rocket::State<TrowConfig>,
     image_data: Json<AdmissionReview>,
 ) -> Json<AdmissionReview> {
     /*
@@ -21,9 +20,9 @@ pub fn validate_image(
      *
      * The docs on this stuff is a bit lacking, it's easiest to refer to the Go code in kubernetes/api.
      */
-    let mut resp_data = image_data.clone();
+    let mut resp_data = image_data.0.clone();
     match image_data.0.request {
-        Some(req) => match ci.validate_admission(&req, &tc.host_names) {
+        Some(req) => match ci.validate_admission(&req, &tc.host_names).await {
             Ok(res) => {
                 resp_data.response = Some(res);
                 Json(resp_data)
@@ -16,13 +16,24 @@ pub struct RepoName(pub String);
 pub struct UploadInfo {
     uuid: Uuid,
     repo_name: RepoName,
-    range: (u32, u32),
+    range: (u64, u64),
+}
+
+/*
+ * How much of an upload has been received, as reported by the upload status endpoint
+ */
+pub struct UploadStatus {
+    uuid: Uuid,
+    repo_name: RepoName,
+    uploaded: u64,
 }
 
 pub struct BlobDeleted {}
 
 pub struct ManifestDeleted {}
 
+pub struct UploadCancelled {}
+
 impl UploadInfo {
     pub fn uuid(&self) -> &Uuid {
         &self.uuid
@@ -32,12 +43,12 @@ impl UploadInfo {
         &self.repo_name
     }
 
-    pub fn range(&self) -> (u32, u32) {
+    pub fn range(&self) -> (u64, u64) {
         self.range
     }
 }
 
-pub fn create_upload_info(uuid: Uuid, repo_name: RepoName, range: (u32, u32)) -> UploadInfo {
+pub fn create_upload_info(uuid: Uuid, repo_name: RepoName, range: (u64, u64)) -> UploadInfo {
     UploadInfo {
         uuid,
         repo_name,
@@ -45,24 +56,44 @@ pub fn create_upload_info(uuid: Uuid, repo_name: RepoName, range: (u32, u32)) ->
     }
 }
 
+impl UploadStatus {
+    pub fn uuid(&self) -> &Uuid {
+        &self.uuid
+    }
+
+    pub fn repo_name(&self) -> &RepoName {
+        &self.repo_name
+    }
+
+    /// Bytes received so far
+    pub fn uploaded(&self) -> u64 {
+        self.uploaded
+    }
+}
+
+pub fn create_upload_status(uuid: Uuid, repo_name: RepoName, uploaded: u64) -> UploadStatus {
+    UploadStatus {
+        uuid,
+        repo_name,
+        uploaded,
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct AcceptedUpload {
     digest: Digest,
     repo_name: RepoName,
-    uuid: Uuid,
-    range: (u32, u32),
+    range: (u64, u64),
 }
 
 pub fn create_accepted_upload(
     digest: Digest,
     repo_name: RepoName,
-    uuid: Uuid,
-    range: (u32, u32),
+    range: (u64, u64),
 ) -> AcceptedUpload {
     AcceptedUpload {
         digest,
         repo_name,
-        uuid,
         range,
     }
 }
@@ -76,7 +107,7 @@ impl AcceptedUpload {
         &self.repo_name
     }
 
-    pub fn range(&self) -> (u32, u32) {
+    pub fn range(&self) -> (u64, u64) {
         self.range
     }
 }
@@ -92,6 +123,7 @@ pub struct VerifiedManifest {
     repo_name: RepoName,
     digest: Digest,
     tag: String,
+    subject: Option<Digest>,
 }
 
 impl VerifiedManifest {
@@ -106,17 +138,23 @@ impl VerifiedManifest {
     pub fn repo_name(&self) -> &RepoName {
         &self.repo_name
     }
+
+    pub fn subject(&self) -> Option<&Digest> {
+        self.subject.as_ref()
+    }
 }
 
 pub fn create_verified_manifest(
     repo_name: RepoName,
     digest: Digest,
     tag: String,
+    subject: Option<Digest>,
 ) -> VerifiedManifest {
     VerifiedManifest {
         repo_name,
         digest,
         tag,
+        subject,
     }
 }
 
@@ -90,17 +90,59 @@ pub async fn upload_layer(cl: &reqwest::Client, name: &str, tag: &str) {
     assert_eq!(digest, digest_header);
     assert_eq!(blob, resp.bytes().await.unwrap());
 
+    //HEAD gives the same headers without the data
+    let resp = cl
+        .head(&format!("{}/v2/{}/blobs/{}", TROW_ADDRESS, name, digest))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers().get("Docker-Content-Digest").unwrap(),
+        &digest
+    );
+    assert_eq!(
+        resp.headers().get("Content-Length").unwrap(),
+        &blob.len().to_string()
+    );
+
+    //Ranges can be fetched on their own
+    let blob_url = format!("{}/v2/{}/blobs/{}", TROW_ADDRESS, name, digest);
+    let resp = cl
+        .get(&blob_url)
+        .header("Range", "bytes=10-19")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        resp.headers().get("Content-Range").unwrap(),
+        &format!("bytes 10-19/{}", blob.len())
+    );
+    assert_eq!(&blob[10..20], &resp.bytes().await.unwrap()[..]);
+    let resp = cl
+        .get(&blob_url)
+        .header("Range", "bytes=1000-")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+
     //Upload manifest
     //For time being use same blob for config and layer
     let config = manifest::Object {
         media_type: "application/vnd.docker.container.image.v1+json".to_owned(),
         size: Some(blob.len() as u64),
         digest: digest.clone(),
+        artifact_type: None,
+        annotations: None,
     };
     let layer = manifest::Object {
         media_type: "application/vnd.docker.image.rootfs.diff.tar.gzip".to_owned(),
         size: Some(blob.len() as u64),
         digest: digest.clone(),
+        artifact_type: None,
+        annotations: None,
     };
     let mut layers = Vec::new();
     layers.push(layer);
@@ -109,6 +151,9 @@ pub async fn upload_layer(cl: &reqwest::Client, name: &str, tag: &str) {
         media_type: Some("application/vnd.docker.distribution.manifest.v2+json".to_owned()),
         config,
         layers,
+        artifact_type: None,
+        subject: None,
+        annotations: None,
     };
     let manifest_addr = format!("{}/v2/{}/manifests/{}", TROW_ADDRESS, name, tag);
     let resp = cl.put(&manifest_addr).json(&mani).send().await.unwrap();
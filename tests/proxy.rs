@@ -100,11 +100,15 @@ mod interface_tests {
             media_type: "application/vnd.docker.container.image.v1+json".to_owned(),
             digest: "fake".to_string(),
             size: None,
+            artifact_type: None,
+            annotations: None,
         };
         let layer = manifest::Object {
             media_type: "application/vnd.docker.image.rootfs.diff.tar.gzip".to_owned(),
             size: None,
             digest: "fake".to_string(),
+            artifact_type: None,
+            annotations: None,
         };
 
         let mut layers = Vec::new();
@@ -114,6 +118,9 @@ mod interface_tests {
             media_type: Some("application/vnd.docker.distribution.manifest.v2+json".to_owned()),
             config,
             layers,
+            artifact_type: None,
+            subject: None,
+            annotations: None,
         };
         let manifest_addr = format!("{}/v2/{}/manifests/{}", TROW_ADDRESS, name, "tag");
         let resp = cl.put(&manifest_addr).json(&mani).send().await.unwrap();
@@ -98,6 +98,13 @@ mod interface_tests {
             .await
             .unwrap();
         assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let resp = cl
+            .head(&(TROW_ADDRESS.to_owned() + "/v2/test/test/blobs/sha256:baadf00d"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
     }
 
     async fn get_manifest(cl: &reqwest::Client, name: &str, tag: &str, size: Option<usize>) {
@@ -129,6 +136,23 @@ mod interface_tests {
         assert_eq!(mani.schema_version, 2);
     }
 
+    async fn head_manifest(cl: &reqwest::Client, name: &str, tag: &str) {
+        let url = format!("{}/v2/{}/manifests/{}", TROW_ADDRESS, name, tag);
+        let head = cl.head(&url).send().await.unwrap();
+        assert_eq!(head.status(), StatusCode::OK);
+        let get = cl.get(&url).send().await.unwrap();
+        for header in &["Content-Length", "Content-Type", "Docker-Content-Digest"] {
+            assert_eq!(head.headers().get(*header), get.headers().get(*header));
+        }
+
+        let resp = cl
+            .head(&format!("{}/v2/{}/manifests/missing", TROW_ADDRESS, name))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
     async fn get_non_existent_manifest(cl: &reqwest::Client, name: &str, tag: &str) {
         //Might need accept headers here
         let resp = cl
@@ -201,6 +225,61 @@ mod interface_tests {
         assert_eq!(resp.status(), StatusCode::CREATED);
     }
 
+    async fn push_sha512_image(cl: &reqwest::Client, name: &str) -> String {
+        let config = "sha512 config\n".as_bytes();
+        let config_digest = digest::sha512_tag_digest(BufReader::new(config)).unwrap();
+        let resp = cl
+            .post(&format!(
+                "{}/v2/{}/blobs/uploads/?digest={}",
+                TROW_ADDRESS, name, config_digest
+            ))
+            .body(config)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        let manifest = format!(
+            r#"{{ "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                 "config": {{ "digest": "{}",
+                             "mediaType": "application/vnd.oci.image.config.v1+json",
+                             "size": {} }},
+                 "layers": [], "schemaVersion": 2 }}"#,
+            config_digest,
+            config.len()
+        );
+        let digest = digest::sha512_tag_digest(BufReader::new(manifest.as_bytes())).unwrap();
+        let resp = cl
+            .put(&format!(
+                "{}/v2/{}/manifests/latest?digest={}",
+                TROW_ADDRESS, name, digest
+            ))
+            .body(manifest)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert_eq!(
+            resp.headers().get("Docker-Content-Digest").unwrap(),
+            &digest
+        );
+
+        let resp = cl
+            .get(&format!(
+                "{}/v2/{}/manifests/{}",
+                TROW_ADDRESS, name, digest
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get("Docker-Content-Digest").unwrap(),
+            &digest
+        );
+        digest
+    }
+
     async fn upload_with_post(cl: &reqwest::Client, name: &str) {
         let config = "{ }\n".as_bytes();
         let digest = digest::sha256_tag_digest(BufReader::new(config)).unwrap();
@@ -216,6 +295,174 @@ mod interface_tests {
         assert_eq!(resp.status(), StatusCode::CREATED);
     }
 
+    async fn mount_blob(cl: &reqwest::Client, name: &str, from: &str) {
+        //Config uploaded by upload_with_put
+        let config = "{}\n".as_bytes();
+        let digest = digest::sha256_tag_digest(BufReader::new(config)).unwrap();
+        let resp = cl
+            .post(&format!(
+                "{}/v2/{}/blobs/uploads/?mount={}&from={}",
+                TROW_ADDRESS, name, digest, from
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert_eq!(
+            resp.headers().get(common::LOCATION_HEADER).unwrap(),
+            &format!("{}/v2/{}/blobs/{}", TROW_ADDRESS, name, digest)
+        );
+        assert_eq!(
+            resp.headers().get("Docker-Content-Digest").unwrap(),
+            &digest
+        );
+        assert!(resp.headers().get(common::UPLOAD_HEADER).is_none());
+
+        //Proxied repositories can't be pushed to, even by mounting
+        let resp = cl
+            .post(&format!(
+                "{}/v2/f/{}/blobs/uploads/?mount={}&from={}",
+                TROW_ADDRESS, name, digest, from
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        //Already stored, so the data isn't needed
+        let resp = cl
+            .post(&format!(
+                "{}/v2/{}/blobs/uploads/?digest={}",
+                TROW_ADDRESS, name, digest
+            ))
+            .body(config.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        //Unknown blobs fall back to a normal upload
+        let unknown = digest::sha256_tag_digest(BufReader::new("unknown".as_bytes())).unwrap();
+        let resp = cl
+            .post(&format!(
+                "{}/v2/{}/blobs/uploads/?mount={}&from={}",
+                TROW_ADDRESS, name, unknown, from
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        assert!(resp.headers().get(common::UPLOAD_HEADER).is_some());
+    }
+
+    async fn resume_upload_with_status(cl: &reqwest::Client, name: &str) {
+        let resp = cl
+            .post(&format!("{}/v2/{}/blobs/uploads/", TROW_ADDRESS, name))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        let uuid = resp
+            .headers()
+            .get(common::UPLOAD_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        let status_url = format!("{}/v2/{}/blobs/uploads/{}", TROW_ADDRESS, name, uuid);
+
+        let resp = cl.get(&status_url).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert_eq!(resp.headers().get("Range").unwrap(), "0-0");
+
+        let blob = common::gen_rand_blob(100);
+        let resp = cl
+            .patch(&status_url)
+            .body(blob[..60].to_vec())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+
+        // Client lost track of the upload, so asks where to carry on from
+        let resp = cl.get(&status_url).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            resp.headers()
+                .get(common::UPLOAD_HEADER)
+                .unwrap()
+                .to_str()
+                .unwrap(),
+            uuid
+        );
+        assert!(resp
+            .headers()
+            .get(common::LOCATION_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .ends_with(&format!("/v2/{}/blobs/uploads/{}", name, uuid)));
+        let range = resp.headers().get("Range").unwrap().to_str().unwrap();
+        assert_eq!(range, "0-59");
+        let next: usize = range["0-".len()..].parse::<usize>().unwrap() + 1;
+
+        let resp = cl
+            .patch(&status_url)
+            .header("Content-Range", format!("{}-{}", next, blob.len() - 1))
+            .body(blob[next..].to_vec())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+
+        let digest = digest::sha256_tag_digest(BufReader::new(blob.as_slice())).unwrap();
+        let resp = cl
+            .put(&format!("{}?digest={}", status_url, digest))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        let resp = cl.get(&status_url).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    async fn cancel_upload(cl: &reqwest::Client, name: &str) {
+        let resp = cl
+            .post(&format!("{}/v2/{}/blobs/uploads/", TROW_ADDRESS, name))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        let uuid = resp
+            .headers()
+            .get(common::UPLOAD_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string();
+        let upload_url = format!("{}/v2/{}/blobs/uploads/{}", TROW_ADDRESS, name, uuid);
+
+        let resp = cl
+            .patch(&upload_url)
+            .body(common::gen_rand_blob(100))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+
+        let resp = cl.delete(&upload_url).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let resp = cl.get(&upload_url).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let resp = cl.delete(&upload_url).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        let err: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(err["errors"][0]["code"], "BLOB_UPLOAD_UNKNOWN");
+    }
+
     async fn push_oci_manifest(cl: &reqwest::Client, name: &str, tag: &str) -> String {
         //Note config was uploaded as blob in earlier test
         let config = "{}\n".as_bytes();
@@ -244,6 +491,138 @@ mod interface_tests {
         digest
     }
 
+    async fn referrers(cl: &reqwest::Client, name: &str, subject: &str) {
+        let config = "{}\n".as_bytes();
+        let config_digest = digest::sha256_tag_digest(BufReader::new(config)).unwrap();
+        let artifact_type = "application/vnd.example.signature";
+        let manifest = format!(
+            r#"{{ "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                 "artifactType": "{}",
+                 "config": {{ "digest": "{}",
+                             "mediaType": "application/vnd.oci.image.config.v1+json",
+                             "size": {} }},
+                 "layers": [], "schemaVersion": 2,
+                 "subject": {{ "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                              "digest": "{}", "size": 358 }} }}"#,
+            artifact_type,
+            config_digest,
+            config.len(),
+            subject
+        );
+        let digest = digest::sha256_tag_digest(BufReader::new(manifest.as_bytes())).unwrap();
+        let resp = cl
+            .put(&format!("{}/v2/{}/manifests/signature", TROW_ADDRESS, name))
+            .body(manifest.clone())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        assert_eq!(resp.headers().get("OCI-Subject").unwrap(), subject);
+
+        let referrers_url = format!("{}/v2/{}/referrers/{}", TROW_ADDRESS, name, subject);
+        let resp = cl.get(&referrers_url).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get("Content-Type").unwrap(),
+            "application/vnd.oci.image.index.v1+json"
+        );
+        let index: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(index["manifests"].as_array().unwrap().len(), 1);
+        assert_eq!(index["manifests"][0]["digest"], digest.as_str());
+        assert_eq!(index["manifests"][0]["artifactType"], artifact_type);
+        assert_eq!(index["manifests"][0]["size"], manifest.len());
+
+        let resp = cl
+            .get(&format!("{}?artifactType=other", referrers_url))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get("OCI-Filters-Applied").unwrap(),
+            "artifactType"
+        );
+        let index: serde_json::Value = resp.json().await.unwrap();
+        assert!(index["manifests"].as_array().unwrap().is_empty());
+
+        let resp = cl
+            .get(&format!(
+                "{}/v2/{}/referrers/notadigest",
+                TROW_ADDRESS, name
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        // Removing the tag removes the referrer
+        let resp = cl
+            .delete(&format!("{}/v2/{}/manifests/signature", TROW_ADDRESS, name))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        let index: serde_json::Value = cl
+            .get(&referrers_url)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(index["manifests"].as_array().unwrap().is_empty());
+    }
+
+    async fn push_artifact(cl: &reqwest::Client, name: &str, tag: &str) {
+        // No config, so the empty descriptor is assumed
+        let manifest = r#"{ "schemaVersion": 2,
+                 "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                 "artifactType": "application/vnd.example.wasm",
+                 "layers": [] }"#;
+        let resp = cl
+            .put(&format!("{}/v2/{}/manifests/{}", TROW_ADDRESS, name, tag))
+            .body(manifest)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::CREATED);
+
+        let resp = cl
+            .get(&format!(
+                "{}/v2/{}/blobs/{}",
+                TROW_ADDRESS,
+                name,
+                manifest::empty_descriptor::DIGEST
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.text().await.unwrap(), "{}");
+    }
+
+    async fn push_oversized_manifest(cl: &reqwest::Client, name: &str) {
+        // Valid apart from its size, which is over the 4 MiB limit
+        let manifest = format!(
+            r#"{{ "schemaVersion": 2,
+                 "artifactType": "application/vnd.example.wasm",
+                 "annotations": {{ "padding": "{}" }},
+                 "layers": [] }}"#,
+            "x".repeat(5 * 1024 * 1024)
+        );
+        let resp = cl
+            .put(&format!("{}/v2/{}/manifests/big", TROW_ADDRESS, name))
+            .body(manifest)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["errors"][0]["code"], "MANIFEST_INVALID");
+
+        get_non_existent_manifest(cl, name, "big").await;
+    }
+
     async fn push_manifest_list(
         cl: &reqwest::Client,
         digest: &str,
@@ -256,8 +635,8 @@ mod interface_tests {
                 "mediaType": "application/vnd.docker.distribution.manifest.list.v2+json",
                 "manifests": [
                   {{
-                    "mediaType": "application/vnd.docker.distribution.manifest.v2+json",
-                    "size": 7143,
+                    "mediaType": "application/vnd.oci.image.manifest.v1+json",
+                    "size": 358,
                     "digest": "{}",
                     "platform": {{
                       "architecture": "ppc64le",
@@ -269,13 +648,21 @@ mod interface_tests {
               "#,
             digest
         );
-        let bytes = manifest.clone();
-        let resp = cl
-            .put(&format!("{}/v2/{}/manifests/{}", TROW_ADDRESS, name, tag))
-            .body(bytes)
-            .send()
-            .await
-            .unwrap();
+        let url = format!("{}/v2/{}/manifests/{}", TROW_ADDRESS, name, tag);
+
+        // The child manifest has only been pushed to another repository
+        let resp = cl.put(&url).body(manifest.clone()).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        let err: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(err["errors"][0]["code"], "MANIFEST_BLOB_UNKNOWN");
+        assert_eq!(err["errors"][0]["detail"]["Missing"][0]["digest"], digest);
+        assert_eq!(
+            err["errors"][0]["detail"]["Missing"][0]["platform"],
+            "linux/ppc64le"
+        );
+
+        assert_eq!(push_oci_manifest(cl, name, "ppc64le").await, digest);
+        let resp = cl.put(&url).body(manifest.clone()).send().await.unwrap();
         assert_eq!(resp.status(), StatusCode::CREATED);
 
         // Try pulling by digest
@@ -350,17 +737,36 @@ mod interface_tests {
         // If it doesn't exist, that's kinda the same as deleted, right?
         assert_eq!(resp.status(), StatusCode::ACCEPTED);
     }
-    async fn attempt_delete_by_tag(cl: &reqwest::Client, name: &str, tag: &str) {
+    async fn delete_by_tag(cl: &reqwest::Client, name: &str, tag: &str, other_tag: &str) {
+        let url = format!("{}/v2/{}/manifests/{}", TROW_ADDRESS, name, tag);
+        let resp = cl.delete(&url).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+
+        get_non_existent_manifest(cl, name, tag).await;
+        //Other tags in the repository are untouched
+        get_manifest(cl, name, other_tag, None).await;
+
+        let resp = cl.delete(&url).send().await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    async fn delete_config_blob(cl: &reqwest::Client, name: &str) {
+        //Deletes blob uploaded in config test
+        let config = "{}\n".as_bytes();
+        let config_digest = digest::sha256_tag_digest(BufReader::new(config)).unwrap();
         let resp = cl
-            .delete(&format!("{}/v2/{}/manifests/{}", TROW_ADDRESS, name, tag))
+            .delete(&format!(
+                "{}/v2/{}/blobs/{}",
+                TROW_ADDRESS, name, config_digest
+            ))
             .send()
             .await
             .unwrap();
-        assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
     }
 
-    async fn delete_config_blob(cl: &reqwest::Client, name: &str) {
-        //Deletes blob uploaded in config test
+    async fn attempt_delete_referenced_blob(cl: &reqwest::Client, name: &str) {
+        //Config blob is still referenced by manifests pushed earlier
         let config = "{}\n".as_bytes();
         let config_digest = digest::sha256_tag_digest(BufReader::new(config)).unwrap();
         let resp = cl
@@ -371,7 +777,15 @@ mod interface_tests {
             .send()
             .await
             .unwrap();
-        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let body: serde_json::Value = resp.json().await.unwrap();
+        assert_eq!(body["errors"][0]["code"], "DENIED");
+        let referrers = body["errors"][0]["detail"]["ReferencedBy"]
+            .as_array()
+            .unwrap();
+        assert!(referrers.contains(&serde_json::json!("puttest:puttest1")));
+        assert!(referrers.contains(&serde_json::json!("listtest:listtest1")));
     }
 
     async fn test_5level_error(cl: &reqwest::Client) {
@@ -457,6 +871,23 @@ mod interface_tests {
         assert!(blob_body.contains("total_blob_requests{type=\"blobs\"} 9"));
     }
 
+    async fn collect_garbage(cl: &reqwest::Client, dry_run: bool) {
+        let resp = cl
+            .post(&format!("{}/admin/gc?dry_run={}", TROW_ADDRESS, dry_run))
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let report: serde_json::Value = resp.json().await.unwrap();
+
+        assert_eq!(report["dry_run"], dry_run);
+        // Everything was pushed by this test run, so is inside the grace period
+        assert_eq!(report["removed_blobs"].as_array().unwrap().len(), 0);
+        assert!(report["skipped_recent_blobs"].as_u64().unwrap() > 0);
+    }
+
     #[tokio::test]
     async fn test_runner() {
         //Need to start with empty repo
@@ -498,24 +929,44 @@ mod interface_tests {
         upload_with_put(&client, "puttest").await;
         println!("Running upload_with_post");
         upload_with_post(&client, "posttest").await;
+        println!("Running push_sha512_image(sha512test)");
+        let digest = push_sha512_image(&client, "sha512test").await;
+        delete_manifest(&client, "sha512test", &digest).await;
+        println!("Running mount_blob(mounttest)");
+        mount_blob(&client, "mounttest", "puttest").await;
+        println!("Running resume_upload_with_status(status/test)");
+        resume_upload_with_status(&client, "status/test").await;
+        println!("Running cancel_upload(cancel/test)");
+        cancel_upload(&client, "cancel/test").await;
 
         println!("Running test_5level_error()");
         test_5level_error(&client).await;
 
         println!("Running push_oci_manifest()");
         let digest = push_oci_manifest(&client, "puttest", "puttest1").await;
+        println!("Running referrers(puttest)");
+        referrers(&client, "puttest", &digest).await;
+        println!("Running push_artifact(puttest:wasm)");
+        push_artifact(&client, "puttest", "wasm").await;
+        delete_by_tag(&client, "puttest", "wasm", "puttest1").await;
+        println!("Running push_oversized_manifest(puttest)");
+        push_oversized_manifest(&client, "puttest").await;
         println!("Running push_manifest_list()");
         let digest_list = push_manifest_list(&client, &digest, "listtest", "listtest1").await;
         println!("Running get_manifest(puttest:puttest1)");
         get_manifest(&client, "puttest", "puttest1", Some(358)).await;
+        println!("Running attempt_delete_referenced_blob(puttest)");
+        attempt_delete_referenced_blob(&client, "puttest").await;
         println!("Running delete_manifest(puttest:digest)");
         delete_manifest(&client, "puttest", &digest).await;
         println!("Running delete_manifest(listtest)");
         delete_manifest(&client, "listtest", &digest_list).await;
+        delete_manifest(&client, "listtest", &digest).await;
         println!("Running delete_non_existent_manifest(onename)");
         delete_non_existent_manifest(&client, "onename").await;
-        println!("Running attempt_delete_by_tag(onename:tag)");
-        attempt_delete_by_tag(&client, "onename", "tag").await;
+        println!("Running delete_by_tag(onename:tag)");
+        common::upload_layer(&client, "onename", "untag").await;
+        delete_by_tag(&client, "onename", "untag", "tag").await;
         println!("Running get_non_existent_manifest(puttest:puttest1)");
         get_non_existent_manifest(&client, "puttest", "puttest1").await;
         println!("Running get_non_existent_manifest(puttest:digest)");
@@ -534,6 +985,8 @@ mod interface_tests {
         get_manifest(&client, "image/test", "latest", None).await;
         println!("Running get_manifest(repo/image/test:tag)");
         get_manifest(&client, "repo/image/test", "tag", None).await;
+        println!("Running head_manifest(repo/image/test:tag)");
+        head_manifest(&client, "repo/image/test", "tag").await;
 
         let mut rc = RepoCatalog::new();
         rc.insert("fourth/repo/image/test".to_string());
@@ -582,5 +1035,10 @@ mod interface_tests {
         println!("Running get_metrics");
         get_metrics(&client).await;
         check_tag_list_n_last(&client, 2, "latest", &tl4).await;
+
+        println!("Running collect_garbage");
+        collect_garbage(&client, true).await;
+        collect_garbage(&client, false).await;
+        get_manifest(&client, "onename", "tag", None).await;
     }
 }